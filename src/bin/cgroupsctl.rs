@@ -0,0 +1,478 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+//! `cgroupsctl` inspects and manages control groups with the logic of the crate, so that the
+//! same code paths run for v1, v2 and hybrid hosts.
+//!
+//! Paths are relative to the cgroup mount points, e.g. `kubepods/pod1`, except for `set` with
+//! a systemd path like `system.slice:docker:abc`.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::thread;
+use std::time::Duration;
+
+use cgroups_rs::fs::cpu::CpuController;
+use cgroups_rs::fs::cpuset::CpuSetController;
+use cgroups_rs::fs::error::ErrorKind;
+use cgroups_rs::fs::freezer::FreezerController;
+use cgroups_rs::fs::memory::MemController;
+use cgroups_rs::fs::pid::PidController;
+use cgroups_rs::fs::{hierarchies, Cgroup, Resources};
+use cgroups_rs::manager::is_systemd_cgroup;
+use cgroups_rs::pidfd::signal_members;
+use cgroups_rs::prometheus;
+use cgroups_rs::{CgroupPid, FsManager, Manager, Pidfd, SystemdManager};
+use clap::{Parser, Subcommand, ValueEnum};
+use oci_spec::runtime::LinuxResources;
+use serde_json::{json, Value};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Parser)]
+#[command(
+    name = "cgroupsctl",
+    version,
+    about = "Inspect and manage control groups"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the control groups below a path, one per line.
+    List {
+        #[arg(default_value = "/")]
+        path: String,
+        /// The v1 controller whose hierarchy is walked. Defaults to the unified hierarchy, or
+        /// to the first v1 hierarchy without one.
+        #[arg(long)]
+        controller: Option<String>,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the control groups below a path as a tree.
+    Tree {
+        #[arg(default_value = "/")]
+        path: String,
+        /// The v1 controller whose hierarchy is walked, see `list`.
+        #[arg(long)]
+        controller: Option<String>,
+    },
+    /// Show the statistics of a control group.
+    Stats {
+        path: String,
+        #[arg(long, value_enum, default_value_t = StatsFormat::Human)]
+        format: StatsFormat,
+    },
+    /// Show the limits of a control group.
+    Limits {
+        path: String,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Create a control group and apply a `Resources` spec to it, read from a JSON file or, with
+    /// the `.toml` extension, a TOML one.
+    Create { path: String, spec: PathBuf },
+    /// Apply an OCI `LinuxResources` JSON file, through the systemd manager for paths like
+    /// `slice:prefix:name` and the cgroupfs one otherwise.
+    Set {
+        path: String,
+        resources: PathBuf,
+        /// Processes added before setting the resources. A systemd unit is only started once
+        /// a process is added to it.
+        #[arg(long = "pid")]
+        pids: Vec<u64>,
+    },
+    /// Freeze the processes of a control group.
+    Freeze { path: String },
+    /// Thaw the processes of a control group.
+    Thaw { path: String },
+    /// Kill the processes of a control group and of its descendants.
+    Kill {
+        path: String,
+        /// Sent instead of `SIGKILL`, to the processes one by one rather than with `cgroup.kill`.
+        #[arg(long)]
+        signal: Option<i32>,
+    },
+    /// Print the changes of the state of a control group until it's removed.
+    Watch {
+        path: String,
+        /// The polling interval, in milliseconds.
+        #[arg(long, default_value_t = 1000)]
+        interval: u64,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum StatsFormat {
+    Human,
+    Json,
+    Prometheus,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli.command) {
+        eprintln!("cgroupsctl: {}", e);
+        exit(1);
+    }
+}
+
+fn run(command: Command) -> Result<()> {
+    match command {
+        Command::List {
+            path,
+            controller,
+            json,
+        } => {
+            let dir = hierarchy_dir(controller.as_deref())?;
+            let mut paths = vec![];
+            walk(&dir, Path::new(&path), &mut |p, _| {
+                paths.push(p.display().to_string())
+            })?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&paths)?);
+            } else {
+                paths.iter().for_each(|p| println!("{}", p));
+            }
+        }
+        Command::Tree { path, controller } => {
+            let dir = hierarchy_dir(controller.as_deref())?;
+            walk(&dir, Path::new(&path), &mut |p, depth| {
+                let name = match depth {
+                    0 => p.display().to_string(),
+                    _ => p
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string(),
+                };
+                println!("{}{}", "  ".repeat(depth), name);
+            })?;
+        }
+        Command::Stats { path, format } => {
+            let manager = existing(&path)?;
+            let stats = manager.stats();
+            match format {
+                StatsFormat::Human => print_flat(&serde_json::to_value(&stats)?),
+                StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+                StatsFormat::Prometheus => print!("{}", prometheus::render(&[(&path, &stats)])),
+            }
+        }
+        Command::Limits { path, json } => {
+            let limits = limits(&path)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&limits)?);
+            } else {
+                print_flat(&limits);
+            }
+        }
+        Command::Create { path, spec } => {
+            let content = fs::read_to_string(&spec)?;
+            let res: Resources = if spec.extension() == Some("toml".as_ref()) {
+                toml::from_str(&content)?
+            } else {
+                serde_json::from_str(&content)?
+            };
+            let cg = Cgroup::new(hierarchies::auto(), path.as_str())?;
+            cg.apply(&res)?;
+        }
+        Command::Set {
+            path,
+            resources,
+            pids,
+        } => {
+            let resources: LinuxResources = serde_json::from_str(&fs::read_to_string(resources)?)?;
+            let mut manager: Box<dyn Manager> = if is_systemd_cgroup(&path) {
+                Box::new(SystemdManager::new(&path)?)
+            } else {
+                Box::new(FsManager::new(&path)?)
+            };
+            for pid in pids {
+                manager.add_proc_pidfd(&Pidfd::open(CgroupPid::from(pid))?)?;
+            }
+            manager.set(&resources)?;
+        }
+        Command::Freeze { path } => freezer(&path, true)?,
+        Command::Thaw { path } => freezer(&path, false)?,
+        Command::Kill { path, signal } => kill(&path, signal)?,
+        Command::Watch { path, interval } => watch(&path, Duration::from_millis(interval))?,
+    }
+    Ok(())
+}
+
+/// Loads the control group at `path`, failing if it doesn't exist.
+fn load(path: &str) -> Result<Cgroup> {
+    let cg = Cgroup::load(hierarchies::auto(), path);
+    if !cg.exists() {
+        return Err(format!("{}: no such control group", path).into());
+    }
+    Ok(cg)
+}
+
+fn existing(path: &str) -> Result<FsManager> {
+    let manager = FsManager::new(path)?;
+    if !manager.exists() {
+        return Err(format!("{}: no such control group", path).into());
+    }
+    Ok(manager)
+}
+
+/// The mount point of the hierarchy walked by `list` and `tree`.
+fn hierarchy_dir(controller: Option<&str>) -> Result<PathBuf> {
+    let hier = hierarchies::auto();
+    if controller.is_none() {
+        if let Some(root) = hier.unified_root() {
+            return Ok(root);
+        }
+    }
+    hier.root_control_group()
+        .subsystems()
+        .iter()
+        .find(|s| controller.is_none_or(|c| s.controller_name() == c))
+        .map(|s| s.to_controller().path().to_path_buf())
+        .ok_or_else(|| format!("no hierarchy found for {:?}", controller).into())
+}
+
+/// Calls `f` with the path and the depth of `path` and of its descendants in the hierarchy
+/// mounted to `root`, in depth-first order.
+fn walk(root: &Path, path: &Path, f: &mut dyn FnMut(&Path, usize)) -> Result<()> {
+    fn walk_dir(
+        root: &Path,
+        dir: &Path,
+        depth: usize,
+        f: &mut dyn FnMut(&Path, usize),
+    ) -> Result<()> {
+        let rel = Path::new("/").join(dir.strip_prefix(root)?);
+        f(&rel, depth);
+        let mut children = fs::read_dir(dir)?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .map(|e| e.path())
+            .collect::<Vec<_>>();
+        children.sort();
+        for child in children {
+            walk_dir(root, &child, depth + 1, f)?;
+        }
+        Ok(())
+    }
+
+    let dir = root.join(path.strip_prefix("/").unwrap_or(path));
+    if !dir.is_dir() {
+        return Err(format!("{}: no such control group", path.display()).into());
+    }
+    walk_dir(root, &dir, 0, f)
+}
+
+/// Limits below 0 stand for no limit.
+fn limit(value: i64) -> Value {
+    if value < 0 {
+        json!("max")
+    } else {
+        json!(value)
+    }
+}
+
+fn limits(path: &str) -> Result<Value> {
+    let manager = existing(path)?;
+    let cg = load(path)?;
+    let stats = manager.stats();
+    let mut limits = BTreeMap::new();
+    let mut insert = |key: &str, value: Value| limits.insert(key.to_string(), value);
+
+    if let Some(m) = &stats.memory.memory {
+        insert("memory.limit", limit(m.limit));
+    }
+    if let Some(m) = &stats.memory.memory_swap {
+        insert("memory.swap_limit", limit(m.limit));
+    }
+    if let Some(mem) = cg.controller_of::<MemController>() {
+        if cg.v2() {
+            let m = mem.get_mem()?;
+            for (name, value) in [("min", m.min), ("low", m.low), ("high", m.high)] {
+                if let Some(v) = value {
+                    insert(&format!("memory.{}", name), json!(v.to_string()));
+                }
+            }
+        }
+    }
+    if let Some(cpu) = cg.controller_of::<CpuController>() {
+        insert("cpu.shares", json!(cpu.shares()?));
+        insert("cpu.cfs_quota", limit(cpu.cfs_quota()?));
+        insert("cpu.cfs_period", json!(cpu.cfs_period()?));
+    }
+    if let Some(cpuset) = cg.controller_of::<CpuSetController>() {
+        let set = cpuset.cpuset();
+        insert("cpuset.cpus", json!(ranges(&set.cpus)));
+        insert("cpuset.mems", json!(ranges(&set.mems)));
+    }
+    if cg.controller_of::<PidController>().is_some() {
+        insert("pids.limit", limit(stats.pids.limit));
+    }
+    for (resource, m) in &stats.misc {
+        insert(&format!("misc.{}", resource), limit(m.limit));
+    }
+    for r in &stats.rdma.limit {
+        insert(
+            &format!("rdma.{}.hca_handles", r.device),
+            limit(r.hca_handles),
+        );
+        insert(
+            &format!("rdma.{}.hca_objects", r.device),
+            limit(r.hca_objects),
+        );
+    }
+    Ok(serde_json::to_value(limits)?)
+}
+
+/// Formats CPU or memory node ranges like `cpuset.cpus`, e.g. `0-3,6`.
+fn ranges(ranges: &[(u64, u64)]) -> String {
+    ranges
+        .iter()
+        .map(|(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Prints the leaves of `value` as `key: value` lines, the keys of nested objects and arrays
+/// being joined with dots.
+fn print_flat(value: &Value) {
+    let mut lines = vec![];
+    flatten("", value, &mut lines);
+    lines.iter().for_each(|l| println!("{}", l));
+}
+
+fn flatten(prefix: &str, value: &Value, lines: &mut Vec<String>) {
+    let key = |k: &str| match prefix {
+        "" => k.to_string(),
+        _ => format!("{}.{}", prefix, k),
+    };
+    match value {
+        Value::Object(map) => map.iter().for_each(|(k, v)| flatten(&key(k), v, lines)),
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .for_each(|(i, v)| flatten(&key(&i.to_string()), v, lines)),
+        Value::Null => (),
+        Value::String(s) => lines.push(format!("{}: {}", prefix, s)),
+        v => lines.push(format!("{}: {}", prefix, v)),
+    }
+}
+
+fn freezer(path: &str, freeze: bool) -> Result<()> {
+    let cg = load(path)?;
+    let freezer = cg
+        .controller_of::<FreezerController>()
+        .ok_or("no freezer controller attached")?;
+    if freeze {
+        freezer.freeze()?;
+    } else {
+        freezer.thaw()?;
+    }
+    Ok(())
+}
+
+fn kill(path: &str, signal: Option<i32>) -> Result<()> {
+    let cg = load(path)?;
+    // In hybrid mode, `cgroup.kill` only kills the processes in the unified hierarchy.
+    if signal.is_none() && cg.v2() {
+        match cg.kill() {
+            Ok(()) => return Ok(()),
+            // Without `cgroup.kill`, the processes are signaled one by one.
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::InvalidOperation | ErrorKind::CgroupVersion
+                ) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let signal = signal.unwrap_or(libc::SIGKILL);
+    for path in descendants(path)? {
+        let cg = Cgroup::load(hierarchies::auto(), path.as_path());
+        signal_members(&cg.procs(), || cg.procs(), signal);
+    }
+    Ok(())
+}
+
+/// The paths of the control group at `path` and of its descendants, in all the hierarchies.
+fn descendants(path: &str) -> Result<BTreeSet<PathBuf>> {
+    let hier = hierarchies::auto();
+    let mut roots = hier
+        .root_control_group()
+        .subsystems()
+        .iter()
+        .map(|s| s.to_controller().path().to_path_buf())
+        .collect::<BTreeSet<_>>();
+    roots.extend(hier.unified_root());
+
+    let mut paths = BTreeSet::new();
+    for root in roots {
+        // The control group may be missing from some hierarchies.
+        let _ = walk(&root, Path::new(path), &mut |p, _| {
+            paths.insert(p.to_path_buf());
+        });
+    }
+    Ok(paths)
+}
+
+fn watch(path: &str, interval: Duration) -> Result<()> {
+    let cg = load(path)?;
+    let oom = match cg.controller_of::<MemController>() {
+        Some(mem) if !cg.v2() => mem.register_oom_event(path).ok(),
+        _ => None,
+    };
+    if oom.is_none() && cg.events().is_err() {
+        return Err(format!("{}: no events to watch", path).into());
+    }
+
+    let mut last = None;
+    while cg.exists() {
+        if let Ok(events) = cg.events() {
+            let state = (events.populated, events.frozen);
+            if last != Some(state) {
+                println!("populated={} frozen={}", events.populated, events.frozen);
+                last = Some(state);
+            }
+        }
+        if let Some(oom) = &oom {
+            while oom.try_recv().is_ok() {
+                println!("oom");
+            }
+        }
+        thread::sleep(interval);
+    }
+    println!("removed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{flatten, ranges};
+
+    #[test]
+    fn test_flatten() {
+        let value = json!({
+            "cpu": {"cpu_acct": null, "usage": [1, 2]},
+            "pids": {"limit": "max"},
+        });
+        let mut lines = vec![];
+        flatten("", &value, &mut lines);
+        assert_eq!(
+            lines,
+            vec!["cpu.usage.0: 1", "cpu.usage.1: 2", "pids.limit: max"]
+        );
+        assert_eq!(ranges(&[(0, 3), (6, 6)]), "0-3,6");
+    }
+}
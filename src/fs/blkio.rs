@@ -8,16 +8,20 @@
 //!
 //! See the Kernel's documentation for more information about this subsystem, found at:
 //!  [Documentation/cgroup-v1/blkio-controller.txt](https://www.kernel.org/doc/Documentation/cgroup-v1/blkio-controller.txt)
+use std::convert::TryFrom;
+use std::fmt;
 use std::io::Write;
 use std::path::PathBuf;
+use std::str::FromStr;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::ErrorKind::*;
 use crate::fs::error::*;
 
-use crate::fs::{read_string_from, read_u64_from};
+use crate::fs::{parse_max_value, read_string_from, read_u64_from};
 use crate::fs::{
     BlkIoResources, ControllIdentifier, ControllerInternal, Controllers, CustomizedAttribute,
-    Resources, Subsystem,
+    MaxValue, Resources, Subsystem,
 };
 
 /// A controller that allows controlling the `blkio` subsystem of a Cgroup.
@@ -28,6 +32,7 @@ use crate::fs::{
 pub struct BlkIoController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
     v2: bool,
 }
 
@@ -65,7 +70,7 @@ pub struct IoService {
     pub total: u64,
 }
 
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Eq, PartialEq, Debug, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Per-device activity from the control group.
 /// Only for cgroup v2
@@ -88,6 +93,91 @@ pub struct IoStat {
     pub dios: u64,
 }
 
+#[derive(Eq, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Per-device statistics of the `io.latency` controller, as reported in `io.stat` for the
+/// devices with a latency target.
+/// Only for cgroup v2
+pub struct IoLatencyStat {
+    /// The major number of the device.
+    pub major: i16,
+    /// The minor number of the device.
+    pub minor: i16,
+    /// The current queue depth the control group is throttled to.
+    pub depth: MaxValue,
+    /// The running average of the I/O latency, in microseconds. Rotational devices only.
+    pub avg_lat: u64,
+    /// The sampling window used to compute `avg_lat`, in milliseconds. Rotational devices only.
+    pub win: u64,
+    /// The number of I/Os that missed the latency target. Non-rotational devices only.
+    pub missed: u64,
+    /// The total number of sampled I/Os. Non-rotational devices only.
+    pub total: u64,
+}
+
+/// The weights set in `io.weight`.
+/// Only for cgroup v2
+#[derive(Eq, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IoWeight {
+    /// The weight applied to devices without a specific entry.
+    pub default: u64,
+    /// The per-device weights.
+    pub devices: Vec<BlkIoData>,
+}
+
+/// The I/O priority class policy of a control group, as set in `io.prio.class`.
+/// Only for cgroup v2
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IoPrioClass {
+    /// Do not modify the I/O priority class.
+    NoChange,
+    /// Deprecated alias of `PromoteToRt`, written as `none-to-rt`.
+    NoneToRt,
+    /// Change all requests into the real-time class.
+    PromoteToRt,
+    /// Change the requests with a higher priority than best-effort, including the ones without
+    /// an I/O priority class, into the best-effort class.
+    RestrictToBe,
+    /// Change all requests into the idle class.
+    Idle,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for IoPrioClass {
+    fn default() -> Self {
+        IoPrioClass::NoChange
+    }
+}
+
+impl fmt::Display for IoPrioClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IoPrioClass::NoChange => write!(f, "no-change"),
+            IoPrioClass::NoneToRt => write!(f, "none-to-rt"),
+            IoPrioClass::PromoteToRt => write!(f, "promote-to-rt"),
+            IoPrioClass::RestrictToBe => write!(f, "restrict-to-be"),
+            IoPrioClass::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+impl FromStr for IoPrioClass {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "no-change" => Ok(IoPrioClass::NoChange),
+            "none-to-rt" => Ok(IoPrioClass::NoneToRt),
+            "promote-to-rt" => Ok(IoPrioClass::PromoteToRt),
+            "restrict-to-be" => Ok(IoPrioClass::RestrictToBe),
+            "idle" => Ok(IoPrioClass::Idle),
+            _ => Err(Error::new(ParseError)),
+        }
+    }
+}
+
 fn parse_io_service(s: String) -> Result<Vec<IoService>> {
     let mut io_services = Vec::<IoService>::new();
     let mut io_service = IoService::default();
@@ -147,36 +237,129 @@ fn parse_io_service(s: String) -> Result<Vec<IoService>> {
     Ok(io_services)
 }
 
-fn get_value(s: &str) -> String {
-    let arr = s.split(':').collect::<Vec<&str>>();
-    if arr.len() != 2 {
-        return "0".to_string();
-    }
-    arr[1].to_string()
+fn parse_device(s: &str) -> Option<(i16, i16)> {
+    let (major, minor) = s.split_once(':')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
 }
 
-fn parse_io_stat(s: String) -> Vec<IoStat> {
+/// Parses a line of `io.stat`, with the statistics of `io.latency` if the device has any.
+fn parse_io_stat_line(line: &str) -> Result<(IoStat, Option<IoLatencyStat>)> {
     // line:
     // 8:0 rbytes=180224 wbytes=0 rios=3 wios=0 dbytes=0 dios=0
+    // optionally followed by the io.latency fields:
+    // ... depth=max avg_lat=2345 win=100 (rotational)
+    // ... missed=2 total=1000 depth=16 (non-rotational)
+    let mut fields = line.split_whitespace();
+    let (major, minor) = fields
+        .next()
+        .and_then(parse_device)
+        .ok_or_else(|| Error::new(ParseError))?;
+    let mut stat = IoStat {
+        major,
+        minor,
+        ..Default::default()
+    };
+    let mut latency: Option<IoLatencyStat> = None;
+    let new_latency = || IoLatencyStat {
+        major,
+        minor,
+        ..Default::default()
+    };
+
+    for field in fields {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or_else(|| Error::new(ParseError))?;
+        if key == "depth" {
+            latency.get_or_insert_with(new_latency).depth = parse_max_value(value)?;
+            continue;
+        }
+        let target = match key {
+            "rbytes" => &mut stat.rbytes,
+            "wbytes" => &mut stat.wbytes,
+            "rios" => &mut stat.rios,
+            "wios" => &mut stat.wios,
+            "dbytes" => &mut stat.dbytes,
+            "dios" => &mut stat.dios,
+            "avg_lat" => &mut latency.get_or_insert_with(new_latency).avg_lat,
+            "win" => &mut latency.get_or_insert_with(new_latency).win,
+            "missed" => &mut latency.get_or_insert_with(new_latency).missed,
+            "total" => &mut latency.get_or_insert_with(new_latency).total,
+            // fields of other controllers, e.g. io.cost
+            _ => continue,
+        };
+        *target = value
+            .parse()
+            .map_err(|e| Error::with_cause(ParseError, e))?;
+    }
+
+    Ok((stat, latency))
+}
+
+fn parse_io_stat(s: String) -> Vec<IoStat> {
+    // lines that can't be parsed are skipped
     s.lines()
-        .filter(|x| x.split_whitespace().count() == 7)
-        .map(|x| {
-            let arr = x.split_whitespace().collect::<Vec<&str>>();
-            let device = arr[0].split(':').collect::<Vec<&str>>();
-            let (major, minor) = (device[0], device[1]);
+        .filter_map(|line| parse_io_stat_line(line).ok().map(|(stat, _)| stat))
+        .collect()
+}
 
-            IoStat {
-                major: major.parse::<i16>().unwrap(),
-                minor: minor.parse::<i16>().unwrap(),
-                rbytes: get_value(arr[1]).parse::<u64>().unwrap(),
-                wbytes: get_value(arr[2]).parse::<u64>().unwrap(),
-                rios: get_value(arr[3]).parse::<u64>().unwrap(),
-                wios: get_value(arr[4]).parse::<u64>().unwrap(),
-                dbytes: get_value(arr[5]).parse::<u64>().unwrap(),
-                dios: get_value(arr[6]).parse::<u64>().unwrap(),
+fn parse_io_latency_stat(s: String) -> Result<Vec<IoLatencyStat>> {
+    s.lines()
+        .filter_map(|line| {
+            parse_io_stat_line(line)
+                .map(|(_, latency)| latency)
+                .transpose()
+        })
+        .collect()
+}
+
+fn parse_io_weight(s: String) -> Result<IoWeight> {
+    // lines:
+    // default 100
+    // 8:0 200
+    let mut weight = IoWeight::default();
+    for line in s.lines() {
+        let mut fields = line.split_whitespace();
+        let (key, value) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(key), Some(value), None) => (key, value),
+            (None, _, _) => continue,
+            _ => return Err(Error::new(ParseError)),
+        };
+        let value = value
+            .parse::<u64>()
+            .map_err(|e| Error::with_cause(ParseError, e))?;
+        if key == "default" {
+            weight.default = value;
+        } else {
+            let (major, minor) = parse_device(key).ok_or_else(|| Error::new(ParseError))?;
+            weight.devices.push(BlkIoData {
+                major,
+                minor,
+                data: value,
+            });
+        }
+    }
+    Ok(weight)
+}
+
+fn parse_io_latency(s: String) -> Result<Vec<BlkIoData>> {
+    // line:
+    // 8:0 target=10000
+    s.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = fields.next().and_then(parse_device);
+            let target = fields
+                .next()
+                .and_then(|x| x.strip_prefix("target="))
+                .and_then(|x| x.parse::<u64>().ok());
+            match (device, target) {
+                (Some((major, minor)), Some(data)) => Ok(BlkIoData { major, minor, data }),
+                _ => Err(Error::new(ParseError)),
             }
         })
-        .collect::<Vec<IoStat>>()
+        .collect()
 }
 
 fn parse_io_service_total(s: String) -> Result<u64> {
@@ -360,6 +543,10 @@ impl ControllerInternal for BlkIoController {
         &self.base
     }
 
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
     fn is_v2(&self) -> bool {
         self.v2
     }
@@ -384,6 +571,19 @@ impl ControllerInternal for BlkIoController {
             }
         }
 
+        if let Some(weight) = res.io_weight {
+            let _ = self.set_io_weight(weight as u64);
+        }
+        for dev in &res.io_weight_device {
+            let _ = self.set_io_weight_for_device(dev.major, dev.minor, dev.weight as u64);
+        }
+        for dev in &res.latency_device {
+            let _ = self.set_latency_target_for_device(dev.major, dev.minor, dev.target);
+        }
+        if let Some(class) = res.prio_class {
+            let _ = self.set_prio_class(class);
+        }
+
         for dev in &res.throttle_read_bps_device {
             let _ = self.throttle_read_bps_for_device(dev.major, dev.minor, dev.rate);
         }
@@ -414,17 +614,15 @@ impl ControllIdentifier for BlkIoController {
     }
 }
 
-impl<'a> From<&'a Subsystem> for &'a BlkIoController {
-    fn from(sub: &'a Subsystem) -> &'a BlkIoController {
-        unsafe {
-            match sub {
-                Subsystem::BlkIo(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a BlkIoController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a BlkIoController> {
+        match sub {
+            Subsystem::BlkIo(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
@@ -435,6 +633,7 @@ impl BlkIoController {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
             v2,
         }
     }
@@ -824,14 +1023,100 @@ impl BlkIoController {
                 })
         })
     }
+
+    fn write_v2(&self, file_name: &str, content: String) -> Result<()> {
+        if !self.v2 {
+            return Err(Error::new(CgroupVersion));
+        }
+        self.open_path(file_name, true).and_then(|mut file| {
+            file.write_all(content.as_ref())
+                .map_err(|e| Error::with_cause(WriteFailed(file_name.to_string(), content), e))
+        })
+    }
+
+    fn read_v2(&self, file_name: &str) -> Result<String> {
+        if !self.v2 {
+            return Err(Error::new(CgroupVersion));
+        }
+        self.open_path(file_name, false).and_then(read_string_from)
+    }
+
+    /// The default and per-device weights of `io.weight`.
+    ///
+    /// Only for cgroup v2.
+    pub fn io_weight(&self) -> Result<IoWeight> {
+        self.read_v2("io.weight").and_then(parse_io_weight)
+    }
+
+    /// Set the default weight of `io.weight`, in the range `[1, 10000]`.
+    ///
+    /// Unlike `set_weight()`, which writes `io.bfq.weight`, this is honored by the `io.cost`
+    /// controller. Only for cgroup v2.
+    pub fn set_io_weight(&self, weight: u64) -> Result<()> {
+        self.write_v2("io.weight", format!("default {}", weight))
+    }
+
+    /// Same as `set_io_weight()`, but for the block device `major:minor` only.
+    pub fn set_io_weight_for_device(&self, major: u64, minor: u64, weight: u64) -> Result<()> {
+        self.write_v2("io.weight", format!("{}:{} {}", major, minor, weight))
+    }
+
+    /// The latency targets of `io.latency`, in microseconds, per block device.
+    ///
+    /// Only for cgroup v2.
+    pub fn latency_targets(&self) -> Result<Vec<BlkIoData>> {
+        self.read_v2("io.latency").and_then(parse_io_latency)
+    }
+
+    /// The statistics of `io.latency`, for the block devices with a latency target.
+    ///
+    /// Only for cgroup v2.
+    pub fn latency_stats(&self) -> Result<Vec<IoLatencyStat>> {
+        self.read_v2("io.stat").and_then(parse_io_latency_stat)
+    }
+
+    /// Protect the I/O latency of the control group's tasks on the block device `major:minor`.
+    ///
+    /// When a sibling control group misses its `target` (in microseconds), the kernel throttles
+    /// the siblings with a higher target. Only for cgroup v2.
+    pub fn set_latency_target_for_device(&self, major: u64, minor: u64, target: u64) -> Result<()> {
+        self.write_v2(
+            "io.latency",
+            format!("{}:{} target={}", major, minor, target),
+        )
+    }
+
+    /// Remove the latency target of the block device `major:minor`.
+    pub fn remove_latency_target_for_device(&self, major: u64, minor: u64) -> Result<()> {
+        self.write_v2("io.latency", format!("{}:{} target=max", major, minor))
+    }
+
+    /// The I/O priority class policy of the control group.
+    ///
+    /// Only for cgroup v2.
+    pub fn prio_class(&self) -> Result<IoPrioClass> {
+        self.read_v2("io.prio.class").and_then(|s| s.parse())
+    }
+
+    /// Set the I/O priority class policy of the control group.
+    ///
+    /// Only for cgroup v2.
+    pub fn set_prio_class(&self, class: IoPrioClass) -> Result<()> {
+        self.write_v2("io.prio.class", class.to_string())
+    }
 }
 
 impl CustomizedAttribute for BlkIoController {}
 #[cfg(test)]
 mod test {
     use crate::fs::blkio::{parse_blkio_data, BlkIoData};
+    use crate::fs::blkio::{
+        parse_io_latency, parse_io_latency_stat, parse_io_stat, parse_io_weight,
+    };
     use crate::fs::blkio::{parse_io_service, parse_io_service_total, IoService};
+    use crate::fs::blkio::{IoLatencyStat, IoPrioClass, IoStat, IoWeight};
     use crate::fs::error::*;
+    use crate::fs::MaxValue;
 
     static TEST_VALUE: &str = "\
 8:32 Read 4280320
@@ -972,4 +1257,145 @@ b:32 Write 1
             ]
         );
     }
+
+    #[test]
+    fn test_parse_io_stat() {
+        let content = "\
+8:16 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0
+8:0 rbytes=90430464 wbytes=299008000 rios=8950 wios=1252 dbytes=50331648 dios=3021 depth=max avg_lat=2345 win=100
+259:0 rbytes=0 wbytes=0 rios=0 wios=0 dbytes=0 dios=0 missed=2 total=1000 depth=16 cost.vrate=100.00
+";
+        let stats = parse_io_stat(content.to_string());
+        assert_eq!(
+            stats,
+            vec![
+                IoStat {
+                    major: 8,
+                    minor: 16,
+                    rbytes: 1459200,
+                    wbytes: 314773504,
+                    rios: 192,
+                    wios: 353,
+                    dbytes: 0,
+                    dios: 0,
+                },
+                IoStat {
+                    major: 8,
+                    minor: 0,
+                    rbytes: 90430464,
+                    wbytes: 299008000,
+                    rios: 8950,
+                    wios: 1252,
+                    dbytes: 50331648,
+                    dios: 3021,
+                },
+                IoStat {
+                    major: 259,
+                    minor: 0,
+                    ..Default::default()
+                },
+            ]
+        );
+
+        let latency = parse_io_latency_stat(content.to_string()).unwrap();
+        assert_eq!(
+            latency,
+            vec![
+                IoLatencyStat {
+                    major: 8,
+                    minor: 0,
+                    depth: MaxValue::Max,
+                    avg_lat: 2345,
+                    win: 100,
+                    ..Default::default()
+                },
+                IoLatencyStat {
+                    major: 259,
+                    minor: 0,
+                    depth: MaxValue::Value(16),
+                    missed: 2,
+                    total: 1000,
+                    ..Default::default()
+                },
+            ]
+        );
+
+        // Malformed lines are skipped in the statistics, but not in the latency ones.
+        let stats = parse_io_stat(
+            "8:32 rbytes=abc wbytes=0\n8 rbytes=0\n8:99999 rbytes=0\n8:0 rbytes=1 newfield\n8:16 rbytes=2\n"
+                .to_string(),
+        );
+        assert_eq!(
+            stats,
+            vec![IoStat {
+                major: 8,
+                minor: 16,
+                rbytes: 2,
+                ..Default::default()
+            }]
+        );
+        assert!(parse_io_latency_stat("8:0 rbytes=0 depth=abc\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_parse_io_weight() {
+        let ok = parse_io_weight("default 100\n8:0 200\n259:0 50\n".to_string()).unwrap();
+        assert_eq!(
+            ok,
+            IoWeight {
+                default: 100,
+                devices: vec![
+                    BlkIoData {
+                        major: 8,
+                        minor: 0,
+                        data: 200,
+                    },
+                    BlkIoData {
+                        major: 259,
+                        minor: 0,
+                        data: 50,
+                    },
+                ],
+            }
+        );
+
+        assert!(parse_io_weight("default abc\n".to_string()).is_err());
+        assert!(parse_io_weight("8 100\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_parse_io_latency() {
+        let ok = parse_io_latency("8:0 target=10000\n259:0 target=500\n".to_string()).unwrap();
+        assert_eq!(
+            ok,
+            vec![
+                BlkIoData {
+                    major: 8,
+                    minor: 0,
+                    data: 10000,
+                },
+                BlkIoData {
+                    major: 259,
+                    minor: 0,
+                    data: 500,
+                },
+            ]
+        );
+        assert_eq!(parse_io_latency("".to_string()).unwrap(), vec![]);
+        assert!(parse_io_latency("8:0 10000\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_io_prio_class() {
+        for class in [
+            IoPrioClass::NoChange,
+            IoPrioClass::NoneToRt,
+            IoPrioClass::PromoteToRt,
+            IoPrioClass::RestrictToBe,
+            IoPrioClass::Idle,
+        ] {
+            assert_eq!(class.to_string().parse::<IoPrioClass>().unwrap(), class);
+        }
+        assert!("realtime".parse::<IoPrioClass>().is_err());
+    }
 }
@@ -9,19 +9,74 @@
 use crate::fs::error::ErrorKind::*;
 use crate::fs::error::*;
 
+use crate::fs::delegate::{chown_delegated, delegate_files};
+use crate::fs::dirfd::{is_beneath, open_dir, DirFd};
 use crate::fs::hierarchies::V1;
-use crate::fs::{CgroupPid, ControllIdentifier, Controller, Hierarchy, Resources, Subsystem};
+use crate::fs::migrate::{migrate, MigrateOptions, MigrateReport};
+use crate::fs::namespace::NamespacePaths;
+use crate::fs::{
+    parse_max_value, read_string_from, CgroupPid, ControllIdentifier, Controller, Hierarchy,
+    HierarchyResources, MaxValue, Resources, Subsystem,
+};
+use crate::pidfd::{open_members, signal_members, Pidfd};
 
 use std::collections::HashMap;
-use std::convert::From;
+use std::convert::{From, TryFrom};
+use std::fmt;
 use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 pub const CGROUP_MODE_DOMAIN: &str = "domain";
 pub const CGROUP_MODE_DOMAIN_THREADED: &str = "domain threaded";
 pub const CGROUP_MODE_DOMAIN_INVALID: &str = "domain invalid";
 pub const CGROUP_MODE_THREADED: &str = "threaded";
 
+/// The type of a v2 control group, read from `cgroup.type`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CgroupType {
+    /// A normal control group, which may contain processes and enable domain controllers.
+    Domain,
+    /// The root of a threaded subtree.
+    DomainThreaded,
+    /// A control group in an invalid state, which can't be populated or have controllers
+    /// enabled. It may be turned into a threaded control group.
+    DomainInvalid,
+    /// A member of a threaded subtree, whose threads may be placed independently of the other
+    /// threads of their process.
+    Threaded,
+}
+
+impl fmt::Display for CgroupType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CgroupType::Domain => write!(f, "{}", CGROUP_MODE_DOMAIN),
+            CgroupType::DomainThreaded => write!(f, "{}", CGROUP_MODE_DOMAIN_THREADED),
+            CgroupType::DomainInvalid => write!(f, "{}", CGROUP_MODE_DOMAIN_INVALID),
+            CgroupType::Threaded => write!(f, "{}", CGROUP_MODE_THREADED),
+        }
+    }
+}
+
+impl FromStr for CgroupType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            CGROUP_MODE_DOMAIN => Ok(CgroupType::Domain),
+            CGROUP_MODE_DOMAIN_THREADED => Ok(CgroupType::DomainThreaded),
+            CGROUP_MODE_DOMAIN_INVALID => Ok(CgroupType::DomainInvalid),
+            CGROUP_MODE_THREADED => Ok(CgroupType::Threaded),
+            _ => Err(Error::new(ParseError)),
+        }
+    }
+}
+
 /// A control group is the central structure to this crate.
 ///
 ///
@@ -79,14 +134,169 @@ impl Cgroup {
         &self.path
     }
 
+    /// Whether this is the root control group of the hierarchy.
+    pub fn is_root(&self) -> bool {
+        self.path.trim_matches('/').is_empty()
+    }
+
+    /// The directory of the control group in the v2 hierarchy, which is the unified hierarchy in
+    /// hybrid mode.
+    fn v2_dir(&self) -> Result<PathBuf> {
+        let root = self
+            .hier
+            .unified_root()
+            .ok_or_else(|| Error::new(CgroupVersion))?;
+        self.check_path()?;
+        Ok(root.join(self.path.trim_start_matches('/')))
+    }
+
+    /// The directory mirroring the control group in the unified hierarchy in hybrid mode.
+    pub(crate) fn hybrid_dir(&self) -> Result<Option<PathBuf>> {
+        if self.v2() {
+            return Ok(None);
+        }
+        self.v2_dir().map(Some).or_else(|e| match e.kind() {
+            CgroupVersion => Ok(None),
+            _ => Err(e),
+        })
+    }
+
+    /// The directory of the control group in the unified hierarchy in hybrid mode, if it's part of
+    /// a threaded subtree there, i.e. its threads can be placed independently of their process.
+    fn hybrid_threaded_dir(&self) -> Result<Option<PathBuf>> {
+        let dir = match self.hybrid_dir()? {
+            Some(dir) => dir,
+            None => return Ok(None),
+        };
+        // The root has no cgroup.type, and is never threaded itself.
+        let cgroup_type = match DirFd::default().open(&dir, "cgroup.type", false) {
+            Ok(file) => read_string_from(file)?.parse::<CgroupType>()?,
+            Err(_) => return Ok(None),
+        };
+        match cgroup_type {
+            CgroupType::DomainThreaded | CgroupType::Threaded => Ok(Some(dir)),
+            _ => Ok(None),
+        }
+    }
+
+    /// The mirror, in the unified hierarchy in hybrid mode, of the v1 control group of the
+    /// process `pid`. Falls back to the root of the unified hierarchy.
+    fn hybrid_follow(&self, pid: CgroupPid) -> PathBuf {
+        let root = self.hier.unified_root().unwrap_or_default();
+        self.subsystems
+            .first()
+            .and_then(|sub| {
+                get_cgroups_relative_paths_by_pid(pid.pid as u32)
+                    .ok()?
+                    .remove(&sub.controller_name())
+            })
+            .map(|path| root.join(path.trim_start_matches('/')))
+            .filter(|dir| is_beneath(dir.strip_prefix(&root).unwrap_or(dir)) && dir.exists())
+            .unwrap_or(root)
+    }
+
+    /// The ID of the control group, i.e. the inode number of its directory. This is the value
+    /// returned by `bpf_get_current_cgroup_id()` for the tasks of the control group. Only
+    /// supported for v2 cgroups and in hybrid mode.
+    pub fn id(&self) -> Result<u64> {
+        let dir = self.v2_dir()?;
+        fs::symlink_metadata(&dir)
+            .map(|m| m.ino())
+            .map_err(|e| Error::with_cause(ReadFailed(dir.display().to_string()), e))
+    }
+
+    /// Opens the directory of the control group, e.g. for `clone3(CLONE_INTO_CGROUP)` or to
+    /// attach BPF programs. Symlinks are not followed. Only supported for v2 cgroups and in hybrid
+    /// mode.
+    pub fn dir_fd(&self) -> Result<fs::File> {
+        open_dir(&self.v2_dir()?)
+    }
+
+    /// Create a handle for the control group with the ID `id` in the v2 hierarchy `hier`.
+    ///
+    /// The control group is looked up with `open_by_handle_at(2)` if the process has
+    /// `CAP_DAC_READ_SEARCH`, by walking the hierarchy otherwise.
+    pub fn load_by_id(hier: Box<dyn Hierarchy>, id: u64) -> Result<Cgroup> {
+        let root = hier
+            .unified_root()
+            .ok_or_else(|| Error::new(CgroupVersion))?;
+        let dir = match path_by_handle(&root, id) {
+            Some(dir) => dir,
+            None => find_by_ino(&root, id)?.ok_or_else(|| {
+                Error::from_string(format!("no control group with id {} found", id))
+            })?,
+        };
+        let path = dir
+            .strip_prefix(&root)
+            .map_err(|_| Error::new(InvalidPath))?;
+        Ok(Cgroup::load(hier, path))
+    }
+
+    /// Create a handle for the control group the process `pid` is in, in the hierarchy of the
+    /// system, see `hierarchies::auto()`.
+    ///
+    /// On v1, and in hybrid mode, each controller enters the control group of the process in its
+    /// hierarchy. `path()` is then the control group of the process in the unified hierarchy in
+    /// hybrid mode, i.e. the one its mirror is looked up with, and on v1 the one of the first
+    /// controller of `Hierarchy::subsystems()`, since the hierarchies may disagree. Paths seen
+    /// from another cgroup namespace are translated to the paths below the mount points.
+    pub fn for_pid(pid: CgroupPid) -> Result<Cgroup> {
+        let hier = crate::fs::hierarchies::auto();
+        let paths = get_cgroups_relative_paths_by_pid(pid.pid as u32)?;
+        let ns = NamespacePaths::current()?;
+        let relative = |key: &str| -> Result<String> {
+            let path = paths.get(key).ok_or_else(|| {
+                Error::from_string(format!(
+                    "process {} isn't in any control group of {:?}",
+                    pid.pid, key
+                ))
+            })?;
+            ns.to_hierarchy_path(key, path)
+        };
+
+        if hier.v2() {
+            let path = relative("")?;
+            return Ok(Cgroup::load(hier, path.trim_start_matches('/')));
+        }
+
+        let mut path = match hier.unified_root() {
+            Some(_) => Some(relative("")?.trim_start_matches('/').to_string()),
+            None => None,
+        };
+        let mut subsystems = vec![];
+        for sub in hier.subsystems() {
+            let rel = relative(&sub.controller_name())?;
+            path.get_or_insert_with(|| rel.trim_start_matches('/').to_string());
+            subsystems.push(sub.enter(Path::new(&rel)));
+        }
+        Ok(Cgroup {
+            path: path.unwrap_or_default(),
+            subsystems,
+            hier,
+            specified_controllers: None,
+        })
+    }
+
+    /// Fails with `InvalidPath` if the path of the control group escapes the hierarchy.
+    fn check_path(&self) -> Result<()> {
+        if !is_beneath(Path::new(self.path.trim_start_matches('/'))) {
+            return Err(Error::new(InvalidPath));
+        }
+        Ok(())
+    }
+
     /// Create this control group.
     pub fn create(&self) -> Result<()> {
+        self.check_path()?;
         if self.hier.v2() {
             create_v2_cgroup(self.hier.root(), &self.path, &self.specified_controllers)
         } else {
             self.subsystems
                 .iter()
                 .try_for_each(|subsystem| subsystem.to_controller().create())?;
+            if let Some(dir) = self.hybrid_dir()? {
+                fs::create_dir_all(dir).map_err(|e| Error::with_cause(FsError, e))?;
+            }
             Ok(())
         }
     }
@@ -237,7 +447,9 @@ impl Cgroup {
     /// actually removed, and remove the descendants first if not. In the future, this behavior
     /// will change.
     pub fn delete(&self) -> Result<()> {
+        self.check_path()?;
         if self.v2() {
+            self.subsystems.iter().for_each(|sub| sub.get_dir().reset());
             if !self.path.is_empty() {
                 let mut p = self.hier.root();
                 p.push(self.path.clone());
@@ -260,12 +472,71 @@ impl Cgroup {
             Subsystem::NetPrio(c) => c.delete(),
             Subsystem::HugeTlb(c) => c.delete(),
             Subsystem::Rdma(c) => c.delete(),
+            Subsystem::Misc(c) => c.delete(),
             Subsystem::Systemd(c) => c.delete(),
-        })
+        })?;
+
+        match self.hybrid_dir()? {
+            Some(dir) if !self.is_root() && dir.exists() => {
+                // The v1 directories are gone, so any process left in the mirror was moved out
+                // of the control group with the v1 interfaces. Move it along in the mirror.
+                for pid in read_procs(&dir) {
+                    let target = self.hybrid_follow(pid);
+                    let _ = fs::write(target.join("cgroup.procs"), pid.pid.to_string());
+                }
+                fs::remove_dir(dir).map_err(|e| Error::with_cause(RemoveFailed, e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Deletes the control group and all its descendants, bottom-up.
+    ///
+    /// Before a control group is removed, the processes left in it are handled according to
+    /// `opts.procs`. Removal is retried `opts.retries` times with an exponential backoff,
+    /// evicting the processes again before each attempt. Control groups that couldn't be removed
+    /// are listed in the returned report, along with the processes that were still in them.
+    ///
+    /// The root control group can't be deleted, this fails with `InvalidOperation`.
+    pub fn delete_recursive(&self, opts: &DeleteOptions) -> Result<DeleteReport> {
+        if self.is_root() {
+            return Err(Error::new(InvalidOperation));
+        }
+        self.check_path()?;
+
+        let mut tops = if self.v2() {
+            vec![self.hier.root().join(self.path.clone())]
+        } else {
+            self.subsystems
+                .iter()
+                .map(|sub| sub.to_controller().path().to_path_buf())
+                .chain(self.hybrid_dir()?)
+                .collect::<Vec<_>>()
+        };
+        // Co-mounted v1 controllers share the same directory.
+        tops.sort();
+        tops.dedup();
+
+        if let DeleteProcs::Kill = opts.procs {
+            // Kill the whole subtree at once where cgroup.kill is available.
+            let _ = self.kill();
+        }
+
+        let parent = self.parent_control_group();
+        let mut report = DeleteReport::default();
+        for top in tops.iter().filter(|t| t.exists()) {
+            for dir in descendants_post_order(top)? {
+                delete_one(&dir, opts, &parent, &mut report);
+            }
+        }
+        report.evicted.sort();
+        report.evicted.dedup();
+        Ok(report)
     }
 
     /// Apply a set of resource limits to the control group.
     pub fn apply(&self, res: &Resources) -> Result<()> {
+        self.apply_hierarchy(&res.hierarchy)?;
         self.subsystems
             .iter()
             .try_fold((), |_, e| e.to_controller().apply(res))
@@ -283,14 +554,14 @@ impl Cgroup {
     /// ```
     pub fn controller_of<'a, T>(&'a self) -> Option<&'a T>
     where
-        &'a T: From<&'a Subsystem>,
+        &'a T: TryFrom<&'a Subsystem>,
         T: Controller + ControllIdentifier,
     {
         for i in &self.subsystems {
             if i.to_controller().control_type() == T::controller_type() {
                 // N.B.:
                 // https://play.rust-lang.org/?gist=978b2846bacebdaa00be62374f4f4334&version=stable&mode=debug&edition=2015
-                return Some(i.into());
+                return <&T>::try_from(i).ok();
             }
         }
         None
@@ -300,6 +571,8 @@ impl Cgroup {
     ///
     /// Note that this means that the task will be moved back to the root control group in the
     /// hierarchy and any rules applied to that control group will _still_ apply to the proc.
+    ///
+    /// In hybrid mode, the process is moved to the root of the unified hierarchy as well.
     pub fn remove_task_by_tgid(&self, tgid: CgroupPid) -> Result<()> {
         self.hier.root_control_group().add_task_by_tgid(tgid)
     }
@@ -308,11 +581,15 @@ impl Cgroup {
     ///
     /// Note that this means that the task will be moved back to the root control group in the
     /// hierarchy and any rules applied to that control group will _still_ apply to the task.
+    ///
+    /// In hybrid mode, the task is left where it is in the unified hierarchy, see `add_task()`.
     pub fn remove_task(&self, tid: CgroupPid) -> Result<()> {
         self.hier.root_control_group().add_task(tid)
     }
 
     /// Moves tasks to the parent control group by thread group id.
+    ///
+    /// In hybrid mode, the process is moved in the unified hierarchy as well.
     pub fn move_task_to_parent_by_tgid(&self, tgid: CgroupPid) -> Result<()> {
         self.hier
             .parent_control_group(&self.path)
@@ -320,6 +597,9 @@ impl Cgroup {
     }
 
     /// Moves a task to the parent control group.
+    ///
+    /// In hybrid mode, the task is moved in the unified hierarchy as well if possible, see
+    /// `add_task()`.
     pub fn move_task_to_parent(&self, tid: CgroupPid) -> Result<()> {
         self.hier.parent_control_group(&self.path).add_task(tid)
     }
@@ -329,17 +609,13 @@ impl Cgroup {
         self.hier.parent_control_group(&self.path)
     }
 
-    /// Kill every process in the control group. Only supported for v2 cgroups and on
-    /// kernels 5.14+. This will fail with InvalidOperation if the 'cgroup.kill' file does
+    /// Kill every process in the control group. Only supported for v2 cgroups and in hybrid mode,
+    /// on kernels 5.14+. This will fail with InvalidOperation if the 'cgroup.kill' file does
     /// not exist.
     pub fn kill(&self) -> Result<()> {
-        if !self.v2() {
-            return Err(Error::new(CgroupVersion));
-        }
-
         let val = "1";
         let file_name = "cgroup.kill";
-        let p = self.hier.root().join(self.path.clone()).join(file_name);
+        let p = self.v2_dir()?.join(file_name);
 
         // If cgroup.kill doesn't exist they're not on 5.14+ so lets
         // surface some error the caller can check against.
@@ -351,16 +627,185 @@ impl Cgroup {
             .map_err(|e| Error::with_cause(WriteFailed(file_name.to_string(), val.to_string()), e))
     }
 
+    /// The state of the control group, read from `cgroup.events`. Only supported for v2 cgroups
+    /// and in hybrid mode.
+    pub fn events(&self) -> Result<CgroupEvents> {
+        let dir = self.v2_dir()?;
+        let mut content = String::new();
+        DirFd::default()
+            .open(&dir, "cgroup.events", false)?
+            .read_to_string(&mut content)
+            .map_err(|e| Error::with_cause(ReadFailed("cgroup.events".to_string()), e))?;
+        parse_cgroup_events(&content)
+    }
+
+    fn apply_hierarchy(&self, res: &HierarchyResources) -> Result<()> {
+        if let Some(depth) = res.max_depth {
+            self.set_max_depth(depth)?;
+        }
+        if let Some(descendants) = res.max_descendants {
+            self.set_max_descendants(descendants)?;
+        }
+        Ok(())
+    }
+
+    /// Read a core interface file (`cgroup.*`) of a v2 control group.
+    fn read_core_file(&self, file_name: &str) -> Result<String> {
+        if !self.v2() {
+            return Err(Error::new(CgroupVersion));
+        }
+
+        let dir = self.v2_dir()?;
+        let mut content = String::new();
+        DirFd::default()
+            .open(&dir, file_name, false)?
+            .read_to_string(&mut content)
+            .map_err(|e| Error::with_cause(ReadFailed(file_name.to_string()), e))?;
+        Ok(content)
+    }
+
+    /// Write a core interface file (`cgroup.*`) of a v2 control group.
+    fn write_core_file(&self, file_name: &str, val: &str) -> Result<()> {
+        if !self.v2() {
+            return Err(Error::new(CgroupVersion));
+        }
+
+        let dir = self.v2_dir()?;
+        DirFd::default()
+            .open(&dir, file_name, true)?
+            .write_all(val.as_bytes())
+            .map_err(|e| Error::with_cause(WriteFailed(file_name.to_string(), val.to_string()), e))
+    }
+
+    /// The controllers available to the control group, read from `cgroup.controllers`. Only
+    /// supported for v2 cgroups.
+    pub fn available_controllers(&self) -> Result<Vec<String>> {
+        if !self.v2() {
+            return Err(Error::new(CgroupVersion));
+        }
+        read_controller_list(
+            &self.hier.root().join(self.path.clone()),
+            "cgroup.controllers",
+        )
+    }
+
+    /// The controllers enabled for the children of the control group, read from
+    /// `cgroup.subtree_control`. Only supported for v2 cgroups.
+    pub fn subtree_control(&self) -> Result<Vec<String>> {
+        if !self.v2() {
+            return Err(Error::new(CgroupVersion));
+        }
+        read_controller_list(
+            &self.hier.root().join(self.path.clone()),
+            "cgroup.subtree_control",
+        )
+    }
+
+    /// Enable `controllers` for the children of the control group.
+    ///
+    /// The controllers are enabled in every ancestor as well, starting from the root of the
+    /// hierarchy, since a controller can only be enabled if it's enabled in the parent. The
+    /// returned `SubtreeControl` error names the control group and the kernel's errno of the
+    /// first write that failed. Only supported for v2 cgroups.
+    pub fn enable_controllers(&self, controllers: &[String]) -> Result<()> {
+        if !self.v2() {
+            return Err(Error::new(CgroupVersion));
+        }
+
+        let mut fp = self.hier.root();
+        enable_controllers(controllers, &fp)?;
+        for ele in self.path.split('/').filter(|e| !e.is_empty()) {
+            fp.push(ele);
+            enable_controllers(controllers, &fp)?;
+        }
+        Ok(())
+    }
+
+    /// Disable `controllers` for the children of the control group.
+    ///
+    /// This fails with a `SubtreeControl` error if one of the children still enables the
+    /// controller for its own children. Only supported for v2 cgroups.
+    pub fn disable_controllers(&self, controllers: &[String]) -> Result<()> {
+        if !self.v2() {
+            return Err(Error::new(CgroupVersion));
+        }
+        disable_controllers(controllers, &self.hier.root().join(self.path.clone()))
+    }
+
+    /// Statistics about the descendants of the control group, read from `cgroup.stat`.
+    ///
+    /// Only supported for v2 cgroups. The per-subsystem counts are only reported by kernels
+    /// 6.7+, they are left empty otherwise.
+    pub fn stat(&self) -> Result<CgroupStat> {
+        self.read_core_file("cgroup.stat")
+            .and_then(|s| parse_cgroup_stat(&s))
+    }
+
+    /// The maximum allowed depth of the sub-hierarchy below the control group, read from
+    /// `cgroup.max.depth`. Only supported for v2 cgroups.
+    pub fn max_depth(&self) -> Result<MaxValue> {
+        self.read_core_file("cgroup.max.depth")
+            .and_then(|s| parse_max_value(&s))
+    }
+
+    /// Limit the depth of the sub-hierarchy below the control group. An attempt to create a
+    /// descendant deeper than the limit fails with `EAGAIN`. Only supported for v2 cgroups.
+    pub fn set_max_depth(&self, depth: MaxValue) -> Result<()> {
+        self.write_core_file("cgroup.max.depth", &depth.to_string())
+    }
+
+    /// The maximum allowed number of descendants of the control group, read from
+    /// `cgroup.max.descendants`. Only supported for v2 cgroups.
+    pub fn max_descendants(&self) -> Result<MaxValue> {
+        self.read_core_file("cgroup.max.descendants")
+            .and_then(|s| parse_max_value(&s))
+    }
+
+    /// Limit the number of descendants of the control group. Dying descendants are counted as
+    /// well. Only supported for v2 cgroups.
+    pub fn set_max_descendants(&self, descendants: MaxValue) -> Result<()> {
+        self.write_core_file("cgroup.max.descendants", &descendants.to_string())
+    }
+
+    /// Delegate the control group to the user `uid` and the group `gid`, e.g. to hand it to a
+    /// rootless container runtime.
+    ///
+    /// `controllers` are enabled for the children of the control group first, see
+    /// `enable_controllers()`, which fails with `EBUSY` if the control group has processes. The
+    /// owner of the directory and of the files listed in `/sys/kernel/cgroup/delegate` is then
+    /// changed and checked. Returns the files that were delegated.
+    ///
+    /// To move its processes into the control group, the delegatee also needs write access to
+    /// `cgroup.procs` of the common ancestor of their current and new control groups, which is
+    /// left to the caller. Only supported for v2 cgroups.
+    pub fn delegate(&self, uid: u32, gid: u32, controllers: &[String]) -> Result<Vec<String>> {
+        if !self.v2() {
+            return Err(Error::new(CgroupVersion));
+        }
+        let dir = self.v2_dir()?;
+        if self.is_root() {
+            return Err(Error::new(InvalidOperation));
+        }
+        if !controllers.is_empty() {
+            self.enable_controllers(controllers)?;
+        }
+        chown_delegated(&dir, &delegate_files(), uid, gid)
+    }
+
     /// Attach a task to the control group.
+    ///
+    /// In hybrid mode, the task is moved in the unified hierarchy as well, best effort like
+    /// `add_task_by_tgid()`, if the control group exists and is threaded there. Otherwise the
+    /// threads of a process can't be split between control groups of the unified hierarchy, so
+    /// the task is left where it is there; use `add_task_by_tgid()` to move the whole process.
     pub fn add_task(&self, tid: CgroupPid) -> Result<()> {
         if self.v2() {
             let subsystems = self.subsystems();
             if !subsystems.is_empty() {
                 let c = subsystems[0].to_controller();
-                let cgroup_type = self.get_cgroup_type()?;
+                let cgroup_type = self.cgroup_type()?;
                 // In cgroup v2, writing to the cgroup.threads file is only supported in thread mode.
-                if cgroup_type == *CGROUP_MODE_DOMAIN_THREADED
-                    || cgroup_type == *CGROUP_MODE_THREADED
+                if cgroup_type == CgroupType::DomainThreaded || cgroup_type == CgroupType::Threaded
                 {
                     // It is used to move the threads of a process into a cgroup in thread mode.
                     c.add_task(&tid)
@@ -373,13 +818,20 @@ impl Cgroup {
                 Err(Error::new(SubsystemsEmpty))
             }
         } else {
+            let mirror = self.hybrid_threaded_dir()?;
             self.subsystems()
                 .iter()
-                .try_for_each(|sub| sub.to_controller().add_task(&tid))
+                .try_for_each(|sub| sub.to_controller().add_task(&tid))?;
+            hybrid_attach(mirror, "cgroup.threads", tid);
+            Ok(())
         }
     }
 
     /// Attach tasks to the control group by thread group id.
+    ///
+    /// In hybrid mode, the process is moved in the unified hierarchy as well if the control group
+    /// exists there. This is best effort: once the process was moved in the v1 hierarchies, a
+    /// failure to move it in the unified hierarchy isn't reported.
     pub fn add_task_by_tgid(&self, tgid: CgroupPid) -> Result<()> {
         if self.v2() {
             let subsystems = self.subsystems();
@@ -392,12 +844,39 @@ impl Cgroup {
                 Err(Error::new(SubsystemsEmpty))
             }
         } else {
+            let mirror = self.hybrid_dir()?;
             self.subsystems()
                 .iter()
-                .try_for_each(|sub| sub.to_controller().add_task_by_tgid(&tgid))
+                .try_for_each(|sub| sub.to_controller().add_task_by_tgid(&tgid))?;
+            hybrid_attach(mirror, "cgroup.procs", tgid);
+            Ok(())
         }
     }
 
+    /// Attach a process to the control group, like `add_task_by_tgid()`, checking with its pidfd
+    /// that it still runs before and after, so that a recycled PID is never attached.
+    ///
+    /// Fails with `ProcessExited` if the process exited. It's then unspecified whether the
+    /// process that was given its PID, if any, was attached.
+    pub fn add_task_by_pidfd(&self, pidfd: &Pidfd) -> Result<()> {
+        pidfd.with_pid(
+            |pid| self.add_task_by_tgid(pid),
+            |e| Error::with_cause(ProcessExited(pidfd.pid().pid), e),
+        )
+    }
+
+    /// Moves all the processes of the control group to `dest`, or all the threads if `dest` is a
+    /// threaded v2 control group, see `migrate::MigrateOptions`. The processes of the descendants
+    /// are not moved.
+    ///
+    /// The processes that couldn't be moved are listed in the returned report. Fails if the
+    /// control groups couldn't be frozen or thawed, or the charges set to be moved.
+    pub fn migrate(&self, dest: &Cgroup, opts: &MigrateOptions) -> Result<MigrateReport> {
+        self.check_path()?;
+        dest.check_path()?;
+        migrate(self, dest, opts)
+    }
+
     /// set cgroup.type
     pub fn set_cgroup_type(&self, cgroup_type: &str) -> Result<()> {
         if self.v2() {
@@ -429,6 +908,82 @@ impl Cgroup {
         }
     }
 
+    /// The type of the control group. Only supported for v2 cgroups.
+    pub fn cgroup_type(&self) -> Result<CgroupType> {
+        self.get_cgroup_type()?.parse()
+    }
+
+    /// Turn the control group into a threaded one, turning its parent into the root of a
+    /// threaded subtree if it isn't part of one yet. Only threaded controllers (e.g. `cpu`,
+    /// `cpuset`, `pids`) can be used below that root. Only supported for v2 cgroups.
+    pub fn set_threaded(&self) -> Result<()> {
+        self.set_cgroup_type(CGROUP_MODE_THREADED)
+    }
+
+    /// Move the thread `tid` of the process `tgid` into the control group, leaving the other
+    /// threads of the process where they are.
+    ///
+    /// On v2 the control group must be part of a threaded subtree that the process belongs to.
+    /// Fails with `InvalidOperation` if `tid` is not a thread of `tgid`, before and after the
+    /// move, and with `ProcessExited` if the process, held through its pidfd, exited. It's then
+    /// unspecified whether the thread that was given `tid`, if any, was moved.
+    pub fn move_thread(&self, tgid: CgroupPid, tid: CgroupPid) -> Result<()> {
+        let is_thread = || Path::new(&format!("/proc/{}/task/{}", tgid.pid, tid.pid)).exists();
+        let move_thread = |_| {
+            if !is_thread() {
+                return Err(Error::new(InvalidOperation));
+            }
+            self.add_task(tid)?;
+            // The thread may have exited and its TID been recycled during the move.
+            if !is_thread() {
+                return Err(Error::new(InvalidOperation));
+            }
+            Ok(())
+        };
+
+        match Pidfd::open(tgid) {
+            Ok(pidfd) => pidfd.with_pid(move_thread, |e| {
+                Error::with_cause(ProcessExited(tgid.pid), e)
+            }),
+            // pidfds require kernel 5.3+.
+            Err(e) if e.raw_os_error() == Some(libc::ENOSYS) => move_thread(tgid),
+            Err(e) => Err(Error::with_cause(ProcessExited(tgid.pid), e)),
+        }
+    }
+
+    /// The threads in each child of the control group, keyed by the name of the child.
+    ///
+    /// Useful to check the placement of the threads in the threaded children of the root of a
+    /// threaded subtree. Only supported for v2 cgroups.
+    pub fn threads_per_child(&self) -> Result<HashMap<String, Vec<CgroupPid>>> {
+        if !self.v2() {
+            return Err(Error::new(CgroupVersion));
+        }
+
+        let dir = self.hier.root().join(self.path.clone());
+        let entries = fs::read_dir(&dir)
+            .map_err(|e| Error::with_cause(ReadFailed(dir.display().to_string()), e))?;
+        let mut m = HashMap::new();
+        for entry in entries {
+            let entry =
+                entry.map_err(|e| Error::with_cause(ReadFailed(dir.display().to_string()), e))?;
+            if !entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                continue;
+            }
+
+            let p = entry.path().join("cgroup.threads");
+            let content = fs::read_to_string(&p)
+                .map_err(|e| Error::with_cause(ReadFailed(p.display().to_string()), e))?;
+            let threads = content
+                .lines()
+                .filter_map(|l| l.trim().parse::<u64>().ok())
+                .map(CgroupPid::from)
+                .collect();
+            m.insert(entry.file_name().to_string_lossy().to_string(), threads);
+        }
+        Ok(m)
+    }
+
     /// Set notify_on_release to the control group.
     pub fn set_notify_on_release(&self, enable: bool) -> Result<()> {
         self.subsystems()
@@ -507,23 +1062,419 @@ impl Cgroup {
     }
 }
 
+/// The content of `cgroup.events`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub struct CgroupEvents {
+    /// Whether the control group or one of its descendants has live processes.
+    pub populated: bool,
+    /// Whether the control group is frozen. Not reported by kernels older than 5.2.
+    pub frozen: bool,
+}
+
+fn parse_cgroup_events(s: &str) -> Result<CgroupEvents> {
+    let mut events = CgroupEvents::default();
+    for line in s.lines().filter(|l| !l.trim().is_empty()) {
+        let (key, value) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [key, "0"] => (key, false),
+            [key, "1"] => (key, true),
+            _ => return Err(Error::new(ParseError)),
+        };
+
+        if key == "populated" {
+            events.populated = value;
+        } else if key == "frozen" {
+            events.frozen = value;
+        }
+    }
+    Ok(events)
+}
+
+/// The content of `cgroup.stat`.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CgroupStat {
+    /// Total number of visible descendant control groups.
+    pub nr_descendants: u64,
+    /// Total number of dying descendant control groups, i.e. removed ones whose resources are
+    /// not released yet.
+    pub nr_dying_descendants: u64,
+    /// Number of live control groups, including this one, in which each subsystem is enabled.
+    pub nr_subsys: HashMap<String, u64>,
+    /// Number of dying control groups, including this one, of each subsystem.
+    pub nr_dying_subsys: HashMap<String, u64>,
+}
+
+fn parse_cgroup_stat(s: &str) -> Result<CgroupStat> {
+    let mut stat = CgroupStat::default();
+    for line in s.lines().filter(|l| !l.trim().is_empty()) {
+        let (key, value) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [key, value] => (
+                key,
+                value
+                    .parse::<u64>()
+                    .map_err(|e| Error::with_cause(ParseError, e))?,
+            ),
+            _ => return Err(Error::new(ParseError)),
+        };
+
+        if key == "nr_descendants" {
+            stat.nr_descendants = value;
+        } else if key == "nr_dying_descendants" {
+            stat.nr_dying_descendants = value;
+        } else if let Some(subsys) = key.strip_prefix("nr_subsys_") {
+            stat.nr_subsys.insert(subsys.to_string(), value);
+        } else if let Some(subsys) = key.strip_prefix("nr_dying_subsys_") {
+            stat.nr_dying_subsys.insert(subsys.to_string(), value);
+        }
+    }
+    Ok(stat)
+}
+
+/// The evolution of the number of dying descendants, as reported by
+/// `DyingDescendantsTracker::sample()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DyingDescendantsGrowth {
+    /// The current number of dying descendants.
+    pub current: u64,
+    /// The change since the previous sample.
+    pub delta: i64,
+    /// The change since the first sample.
+    pub total_delta: i64,
+    /// Time elapsed since the first sample.
+    pub elapsed: Duration,
+    /// Average growth per second since the first sample.
+    pub rate: f64,
+}
+
+/// Keeps track of `nr_dying_descendants` of a control group to detect leaking dying cgroups,
+/// e.g. memory cgroups pinned by page cache that is never reclaimed.
+///
+/// ## Example:
+///
+/// ```text
+/// let mut tracker = DyingDescendantsTracker::new();
+/// loop {
+///     let growth = tracker.sample(&cg)?;
+///     if growth.rate > 1.0 {
+///         warn!("{} dying cgroups and counting", growth.current);
+///     }
+///     std::thread::sleep(interval);
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DyingDescendantsTracker {
+    first: Option<(Instant, u64)>,
+    last: Option<u64>,
+}
+
+impl DyingDescendantsTracker {
+    /// A tracker without samples yet, the first one is the reference of the growth rate.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads `cgroup.stat` of `cg` and reports the growth of the number of dying descendants
+    /// since the previous and first samples.
+    pub fn sample(&mut self, cg: &Cgroup) -> Result<DyingDescendantsGrowth> {
+        let stat = cg.stat()?;
+        Ok(self.record(Instant::now(), stat.nr_dying_descendants))
+    }
+
+    fn record(&mut self, now: Instant, current: u64) -> DyingDescendantsGrowth {
+        let (start, first) = *self.first.get_or_insert((now, current));
+        let last = self.last.replace(current).unwrap_or(current);
+
+        let elapsed = now.duration_since(start);
+        let total_delta = current as i64 - first as i64;
+        let rate = if elapsed.is_zero() {
+            0.0
+        } else {
+            total_delta as f64 / elapsed.as_secs_f64()
+        };
+
+        DyingDescendantsGrowth {
+            current,
+            delta: current as i64 - last as i64,
+            total_delta,
+            elapsed,
+            rate,
+        }
+    }
+}
+
+/// The longest delay between two attempts of `Cgroup::delete_recursive()` or `Cgroup::migrate()`,
+/// unless their initial backoff is longer.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Doubles `delay`, up to `MAX_BACKOFF`.
+pub(crate) fn next_backoff(delay: Duration) -> Duration {
+    delay.saturating_mul(2).min(MAX_BACKOFF.max(delay))
+}
+
+/// What to do with the processes left in a control group that is deleted recursively.
+#[derive(Debug, Clone, Copy)]
+pub enum DeleteProcs<'a> {
+    /// Leave the processes alone, the control group can't be removed while they're alive.
+    Keep,
+    /// Kill the processes with `SIGKILL`.
+    Kill,
+    /// Move the processes to the parent of the control group being deleted.
+    MoveToParent,
+    /// Move the processes to the given control group.
+    MoveTo(&'a Cgroup),
+}
+
+/// Options of `Cgroup::delete_recursive()`.
+#[derive(Debug, Clone, Copy)]
+pub struct DeleteOptions<'a> {
+    /// What to do with the processes left in the control groups.
+    pub procs: DeleteProcs<'a>,
+    /// How many times the removal of a control group is retried.
+    pub retries: u32,
+    /// The delay before the first retry, doubled after every retry up to `MAX_BACKOFF`.
+    pub backoff: Duration,
+}
+
+impl Default for DeleteOptions<'_> {
+    fn default() -> Self {
+        // Same as `Controller::delete()`, compatible with runC.
+        DeleteOptions {
+            procs: DeleteProcs::Keep,
+            retries: 4,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+/// A control group `Cgroup::delete_recursive()` failed to remove.
+#[derive(Debug)]
+pub struct DeleteFailure {
+    /// The path of the control group.
+    pub path: PathBuf,
+    /// The processes still in the control group after the last attempt.
+    pub procs: Vec<CgroupPid>,
+    /// The error of the last attempt.
+    pub error: Error,
+}
+
+/// The outcome of `Cgroup::delete_recursive()`.
+#[derive(Debug, Default)]
+pub struct DeleteReport {
+    /// The control groups that were removed, in the order of removal.
+    pub removed: Vec<PathBuf>,
+    /// The control groups that couldn't be removed.
+    pub failed: Vec<DeleteFailure>,
+    /// The processes that were killed or moved out of the deleted control groups.
+    pub evicted: Vec<CgroupPid>,
+}
+
+impl DeleteReport {
+    /// Whether every control group was removed.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Lists `top` and all the directories below it, children before their parents.
+fn descendants_post_order(top: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(top)
+        .map_err(|e| Error::with_cause(ReadFailed(top.display().to_string()), e))?
+    {
+        let entry =
+            entry.map_err(|e| Error::with_cause(ReadFailed(top.display().to_string()), e))?;
+        if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+            dirs.append(&mut descendants_post_order(&entry.path())?);
+        }
+    }
+    dirs.push(top.to_path_buf());
+    Ok(dirs)
+}
+
+fn read_procs(dir: &Path) -> Vec<CgroupPid> {
+    fs::read_to_string(dir.join("cgroup.procs"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| l.trim().parse::<u64>().ok())
+        .map(CgroupPid::from)
+        .collect()
+}
+
+/// Moves the processes of `procs` still in `dir` to `dest`, without moving a process that got
+/// the PID of one that exited.
+/// Moves the processes of `procs` that are members of `dir` to `dest`, returning the moved ones.
+fn move_members(dir: &Path, procs: &[CgroupPid], dest: &Cgroup) -> Vec<CgroupPid> {
+    match open_members(procs, || read_procs(dir)) {
+        Some(pidfds) => pidfds
+            .iter()
+            .filter(|pidfd| dest.add_task_by_pidfd(pidfd).is_ok())
+            .map(Pidfd::pid)
+            .collect(),
+        // Without pidfds, a recycled PID may be moved.
+        None => procs
+            .iter()
+            .filter(|pid| dest.add_task_by_tgid(**pid).is_ok())
+            .copied()
+            .collect(),
+    }
+}
+
+/// Kills or moves the processes of `procs`, returning the ones that were.
+fn evict(dir: &Path, procs: &[CgroupPid], action: DeleteProcs, parent: &Cgroup) -> Vec<CgroupPid> {
+    // Failures are reported through the processes left when the removal fails.
+    match action {
+        DeleteProcs::Keep => vec![],
+        DeleteProcs::Kill => signal_members(procs, || read_procs(dir), libc::SIGKILL),
+        DeleteProcs::MoveToParent => move_members(dir, procs, parent),
+        DeleteProcs::MoveTo(cg) => move_members(dir, procs, cg),
+    }
+}
+
+fn delete_one(dir: &Path, opts: &DeleteOptions, parent: &Cgroup, report: &mut DeleteReport) {
+    let mut delay = opts.backoff;
+    let mut attempt = 0;
+    loop {
+        let procs = read_procs(dir);
+        if !procs.is_empty() {
+            report
+                .evicted
+                .extend(evict(dir, &procs, opts.procs, parent));
+        }
+
+        match fs::remove_dir(dir) {
+            Ok(()) => {
+                report.removed.push(dir.to_path_buf());
+                return;
+            }
+            Err(_) if attempt < opts.retries => {
+                std::thread::sleep(delay);
+                delay = next_backoff(delay);
+                attempt += 1;
+            }
+            Err(e) => {
+                report.failed.push(DeleteFailure {
+                    path: dir.to_path_buf(),
+                    procs: read_procs(dir),
+                    error: Error::with_cause(RemoveFailed, e),
+                });
+                return;
+            }
+        }
+    }
+}
+
 pub const UNIFIED_MOUNTPOINT: &str = "/sys/fs/cgroup";
 
-fn enable_controllers(controllers: &[String], path: &Path) {
-    let f = path.join("cgroup.subtree_control");
-    for c in controllers {
-        let body = format!("+{}", c);
-        let _rest = fs::write(f.as_path(), body.as_bytes());
+/// `struct file_handle` of a kernfs node, whose payload is the cgroup ID.
+#[repr(C)]
+struct KernfsFileHandle {
+    handle_bytes: u32,
+    handle_type: i32,
+    id: u64,
+}
+
+const FILEID_KERNFS: i32 = 0xfe;
+
+/// Looks up the directory of the control group `id` with `open_by_handle_at(2)`.
+/// Writes `pid` to the file `name` of `mirror`, the directory of a control group in the unified
+/// hierarchy in hybrid mode, after the task was attached in the v1 hierarchies. The mirror is
+/// skipped if it doesn't exist, e.g. if the control group wasn't created by this crate, and
+/// failures are ignored so as not to report an error for a task that was attached.
+fn hybrid_attach(mirror: Option<PathBuf>, name: &str, pid: CgroupPid) {
+    if let Some(dir) = mirror.filter(|dir| dir.is_dir()) {
+        if let Ok(mut file) = DirFd::default().open(&dir, name, true) {
+            let _ = file.write_all(pid.pid.to_string().as_bytes());
+        }
     }
 }
 
+fn path_by_handle(root: &Path, id: u64) -> Option<PathBuf> {
+    let mount = open_dir(root).ok()?;
+    let mut handle = KernfsFileHandle {
+        handle_bytes: std::mem::size_of::<u64>() as u32,
+        handle_type: FILEID_KERNFS,
+        id,
+    };
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_open_by_handle_at,
+            mount.as_raw_fd(),
+            &mut handle as *mut KernfsFileHandle,
+            libc::O_PATH | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return None;
+    }
+
+    let fd = unsafe { std::os::unix::io::OwnedFd::from_raw_fd(fd as i32) };
+    fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd()))
+        .ok()
+        .filter(|p| p.starts_with(root))
+}
+
+/// Walks the hierarchy below `dir` to find the directory with the inode number `ino`.
+fn find_by_ino(dir: &Path, ino: u64) -> Result<Option<PathBuf>> {
+    let meta = fs::symlink_metadata(dir)
+        .map_err(|e| Error::with_cause(ReadFailed(dir.display().to_string()), e))?;
+    if meta.ino() == ino {
+        return Ok(Some(dir.to_path_buf()));
+    }
+
+    for entry in fs::read_dir(dir)
+        .map_err(|e| Error::with_cause(ReadFailed(dir.display().to_string()), e))?
+    {
+        let entry =
+            entry.map_err(|e| Error::with_cause(ReadFailed(dir.display().to_string()), e))?;
+        if !entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+            continue;
+        }
+        // The control group may be removed while walking.
+        match find_by_ino(&entry.path(), ino) {
+            Ok(Some(p)) => return Ok(Some(p)),
+            Ok(None) | Err(_) => continue,
+        }
+    }
+    Ok(None)
+}
+
+fn read_controller_list(path: &Path, file_name: &str) -> Result<Vec<String>> {
+    let p = path.join(file_name);
+    let content = fs::read_to_string(&p)
+        .map_err(|e| Error::with_cause(ReadFailed(p.display().to_string()), e))?;
+    Ok(content.split_whitespace().map(|x| x.to_string()).collect())
+}
+
+fn write_subtree_control(path: &Path, body: &str) -> Result<()> {
+    fs::write(path.join("cgroup.subtree_control"), body.as_bytes()).map_err(|e| {
+        let errno = nix::errno::Errno::from_i32(e.raw_os_error().unwrap_or(0));
+        Error::with_cause(
+            SubtreeControl(path.display().to_string(), body.to_string(), errno),
+            e,
+        )
+    })
+}
+
+/// Enable the `controllers` which are not enabled yet in `cgroup.subtree_control` of `path`.
+fn enable_controllers(controllers: &[String], path: &Path) -> Result<()> {
+    let enabled = read_controller_list(path, "cgroup.subtree_control")?;
+    controllers
+        .iter()
+        .filter(|c| !enabled.contains(c))
+        .try_for_each(|c| write_subtree_control(path, &format!("+{}", c)))
+}
+
+/// Disable the `controllers` which are enabled in `cgroup.subtree_control` of `path`.
+fn disable_controllers(controllers: &[String], path: &Path) -> Result<()> {
+    let enabled = read_controller_list(path, "cgroup.subtree_control")?;
+    controllers
+        .iter()
+        .filter(|c| enabled.contains(c))
+        .try_for_each(|c| write_subtree_control(path, &format!("-{}", c)))
+}
+
 fn supported_controllers() -> Vec<String> {
-    let p = format!("{}/{}", UNIFIED_MOUNTPOINT, "cgroup.controllers");
-    let ret = fs::read_to_string(p.as_str());
-    ret.unwrap_or_default()
-        .split(' ')
-        .map(|x| x.trim().to_string())
-        .collect::<Vec<String>>()
+    read_controller_list(Path::new(UNIFIED_MOUNTPOINT), "cgroup.controllers").unwrap_or_default()
 }
 
 fn create_v2_cgroup(
@@ -541,11 +1492,36 @@ fn create_v2_cgroup(
     } else {
         supported_controllers()
     };
+    create_v2_dirs(root, path, &controllers, specified_controllers.is_some())
+}
+
+/// Creates the directories of `path` below `root`, enabling `controllers` for the children of
+/// each parent. Unless they were `specified`, the ones a parent lacks are skipped there.
+fn create_v2_dirs(
+    root: PathBuf,
+    path: &str,
+    controllers: &[String],
+    specified: bool,
+) -> Result<()> {
+    // The default set is the one of the root, which a delegated or namespaced parent may
+    // partly lack: only the controllers it has are enabled there.
+    let enable = |path: &Path| -> Result<()> {
+        if specified {
+            return enable_controllers(controllers, path);
+        }
+        let available = read_controller_list(path, "cgroup.controllers")?;
+        let controllers = controllers
+            .iter()
+            .filter(|c| available.contains(c))
+            .cloned()
+            .collect::<Vec<_>>();
+        enable_controllers(&controllers, path)
+    };
 
     let mut fp = root;
 
     // enable for root
-    enable_controllers(&controllers, &fp);
+    enable(&fp)?;
 
     // path: "a/b/c"
     let elements = path.split('/').collect::<Vec<&str>>();
@@ -562,7 +1538,7 @@ fn create_v2_cgroup(
 
         if i < last_index {
             // enable controllers for subtree
-            enable_controllers(&controllers, &fp);
+            enable(&fp)?;
         }
     }
 
@@ -599,6 +1575,7 @@ fn get_cgroup_destination(mut mount_root: String, pidpath: String) -> String {
 pub fn existing_path(paths: HashMap<String, String>) -> Result<HashMap<String, String>> {
     let mount_roots_v1 = V1::new();
     let mut mount_roots_subsystems_map = HashMap::new();
+    let mut ns_paths = None;
 
     for s in mount_roots_v1.subsystems().iter() {
         let controller_name = s.controller_name();
@@ -608,7 +1585,18 @@ pub fn existing_path(paths: HashMap<String, String>) -> Result<HashMap<String, S
                 "controller {} found in mountinfo, but not found in cgroup.",
                 controller_name
             ))))?;
-        let path_from_mountinfo = s.to_controller().base().to_string_lossy().to_string();
+        let base = s.to_controller().base();
+        // The hierarchy was mounted outside of our cgroup namespace, above its root.
+        if base
+            .components()
+            .any(|c| c == std::path::Component::ParentDir)
+        {
+            let ns = ns_paths.get_or_insert(NamespacePaths::current()?);
+            let des_path = ns.to_hierarchy_path(&controller_name, path_from_cgroup)?;
+            mount_roots_subsystems_map.insert(controller_name, des_path);
+            continue;
+        }
+        let path_from_mountinfo = base.to_string_lossy().to_string();
 
         let des_path = get_cgroup_destination(path_from_mountinfo, path_from_cgroup.to_owned());
         mount_roots_subsystems_map.insert(controller_name, des_path);
@@ -617,20 +1605,252 @@ pub fn existing_path(paths: HashMap<String, String>) -> Result<HashMap<String, S
 }
 
 fn get_cgroups_relative_paths_by_path(path: String) -> Result<HashMap<String, String>> {
-    let mut m = HashMap::new();
     let content =
         fs::read_to_string(path.clone()).map_err(|e| Error::with_cause(ReadFailed(path), e))?;
+    Ok(parse_proc_cgroup(&content))
+}
+
+/// Parses the content of `/proc/<pid>/cgroup` into a map of the subsystems, e.g. `memory`,
+/// `name=systemd` or `""` for the v2 hierarchy, to the paths of the control groups.
+fn parse_proc_cgroup(content: &str) -> HashMap<String, String> {
+    let mut m = HashMap::new();
     // cgroup path may have ":" , likes
     // "2:cpu,cpuacct:/system.slice/containerd.service/test.slice:cri-containerd:96b37a2edf84351487f42039e137427f1812f678850675fac214caf597ee5e4a"
     for line in content.lines() {
-        if let Some((first_value_part, remaining_path)) =
-            line.split_once(':').unwrap_or_default().1.split_once(':')
-        {
-            let keys: Vec<&str> = first_value_part.split(',').collect();
-            keys.iter().for_each(|key| {
-                m.insert(key.to_string(), remaining_path.to_string());
-            });
+        let mut fields = line.splitn(3, ':');
+        let (id, controllers, path) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(id), Some(controllers), Some(path)) => (id, controllers, path),
+            _ => continue,
+        };
+        if id.parse::<u32>().is_err() || !path.starts_with('/') {
+            continue;
+        }
+        // The kernel appends " (deleted)" to the path of a removed v2 control group that the
+        // process is still in, e.g. a zombie.
+        let path = path.strip_suffix(" (deleted)").unwrap_or(path);
+        for key in controllers.split(',') {
+            m.insert(key.to_string(), path.to_string());
         }
     }
-    Ok(m)
+    m
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    use crate::fs::cgroup::{
+        create_v2_dirs, descendants_post_order, disable_controllers, enable_controllers,
+        find_by_ino, next_backoff, parse_cgroup_events, parse_cgroup_stat, parse_proc_cgroup,
+        CgroupEvents, CgroupStat, CgroupType, DyingDescendantsTracker, MAX_BACKOFF,
+    };
+    use crate::fs::error::ErrorKind;
+
+    #[test]
+    fn test_parse_cgroup_stat() {
+        let ok = parse_cgroup_stat("nr_descendants 3\nnr_dying_descendants 1\n").unwrap();
+        assert_eq!(
+            ok,
+            CgroupStat {
+                nr_descendants: 3,
+                nr_dying_descendants: 1,
+                ..Default::default()
+            }
+        );
+
+        let ok = parse_cgroup_stat(
+            "nr_descendants 2\nnr_dying_descendants 0\nnr_subsys_cpu 3\nnr_subsys_memory 3\nnr_dying_subsys_cpu 0\nnr_dying_subsys_memory 5\n",
+        )
+        .unwrap();
+        assert_eq!(
+            ok.nr_subsys,
+            HashMap::from([("cpu".to_string(), 3), ("memory".to_string(), 3)])
+        );
+        assert_eq!(
+            ok.nr_dying_subsys,
+            HashMap::from([("cpu".to_string(), 0), ("memory".to_string(), 5)])
+        );
+
+        assert!(parse_cgroup_stat("nr_descendants many\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_cgroup_events() {
+        assert_eq!(
+            parse_cgroup_events("populated 1\nfrozen 0\n").unwrap(),
+            CgroupEvents {
+                populated: true,
+                frozen: false
+            }
+        );
+        assert_eq!(
+            parse_cgroup_events("populated 0\n").unwrap(),
+            CgroupEvents::default()
+        );
+        assert!(parse_cgroup_events("populated yes\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_proc_cgroup() {
+        let m = parse_proc_cgroup(
+            "12:cpu,cpuacct:/system.slice/test.slice:cri-containerd:96b3\n\
+             1:name=systemd:/user.slice\n\
+             0::/a/zombie (deleted)\n\
+             garbage\n\
+             x:memory:/\n",
+        );
+        assert_eq!(
+            m,
+            HashMap::from([
+                (
+                    "cpu".to_string(),
+                    "/system.slice/test.slice:cri-containerd:96b3".to_string()
+                ),
+                (
+                    "cpuacct".to_string(),
+                    "/system.slice/test.slice:cri-containerd:96b3".to_string()
+                ),
+                ("name=systemd".to_string(), "/user.slice".to_string()),
+                (String::new(), "/a/zombie".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_dying_descendants_tracker() {
+        let mut tracker = DyingDescendantsTracker::new();
+        let start = Instant::now();
+
+        let growth = tracker.record(start, 10);
+        assert_eq!(growth.delta, 0);
+        assert_eq!(growth.total_delta, 0);
+        assert_eq!(growth.rate, 0.0);
+
+        let growth = tracker.record(start + Duration::from_secs(2), 14);
+        assert_eq!(growth.delta, 4);
+        assert_eq!(growth.total_delta, 4);
+        assert_eq!(growth.rate, 2.0);
+
+        let growth = tracker.record(start + Duration::from_secs(4), 12);
+        assert_eq!(growth.current, 12);
+        assert_eq!(growth.delta, -2);
+        assert_eq!(growth.total_delta, 2);
+        assert_eq!(growth.elapsed, Duration::from_secs(4));
+        assert_eq!(growth.rate, 0.5);
+    }
+
+    #[test]
+    fn test_subtree_control_writes() {
+        let dir = std::env::temp_dir().join(format!("cgroups-rs-subtree-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("cgroup.subtree_control");
+        let controllers = vec!["cpu".to_string(), "io".to_string()];
+
+        // Only the missing controllers are written.
+        std::fs::write(&file, "cpu memory\n").unwrap();
+        enable_controllers(&controllers, &dir).unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "+io");
+
+        std::fs::write(&file, "cpu memory\n").unwrap();
+        disable_controllers(&controllers, &dir).unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "-cpu");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        let err = enable_controllers(&controllers, &dir).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ReadFailed(p) if p.contains("cgroups-rs-subtree")));
+    }
+
+    #[test]
+    fn test_create_v2_dirs() {
+        let root = std::env::temp_dir().join(format!("cgroups-rs-create-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("a")).unwrap();
+        for (dir, available) in [(root.clone(), "cpu misc"), (root.join("a"), "cpu")] {
+            std::fs::write(dir.join("cgroup.controllers"), available).unwrap();
+            std::fs::write(dir.join("cgroup.subtree_control"), "").unwrap();
+        }
+        let controllers = vec!["cpu".to_string(), "misc".to_string()];
+
+        // The default controllers a parent lacks are skipped.
+        create_v2_dirs(root.clone(), "a/b", &controllers, false).unwrap();
+        assert!(root.join("a/b").is_dir());
+        assert_eq!(
+            std::fs::read_to_string(root.join("a/cgroup.subtree_control")).unwrap(),
+            "+cpu"
+        );
+
+        // The specified ones are not.
+        std::fs::remove_file(root.join("a/cgroup.controllers")).unwrap();
+        std::fs::remove_dir(root.join("a/b")).unwrap();
+        std::fs::write(root.join("a/cgroup.subtree_control"), "cpu").unwrap();
+        create_v2_dirs(root.clone(), "a/b", &controllers, true).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("a/cgroup.subtree_control")).unwrap(),
+            "+misc"
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_cgroup_type() {
+        for t in [
+            CgroupType::Domain,
+            CgroupType::DomainThreaded,
+            CgroupType::DomainInvalid,
+            CgroupType::Threaded,
+        ] {
+            assert_eq!(t.to_string().parse::<CgroupType>().unwrap(), t);
+        }
+        assert_eq!(
+            "domain threaded\n".parse::<CgroupType>().unwrap(),
+            CgroupType::DomainThreaded
+        );
+        assert!("threads".parse::<CgroupType>().is_err());
+    }
+
+    #[test]
+    fn test_descendants_post_order() {
+        let top = std::env::temp_dir().join(format!("cgroups-rs-delete-{}", std::process::id()));
+        std::fs::create_dir_all(top.join("a/b")).unwrap();
+        std::fs::create_dir_all(top.join("c")).unwrap();
+        std::fs::write(top.join("a/cgroup.procs"), "").unwrap();
+
+        let dirs = descendants_post_order(&top).unwrap();
+        assert_eq!(dirs.len(), 4);
+        assert_eq!(dirs.last(), Some(&top));
+        let pos = |p: &str| dirs.iter().position(|d| *d == top.join(p)).unwrap();
+        assert!(pos("a/b") < pos("a"));
+
+        std::fs::remove_dir_all(&top).unwrap();
+    }
+
+    #[test]
+    fn test_next_backoff() {
+        assert_eq!(
+            next_backoff(Duration::from_millis(10)),
+            Duration::from_millis(20)
+        );
+        assert_eq!(next_backoff(Duration::from_millis(800)), MAX_BACKOFF);
+        // A longer initial backoff is kept, and doubling never overflows.
+        assert_eq!(next_backoff(Duration::from_secs(5)), Duration::from_secs(5));
+        assert_eq!(next_backoff(Duration::MAX), Duration::MAX);
+    }
+
+    #[test]
+    fn test_find_by_ino() {
+        use std::os::unix::fs::MetadataExt;
+
+        let top = std::env::temp_dir().join(format!("cgroups-rs-ino-{}", std::process::id()));
+        std::fs::create_dir_all(top.join("a/b")).unwrap();
+        std::fs::create_dir_all(top.join("c")).unwrap();
+
+        let ino = std::fs::metadata(top.join("a/b")).unwrap().ino();
+        assert_eq!(find_by_ino(&top, ino).unwrap(), Some(top.join("a/b")));
+        let ino = std::fs::metadata(&top).unwrap().ino();
+        assert_eq!(find_by_ino(&top, ino).unwrap(), Some(top.clone()));
+        assert_eq!(find_by_ino(&top.join("c"), ino).unwrap(), None);
+
+        std::fs::remove_dir_all(&top).unwrap();
+    }
 }
@@ -60,9 +60,11 @@
 //!      .build(h).unwrap();
 //! ```
 
+use crate::fs::blkio::IoPrioClass;
 use crate::fs::{
-    BlkIoDeviceResource, BlkIoDeviceThrottleResource, Cgroup, DeviceResource, Error, Hierarchy,
-    HugePageResource, MaxValue, NetworkPriority, Resources,
+    BlkIoDeviceLatencyResource, BlkIoDeviceResource, BlkIoDeviceThrottleResource,
+    BlkIoDeviceWeightResource, Cgroup, DeviceResource, Error, Hierarchy, HugePageResource,
+    MaxValue, MiscResource, NetworkPriority, RdmaResource, Resources,
 };
 
 macro_rules! gen_setter {
@@ -82,6 +84,8 @@ pub struct CgroupBuilder {
     resources: Resources,
     /// List of controllers specifically enabled in the control group.
     specified_controllers: Option<Vec<String>>,
+    /// Whether the control group is made a threaded one.
+    threaded: bool,
 }
 
 impl CgroupBuilder {
@@ -93,6 +97,7 @@ impl CgroupBuilder {
             name: name.to_owned(),
             resources: Resources::default(),
             specified_controllers: None,
+            threaded: false,
         }
     }
 
@@ -136,17 +141,44 @@ impl CgroupBuilder {
         }
     }
 
+    /// Builds the miscellaneous scalar resources (e.g. SEV ASIDs) available to the control group.
+    pub fn misc(self) -> MiscResourceBuilder {
+        MiscResourceBuilder { cgroup: self }
+    }
+
+    /// Builds the RDMA/IB resources available to the control group.
+    pub fn rdma(self) -> RdmaResourceBuilder {
+        RdmaResourceBuilder { cgroup: self }
+    }
+
+    /// Builds the limits on the sub-hierarchy of the control group.
+    pub fn hierarchy(self) -> HierarchyResourceBuilder {
+        HierarchyResourceBuilder { cgroup: self }
+    }
+
     /// Finalize the control group, consuming the builder and creating the control group.
     pub fn build(self, hier: Box<dyn Hierarchy>) -> Result<Cgroup, Error> {
-        if let Some(controllers) = self.specified_controllers {
-            let cg = Cgroup::new_with_specified_controllers(hier, self.name, Some(controllers))?;
-            cg.apply(&self.resources)?;
-            Ok(cg)
+        let cg = if let Some(controllers) = self.specified_controllers {
+            Cgroup::new_with_specified_controllers(hier, self.name, Some(controllers))?
         } else {
-            let cg = Cgroup::new(hier, self.name)?;
-            cg.apply(&self.resources)?;
-            Ok(cg)
+            Cgroup::new(hier, self.name)?
+        };
+        if self.threaded {
+            cg.set_threaded()?;
         }
+        cg.apply(&self.resources)?;
+        Ok(cg)
+    }
+
+    /// Make the control group a threaded one, so that threads of a process can be placed in it
+    /// individually. Its parent becomes the root of a threaded subtree. Only supported for v2
+    /// cgroups.
+    ///
+    /// Only threaded controllers (e.g. `cpu`, `cpuset`, `pids`) can be configured in a threaded
+    /// control group, consider using `set_specified_controllers()`.
+    pub fn threaded(mut self) -> Self {
+        self.threaded = true;
+        self
     }
 
     /// Specifically enable some controllers in the control group.
@@ -219,6 +251,27 @@ impl PidResourceBuilder {
     }
 }
 
+/// A builder that configures the limits on the sub-hierarchy of a control group.
+pub struct HierarchyResourceBuilder {
+    cgroup: CgroupBuilder,
+}
+
+impl HierarchyResourceBuilder {
+    gen_setter!(hierarchy, Cgroup, set_max_depth, max_depth, MaxValue);
+    gen_setter!(
+        hierarchy,
+        Cgroup,
+        set_max_descendants,
+        max_descendants,
+        MaxValue
+    );
+
+    /// Finish the construction of the hierarchy limits of a control group.
+    pub fn done(self) -> CgroupBuilder {
+        self.cgroup
+    }
+}
+
 /// A builder that configures the cpuset & cpu controllers of a control group.
 pub struct CpuResourceBuilder {
     cgroup: CgroupBuilder,
@@ -311,12 +364,72 @@ impl HugepagesResourceBuilder {
         self
     }
 
+    /// Limit the reservation of certain hugepages (determined by `size`) to be at most `limit`
+    /// bytes.
+    pub fn rsvd_limit(mut self, size: String, limit: u64) -> HugepagesResourceBuilder {
+        self.cgroup
+            .resources
+            .hugepages
+            .rsvd_limits
+            .push(HugePageResource { size, limit });
+        self
+    }
+
     /// Finish the construction of the network resources of a control group.
     pub fn done(self) -> CgroupBuilder {
         self.cgroup
     }
 }
 
+/// A builder that configures the misc controller of a control group.
+pub struct MiscResourceBuilder {
+    cgroup: CgroupBuilder,
+}
+
+impl MiscResourceBuilder {
+    /// Limit the usage of the resource `name` (e.g. `sev`) to be at most `max`.
+    pub fn max(mut self, name: String, max: MaxValue) -> MiscResourceBuilder {
+        self.cgroup
+            .resources
+            .misc
+            .limits
+            .push(MiscResource { name, max });
+        self
+    }
+
+    /// Finish the construction of the misc resources of a control group.
+    pub fn done(self) -> CgroupBuilder {
+        self.cgroup
+    }
+}
+
+/// A builder that configures the rdma controller of a control group.
+pub struct RdmaResourceBuilder {
+    cgroup: CgroupBuilder,
+}
+
+impl RdmaResourceBuilder {
+    /// Limit the number of HCA handles and objects of the device `device` (e.g. `mlx4_0`).
+    pub fn limit(
+        mut self,
+        device: String,
+        hca_handle: Option<MaxValue>,
+        hca_object: Option<MaxValue>,
+    ) -> RdmaResourceBuilder {
+        self.cgroup.resources.rdma.limits.push(RdmaResource {
+            device,
+            hca_handle,
+            hca_object,
+        });
+        self
+    }
+
+    /// Finish the construction of the rdma resources of a control group.
+    pub fn done(self) -> CgroupBuilder {
+        self.cgroup
+    }
+}
+
 /// A builder that configures the blkio controller of a control group.
 pub struct BlkIoResourcesBuilder {
     cgroup: CgroupBuilder,
@@ -326,6 +439,14 @@ pub struct BlkIoResourcesBuilder {
 impl BlkIoResourcesBuilder {
     gen_setter!(blkio, BlkIoController, set_weight, weight, u16);
     gen_setter!(blkio, BlkIoController, set_leaf_weight, leaf_weight, u16);
+    gen_setter!(blkio, BlkIoController, set_io_weight, io_weight, u16);
+    gen_setter!(
+        blkio,
+        BlkIoController,
+        set_prio_class,
+        prio_class,
+        IoPrioClass
+    );
 
     /// Set the weight of a certain device.
     pub fn weight_device(
@@ -348,6 +469,39 @@ impl BlkIoResourcesBuilder {
         self
     }
 
+    /// Set the `io.weight` of a certain device (cgroup v2 only).
+    pub fn io_weight_device(
+        mut self,
+        major: u64,
+        minor: u64,
+        weight: u16,
+    ) -> BlkIoResourcesBuilder {
+        self.cgroup
+            .resources
+            .blkio
+            .io_weight_device
+            .push(BlkIoDeviceWeightResource {
+                major,
+                minor,
+                weight,
+            });
+        self
+    }
+
+    /// Set the `io.latency` target of a certain device, in microseconds (cgroup v2 only).
+    pub fn latency_target(mut self, major: u64, minor: u64, target: u64) -> BlkIoResourcesBuilder {
+        self.cgroup
+            .resources
+            .blkio
+            .latency_device
+            .push(BlkIoDeviceLatencyResource {
+                major,
+                minor,
+                target,
+            });
+        self
+    }
+
     /// Start configuring the I/O operations per second metric.
     pub fn throttle_iops(mut self) -> BlkIoResourcesBuilder {
         self.throttling_iops = true;
@@ -0,0 +1,615 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+//! This module contains an extension of `std::process::Command` to spawn processes directly
+//! into a control group.
+//!
+//! Spawning a child and then attaching it with `Cgroup::add_task_by_tgid()` lets the child run in
+//! the control group of its parent for a while, and anything it forks meanwhile escapes. Instead,
+//! the child moves itself into the control group between `fork()` and `exec()`. For simple
+//! commands on cgroup v2 and kernels 5.7+, `CloneCommand` creates the child in the control group
+//! with `clone3(CLONE_INTO_CGROUP)`.
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+
+use crate::fs::dirfd::{open_dir, DirFd};
+use crate::fs::error::ErrorKind::*;
+use crate::fs::error::*;
+use crate::fs::Cgroup;
+use crate::{CgroupPid, Pidfd};
+
+const CLONE_PIDFD: u64 = 0x1000;
+const CLONE_INTO_CGROUP: u64 = 0x200000000;
+
+/// `struct clone_args` of `clone3(2)`, up to `cgroup` (CLONE_ARGS_SIZE_VER2).
+#[repr(C)]
+#[derive(Default)]
+struct CloneArgs {
+    flags: u64,
+    pidfd: u64,
+    child_tid: u64,
+    parent_tid: u64,
+    exit_signal: u64,
+    stack: u64,
+    stack_size: u64,
+    tls: u64,
+    set_tid: u64,
+    set_tid_size: u64,
+    cgroup: u64,
+}
+
+/// A child process spawned by `CgroupCommandExt::spawn_into_cgroup()` or
+/// `CloneCommand::spawn_into_cgroup()`.
+#[derive(Debug)]
+pub enum CgroupChild {
+    /// Spawned with `clone3(CLONE_INTO_CGROUP)`.
+    Cloned(ClonedChild),
+    /// Spawned by `Command::spawn()`, after moving itself into the control group.
+    Spawned(Child),
+}
+
+impl CgroupChild {
+    /// The process identifier of the child.
+    pub fn id(&self) -> u32 {
+        match self {
+            CgroupChild::Cloned(child) => child.id(),
+            CgroupChild::Spawned(child) => child.id(),
+        }
+    }
+
+    /// Sends `SIGKILL` to the child. Does nothing if it was already reaped.
+    pub fn kill(&mut self) -> io::Result<()> {
+        match self {
+            CgroupChild::Cloned(child) => child.kill(),
+            CgroupChild::Spawned(child) => child.kill(),
+        }
+    }
+
+    /// Waits for the child to exit.
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        match self {
+            CgroupChild::Cloned(child) => child.wait(),
+            CgroupChild::Spawned(child) => child.wait(),
+        }
+    }
+
+    /// Returns the exit status of the child if it exited, without blocking.
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        match self {
+            CgroupChild::Cloned(child) => child.try_wait(),
+            CgroupChild::Spawned(child) => child.try_wait(),
+        }
+    }
+}
+
+/// A child process spawned with `clone3(CLONE_INTO_CGROUP)`.
+///
+/// Like `std::process::Child`, it keeps the exit status once the child is reaped, since its pid
+/// may be recycled from then on. Signals are sent through a pidfd of the child.
+#[derive(Debug)]
+pub struct ClonedChild {
+    pidfd: Pidfd,
+    status: Option<ExitStatus>,
+}
+
+impl ClonedChild {
+    /// The process identifier of the child.
+    pub fn id(&self) -> u32 {
+        self.pidfd.pid().pid as u32
+    }
+
+    /// The pidfd of the child.
+    pub fn pidfd(&self) -> &Pidfd {
+        &self.pidfd
+    }
+
+    /// Sends `SIGKILL` to the child. Does nothing if it was already reaped.
+    pub fn kill(&mut self) -> io::Result<()> {
+        if self.status.is_some() {
+            return Ok(());
+        }
+        self.pidfd.send_signal(libc::SIGKILL)
+    }
+
+    /// Waits for the child to exit.
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        if let Some(status) = self.status {
+            return Ok(status);
+        }
+        let status = wait_pid(self.id(), 0)?.unwrap();
+        self.status = Some(status);
+        Ok(status)
+    }
+
+    /// Returns the exit status of the child if it exited, without blocking.
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        if self.status.is_none() {
+            self.status = wait_pid(self.id(), libc::WNOHANG)?;
+        }
+        Ok(self.status)
+    }
+}
+
+fn wait_pid(pid: u32, options: libc::c_int) -> io::Result<Option<ExitStatus>> {
+    let mut status = 0;
+    loop {
+        match unsafe { libc::waitpid(pid as libc::pid_t, &mut status, options) } {
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            -1 => return Err(io::Error::last_os_error()),
+            0 => return Ok(None),
+            _ => return Ok(Some(ExitStatus::from_raw(status))),
+        }
+    }
+}
+
+/// Extends `std::process::Command` to spawn processes directly into a control group.
+///
+/// ## Example:
+///
+/// ```text
+/// let mut child = Command::new("sleep").arg("10").spawn_into_cgroup(&cg)?;
+/// child.wait()?;
+/// ```
+pub trait CgroupCommandExt {
+    /// Makes the child move itself into `cg` right before executing the program, by writing to
+    /// `cgroup.procs` of each of its controllers. The program never runs outside of `cg`.
+    fn cgroup(&mut self, cg: &Cgroup) -> io::Result<&mut Command>;
+
+    /// Like `cgroup()`, then makes the child unshare its cgroup namespace, so that `cg` is the
+    /// root of the hierarchies it sees, e.g. in `/proc/self/cgroup` or in a cgroupfs it mounts.
+    /// Requires `CAP_SYS_ADMIN` and kernel 4.6+.
+    fn cgroup_ns(&mut self, cg: &Cgroup) -> io::Result<&mut Command>;
+
+    /// Spawns the command with `cgroup()` and `Command::spawn()`, honoring all the settings of
+    /// the command.
+    fn spawn_into_cgroup(&mut self, cg: &Cgroup) -> io::Result<CgroupChild>;
+}
+
+impl CgroupCommandExt for Command {
+    fn cgroup(&mut self, cg: &Cgroup) -> io::Result<&mut Command> {
+        let files = cgroup_dirs(cg)?
+            .iter()
+            .map(|(dir, fd)| fd.open(dir, "cgroup.procs", true).map_err(io::Error::other))
+            .collect::<io::Result<Vec<File>>>()?;
+        // In hybrid mode, the child joins the mirror in the unified hierarchy as well, best effort
+        // like `Cgroup::add_task_by_tgid()`.
+        let mirror = cg
+            .hybrid_dir()
+            .map_err(io::Error::other)?
+            .filter(|dir| dir.is_dir())
+            .and_then(|dir| DirFd::default().open(&dir, "cgroup.procs", true).ok());
+
+        let hook = move || {
+            // "0" stands for the writing process, no need to format the pid here.
+            for f in &files {
+                if unsafe { libc::write(f.as_raw_fd(), b"0".as_ptr() as *const libc::c_void, 1) }
+                    != 1
+                {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(f) = &mirror {
+                unsafe { libc::write(f.as_raw_fd(), b"0".as_ptr() as *const libc::c_void, 1) };
+            }
+            Ok(())
+        };
+        // Safety: the hook only calls write(2), which is async-signal-safe.
+        Ok(unsafe { self.pre_exec(hook) })
+    }
+
+    fn cgroup_ns(&mut self, cg: &Cgroup) -> io::Result<&mut Command> {
+        self.cgroup(cg)?;
+        let hook = || {
+            if unsafe { libc::unshare(libc::CLONE_NEWCGROUP) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        };
+        // Safety: the hook only calls unshare(2), which doesn't allocate or take locks. It runs
+        // after the hook of `cgroup()`, so the namespace is rooted at `cg`.
+        Ok(unsafe { self.pre_exec(hook) })
+    }
+
+    fn spawn_into_cgroup(&mut self, cg: &Cgroup) -> io::Result<CgroupChild> {
+        self.cgroup(cg)?.spawn().map(CgroupChild::Spawned)
+    }
+}
+
+/// A command whose process is created in a control group by `clone3(CLONE_INTO_CGROUP)`, so that
+/// not even the child runs outside of it.
+///
+/// Unlike `std::process::Command`, it only carries what the child honors: the program, its
+/// arguments, environment variables and working directory. The standard streams and the
+/// credentials are inherited, use `CgroupCommandExt` for anything else.
+///
+/// ## Example:
+///
+/// ```text
+/// let mut child = CloneCommand::new("sleep").arg("10").spawn_into_cgroup(&cg)?;
+/// child.wait()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct CloneCommand {
+    program: OsString,
+    args: Vec<OsString>,
+    envs: Vec<(OsString, Option<OsString>)>,
+    cwd: Option<PathBuf>,
+}
+
+impl CloneCommand {
+    /// A command running `program`, looked up in `PATH` if it has no `/`.
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Self {
+        CloneCommand {
+            program: program.as_ref().to_owned(),
+            args: vec![],
+            envs: vec![],
+            cwd: None,
+        }
+    }
+
+    /// Adds an argument.
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Adds several arguments.
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        args.into_iter().for_each(|arg| {
+            self.arg(arg);
+        });
+        self
+    }
+
+    /// Sets an environment variable, on top of the ones of the current process.
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, val: V) -> &mut Self {
+        self.envs
+            .push((key.as_ref().to_owned(), Some(val.as_ref().to_owned())));
+        self
+    }
+
+    /// Removes an environment variable of the current process.
+    pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Self {
+        self.envs.push((key.as_ref().to_owned(), None));
+        self
+    }
+
+    /// Sets the working directory of the child.
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.cwd = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// The same command as a `std::process::Command`.
+    pub fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args);
+        for (key, val) in &self.envs {
+            match val {
+                Some(val) => cmd.env(key, val),
+                None => cmd.env_remove(key),
+            };
+        }
+        if let Some(cwd) = &self.cwd {
+            cmd.current_dir(cwd);
+        }
+        cmd
+    }
+
+    /// Spawns the command in `cg`.
+    ///
+    /// Falls back to `CgroupCommandExt::spawn_into_cgroup()` on v1, or where `clone3` or
+    /// `CLONE_INTO_CGROUP` isn't available. A failure to execute the program is returned as is.
+    pub fn spawn_into_cgroup(&self, cg: &Cgroup) -> io::Result<CgroupChild> {
+        if cg.v2() {
+            let dirs = cgroup_dirs(cg)?;
+            match clone3_into_cgroup(self, &dirs[0].0) {
+                Ok(child) => return Ok(CgroupChild::Cloned(child)),
+                Err(CloneError::Exec(e)) => return Err(e),
+                Err(CloneError::Clone(e))
+                    if matches!(
+                        e.raw_os_error(),
+                        Some(libc::ENOSYS)
+                            | Some(libc::E2BIG)
+                            | Some(libc::EINVAL)
+                            | Some(libc::EPERM)
+                    ) => {}
+                Err(CloneError::Clone(e)) => return Err(e),
+            }
+        }
+
+        self.command().spawn_into_cgroup(cg)
+    }
+}
+
+/// The directories of the control group, one per hierarchy, with the file descriptors of their
+/// controllers.
+fn cgroup_dirs(cg: &Cgroup) -> io::Result<Vec<(PathBuf, &DirFd)>> {
+    let mut dirs = cg
+        .subsystems()
+        .iter()
+        .map(|sub| (sub.to_controller().path().to_path_buf(), sub.get_dir()))
+        .collect::<Vec<_>>();
+    dirs.sort_by(|a, b| a.0.cmp(&b.0));
+    dirs.dedup_by(|a, b| a.0 == b.0);
+    if dirs.is_empty() {
+        return Err(io::Error::other(Error::new(SubsystemsEmpty)));
+    }
+    Ok(dirs)
+}
+
+fn cstring(s: &OsStr) -> io::Result<CString> {
+    CString::new(s.as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// The paths `execve` is tried with, following the `PATH` of the child like `execvp`.
+fn exec_candidates(program: &OsStr, path: Option<&OsStr>) -> Vec<PathBuf> {
+    if program.as_bytes().contains(&b'/') {
+        return vec![PathBuf::from(program)];
+    }
+    let path = path
+        .map(OsString::from)
+        .unwrap_or_else(|| "/bin:/usr/bin".into());
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .collect()
+}
+
+/// Why `clone3_into_cgroup()` failed.
+#[derive(Debug)]
+enum CloneError {
+    /// No child was created, e.g. `clone3` isn't available.
+    Clone(io::Error),
+    /// The child was created, but failed to execute the program. It was reaped.
+    Exec(io::Error),
+}
+
+impl From<io::Error> for CloneError {
+    fn from(e: io::Error) -> Self {
+        CloneError::Clone(e)
+    }
+}
+
+fn clone3_into_cgroup(
+    cmd: &CloneCommand,
+    dir: &Path,
+) -> std::result::Result<ClonedChild, CloneError> {
+    // Everything the child needs is prepared here, since it may only call async-signal-safe
+    // functions.
+    let mut env = std::env::vars_os().collect::<Vec<_>>();
+    for (key, value) in &cmd.envs {
+        env.retain(|(k, _)| k != key);
+        if let Some(value) = value {
+            env.push((key.to_owned(), value.to_owned()));
+        }
+    }
+    let candidates = exec_candidates(
+        &cmd.program,
+        env.iter()
+            .find(|(k, _)| k == "PATH")
+            .map(|(_, v)| v.as_os_str()),
+    )
+    .iter()
+    .map(|p| cstring(p.as_os_str()))
+    .collect::<io::Result<Vec<_>>>()?;
+    let args = std::iter::once(&cmd.program)
+        .chain(&cmd.args)
+        .map(|a| cstring(a))
+        .collect::<io::Result<Vec<_>>>()?;
+    let env = env
+        .iter()
+        .map(|(k, v)| {
+            let mut kv = k.clone();
+            kv.push("=");
+            kv.push(v);
+            cstring(&kv)
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let cwd = cmd
+        .cwd
+        .as_ref()
+        .map(|d| cstring(d.as_os_str()))
+        .transpose()?;
+    let mut empty_mask: libc::sigset_t = unsafe { std::mem::zeroed() };
+    unsafe { libc::sigemptyset(&mut empty_mask) };
+
+    let mut argv = args.iter().map(|a| a.as_ptr()).collect::<Vec<_>>();
+    argv.push(std::ptr::null());
+    let mut envp = env.iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
+    envp.push(std::ptr::null());
+
+    let cgroup_fd = open_dir(dir).map_err(io::Error::other)?;
+    // The child reports the errno of a failed exec through the pipe.
+    let mut fds: [RawFd; 2] = [-1; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let (mut reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+    let mut pidfd: libc::c_int = -1;
+    let mut args = CloneArgs {
+        flags: CLONE_INTO_CGROUP | CLONE_PIDFD,
+        pidfd: &mut pidfd as *mut libc::c_int as u64,
+        exit_signal: libc::SIGCHLD as u64,
+        cgroup: cgroup_fd.as_raw_fd() as u64,
+        ..Default::default()
+    };
+    let pid = unsafe {
+        libc::syscall(
+            libc::SYS_clone3,
+            &mut args as *mut CloneArgs,
+            std::mem::size_of::<CloneArgs>(),
+        )
+    };
+    if pid < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    if pid == 0 {
+        // In the child.
+        unsafe {
+            // Restore the signal state of a new process, like `Command`: Rust ignores SIGPIPE.
+            libc::pthread_sigmask(libc::SIG_SETMASK, &empty_mask, std::ptr::null_mut());
+            libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+
+            let mut err = 0;
+            if cwd
+                .as_ref()
+                .map(|d| libc::chdir(d.as_ptr()) == 0)
+                .unwrap_or(true)
+            {
+                for c in &candidates {
+                    libc::execve(c.as_ptr(), argv.as_ptr(), envp.as_ptr());
+                    let e = io::Error::last_os_error().raw_os_error().unwrap_or(0);
+                    // Keep the most meaningful error, like execvp.
+                    if err == 0 || e != libc::ENOENT {
+                        err = e;
+                    }
+                }
+            } else {
+                err = io::Error::last_os_error().raw_os_error().unwrap_or(0);
+            }
+            let bytes = err.to_ne_bytes();
+            libc::write(
+                writer.as_raw_fd(),
+                bytes.as_ptr() as *const libc::c_void,
+                bytes.len(),
+            );
+            libc::_exit(127);
+        }
+    }
+
+    drop(writer);
+    let mut child = ClonedChild {
+        pidfd: Pidfd::from_fd(CgroupPid::from(pid as u64), unsafe {
+            File::from_raw_fd(pidfd)
+        }),
+        status: None,
+    };
+    // The pipe is closed on a successful exec, and receives the errno of a failed one.
+    let mut bytes = [0u8; 4];
+    let mut len = 0;
+    let err = loop {
+        match reader.read(&mut bytes[len..]) {
+            Ok(0) if len == 0 => return Ok(child),
+            Ok(0) => {
+                break io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "short read of the exec error of the child",
+                )
+            }
+            Ok(n) => {
+                len += n;
+                if len == bytes.len() {
+                    break io::Error::from_raw_os_error(i32::from_ne_bytes(bytes));
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                // Whether the child executed the program is unknown.
+                let _ = child.kill();
+                break e;
+            }
+        }
+    };
+    let _ = child.wait();
+    Err(CloneError::Exec(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::fs::command::{clone3_into_cgroup, exec_candidates, CloneCommand, CloneError};
+    use crate::fs::hierarchies;
+
+    #[test]
+    fn test_exec_candidates() {
+        assert_eq!(
+            exec_candidates(OsStr::new("./run"), Some(OsStr::new("/bin"))),
+            vec![PathBuf::from("./run")]
+        );
+        assert_eq!(
+            exec_candidates(OsStr::new("sleep"), Some(OsStr::new("/usr/bin:/bin"))),
+            vec![PathBuf::from("/usr/bin/sleep"), PathBuf::from("/bin/sleep")]
+        );
+        assert_eq!(
+            exec_candidates(OsStr::new("sleep"), None),
+            vec![PathBuf::from("/bin/sleep"), PathBuf::from("/usr/bin/sleep")]
+        );
+    }
+
+    #[test]
+    fn test_clone3_into_cgroup() {
+        let root = match hierarchies::auto().unified_root() {
+            Some(root) => root,
+            None => return,
+        };
+        let dir = root.join("test_clone3_into_cgroup");
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut child = match clone3_into_cgroup(CloneCommand::new("sleep").arg("infinity"), &dir) {
+            Ok(child) => child,
+            // No clone3 or CLONE_INTO_CGROUP.
+            Err(CloneError::Clone(_)) => {
+                fs::remove_dir(&dir).unwrap();
+                return;
+            }
+            Err(CloneError::Exec(e)) => panic!("{}", e),
+        };
+        let procs = fs::read_to_string(dir.join("cgroup.procs")).unwrap();
+        assert_eq!(procs.trim(), child.id().to_string());
+        assert!(child.try_wait().unwrap().is_none());
+        assert_eq!(child.pidfd().pid().pid, child.id() as u64);
+        child.pidfd().check().unwrap();
+        child.kill().unwrap();
+        let status = child.wait().unwrap();
+        // The status is kept once reaped, and the recyclable pid isn't signaled anymore.
+        assert_eq!(child.wait().unwrap(), status);
+        assert_eq!(child.try_wait().unwrap(), Some(status));
+        child.kill().unwrap();
+
+        match clone3_into_cgroup(&CloneCommand::new("/nonexistent"), &dir) {
+            Err(CloneError::Exec(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+            ret => panic!("unexpected {:?}", ret),
+        }
+        // An exec failure with an errno clone3 could fail with is still an exec failure.
+        match clone3_into_cgroup(&CloneCommand::new("/proc/self/cgroup"), &dir) {
+            Err(CloneError::Exec(e)) => assert_eq!(e.raw_os_error(), Some(libc::EACCES)),
+            ret => panic!("unexpected {:?}", ret),
+        }
+
+        // SIGPIPE, ignored by Rust, is restored.
+        let out = std::env::temp_dir().join(format!("cgroups-rs-clone3-{}", std::process::id()));
+        let mut child = clone3_into_cgroup(
+            CloneCommand::new("sh")
+                .arg("-c")
+                .arg("grep ^SigIgn: /proc/self/status > \"$OUT\"")
+                .env("OUT", &out),
+            &dir,
+        )
+        .unwrap();
+        assert!(child.wait().unwrap().success());
+        let ignored = fs::read_to_string(&out).unwrap();
+        let ignored =
+            u64::from_str_radix(ignored.trim_start_matches("SigIgn:").trim(), 16).unwrap();
+        assert_eq!(ignored & (1 << (libc::SIGPIPE - 1)), 0);
+        fs::remove_file(&out).unwrap();
+
+        fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -9,10 +9,12 @@
 //! See the Kernel's documentation for more information about this subsystem, found at:
 //!  [Documentation/scheduler/sched-design-CFS.txt](https://www.kernel.org/doc/Documentation/scheduler/sched-design-CFS.txt)
 //!  paragraph 7 ("GROUP SCHEDULER EXTENSIONS TO CFS").
+use std::convert::TryFrom;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::PathBuf;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::ErrorKind::*;
 use crate::fs::error::*;
 use crate::fs::{parse_max_value, read_i64_from, read_u64_from};
@@ -31,6 +33,7 @@ use crate::fs::{
 pub struct CpuController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
     v2: bool,
 }
 
@@ -67,6 +70,10 @@ impl ControllerInternal for CpuController {
         &self.base
     }
 
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
     fn is_v2(&self) -> bool {
         self.v2
     }
@@ -95,17 +102,15 @@ impl ControllIdentifier for CpuController {
     }
 }
 
-impl<'a> From<&'a Subsystem> for &'a CpuController {
-    fn from(sub: &'a Subsystem) -> &'a CpuController {
-        unsafe {
-            match sub {
-                Subsystem::Cpu(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a CpuController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a CpuController> {
+        match sub {
+            Subsystem::Cpu(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
@@ -116,6 +121,7 @@ impl CpuController {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
             v2,
         }
     }
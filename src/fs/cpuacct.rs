@@ -7,9 +7,11 @@
 //!
 //! See the Kernel's documentation for more information about this subsystem, found at:
 //!  [Documentation/cgroup-v1/cpuacct.txt](https://www.kernel.org/doc/Documentation/cgroup-v1/cpuacct.txt)
+use std::convert::TryFrom;
 use std::io::Write;
 use std::path::PathBuf;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::ErrorKind::*;
 use crate::fs::error::*;
 
@@ -24,6 +26,7 @@ use crate::fs::{ControllIdentifier, ControllerInternal, Controllers, Resources,
 pub struct CpuAcctController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
 }
 
 /// Represents the statistics retrieved from the control group.
@@ -72,6 +75,10 @@ impl ControllerInternal for CpuAcctController {
         &self.base
     }
 
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
     fn apply(&self, _res: &Resources) -> Result<()> {
         Ok(())
     }
@@ -83,17 +90,15 @@ impl ControllIdentifier for CpuAcctController {
     }
 }
 
-impl<'a> From<&'a Subsystem> for &'a CpuAcctController {
-    fn from(sub: &'a Subsystem) -> &'a CpuAcctController {
-        unsafe {
-            match sub {
-                Subsystem::CpuAcct(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a CpuAcctController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a CpuAcctController> {
+        match sub {
+            Subsystem::CpuAcct(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
@@ -104,6 +109,7 @@ impl CpuAcctController {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
         }
     }
 
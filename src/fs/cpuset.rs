@@ -10,9 +10,11 @@
 //!  [Documentation/cgroup-v1/cpusets.txt](https://www.kernel.org/doc/Documentation/cgroup-v1/cpusets.txt)
 
 use log::*;
+use std::convert::TryFrom;
 use std::io::Write;
 use std::path::PathBuf;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::ErrorKind::*;
 use crate::fs::error::*;
 
@@ -29,6 +31,7 @@ use crate::fs::{
 pub struct CpuSetController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
     v2: bool,
 }
 
@@ -103,6 +106,10 @@ impl ControllerInternal for CpuSetController {
         &self.base
     }
 
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
     fn is_v2(&self) -> bool {
         self.v2
     }
@@ -200,17 +207,15 @@ impl ControllIdentifier for CpuSetController {
     }
 }
 
-impl<'a> From<&'a Subsystem> for &'a CpuSetController {
-    fn from(sub: &'a Subsystem) -> &'a CpuSetController {
-        unsafe {
-            match sub {
-                Subsystem::CpuSet(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a CpuSetController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a CpuSetController> {
+        match sub {
+            Subsystem::CpuSet(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
@@ -258,6 +263,7 @@ impl CpuSetController {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
             v2,
         }
     }
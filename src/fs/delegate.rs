@@ -0,0 +1,205 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+//! This module contains the delegation of a control group to an unprivileged user.
+//!
+//! Following the delegation contract of cgroup v2, a control group is delegated by changing the
+//! owner of its directory and of the interface files listed in `/sys/kernel/cgroup/delegate`.
+//! The delegatee can then create children, move processes between them and enable controllers
+//! for them, but can't change the limits imposed on the delegated control group itself.
+//!
+//! See `Cgroup::delegate()`.
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use crate::fs::dirfd::{is_beneath, open_dir};
+use crate::fs::error::ErrorKind::*;
+use crate::fs::error::*;
+
+const DELEGATE_FILE: &str = "/sys/kernel/cgroup/delegate";
+
+/// The files to delegate on kernels that don't have `/sys/kernel/cgroup/delegate` (before 4.15).
+pub const DEFAULT_DELEGATE_FILES: &[&str] =
+    &["cgroup.procs", "cgroup.threads", "cgroup.subtree_control"];
+
+fn parse_delegate_files(s: &str) -> Vec<String> {
+    s.lines()
+        .map(str::trim)
+        // Only interface files are listed, skip anything that isn't one.
+        .filter(|l| l.contains('.') && !l.contains('/'))
+        .map(String::from)
+        .collect()
+}
+
+/// The interface files to chown when delegating a control group, read from
+/// `/sys/kernel/cgroup/delegate`, or `DEFAULT_DELEGATE_FILES` if the kernel doesn't provide it.
+pub fn delegate_files() -> Vec<String> {
+    match fs::read_to_string(DELEGATE_FILE) {
+        Ok(s) => parse_delegate_files(&s),
+        Err(_) => DEFAULT_DELEGATE_FILES
+            .iter()
+            .map(|f| f.to_string())
+            .collect(),
+    }
+}
+
+fn owner(dirfd: &fs::File, name: Option<&CString>) -> io::Result<(u32, u32)> {
+    match name {
+        None => dirfd.metadata().map(|m| (m.uid(), m.gid())),
+        Some(name) => {
+            let mut st: libc::stat = unsafe { std::mem::zeroed() };
+            let ret = unsafe {
+                libc::fstatat(
+                    dirfd.as_raw_fd(),
+                    name.as_ptr(),
+                    &mut st,
+                    libc::AT_SYMLINK_NOFOLLOW,
+                )
+            };
+            if ret != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok((st.st_uid, st.st_gid))
+        }
+    }
+}
+
+fn chown(dirfd: &fs::File, name: Option<&CString>, uid: u32, gid: u32) -> io::Result<()> {
+    let ret = match name {
+        None => unsafe { libc::fchown(dirfd.as_raw_fd(), uid, gid) },
+        Some(name) => unsafe {
+            libc::fchownat(
+                dirfd.as_raw_fd(),
+                name.as_ptr(),
+                uid,
+                gid,
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        },
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Changes the owner of the directory `dir` and of its `files` to `uid` and `gid`, then checks
+/// that they're owned by them. Files that don't exist, e.g. the ones of a controller that isn't
+/// enabled, are skipped. Returns the files that were changed.
+pub(crate) fn chown_delegated(
+    dir: &Path,
+    files: &[String],
+    uid: u32,
+    gid: u32,
+) -> Result<Vec<String>> {
+    let dirfd = open_dir(dir)?;
+    let mut targets = vec![(dir.display().to_string(), None)];
+    for file in files {
+        // Only files directly in the directory are delegated.
+        if file.contains('/') || !is_beneath(Path::new(file)) {
+            return Err(Error::new(InvalidPath));
+        }
+        let name = CString::new(file.as_bytes()).map_err(|_| Error::new(InvalidPath))?;
+        targets.push((file.clone(), Some(name)));
+    }
+
+    let value = format!("{}:{}", uid, gid);
+    let mut changed = Vec::new();
+    for (file, name) in &targets {
+        match chown(&dirfd, name.as_ref(), uid, gid) {
+            Err(e) if name.is_some() && e.raw_os_error() == Some(libc::ENOENT) => continue,
+            Err(e) => {
+                return Err(Error::with_cause(
+                    WriteFailed(dir.join(file).display().to_string(), value),
+                    e,
+                ))
+            }
+            Ok(()) => (),
+        }
+
+        let (u, g) = owner(&dirfd, name.as_ref())
+            .map_err(|e| Error::with_cause(ReadFailed(dir.join(file).display().to_string()), e))?;
+        if (u, g) != (uid, gid) {
+            return Err(Error::from_string(format!(
+                "{} is owned by {}:{} after delegating it to {}",
+                dir.join(file).display(),
+                u,
+                g,
+                value
+            )));
+        }
+        if name.is_some() {
+            changed.push(file.clone());
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    use crate::fs::delegate::{chown_delegated, parse_delegate_files};
+    use crate::fs::error::ErrorKind;
+
+    #[test]
+    fn test_parse_delegate_files() {
+        let s = "cgroup.procs\ncgroup.threads\ncgroup.subtree_control\nmemory.oom.group\n\
+                 memory.reclaim\nnsdelegate\n";
+        assert_eq!(
+            parse_delegate_files(s),
+            vec![
+                "cgroup.procs",
+                "cgroup.threads",
+                "cgroup.subtree_control",
+                "memory.oom.group",
+                "memory.reclaim"
+            ]
+        );
+        assert!(parse_delegate_files("").is_empty());
+    }
+
+    #[test]
+    fn test_chown_delegated() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("cgroups-rs-delegate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("cgroup.procs"), "").unwrap();
+        fs::write(dir.join("memory.max"), "max").unwrap();
+        let outside = dir.with_extension("outside");
+        fs::write(&outside, "").unwrap();
+        std::os::unix::fs::symlink(&outside, dir.join("cgroup.threads")).unwrap();
+
+        let files = ["cgroup.procs", "cgroup.threads", "memory.reclaim"]
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>();
+        let changed = chown_delegated(&dir, &files, 1234, 5678).unwrap();
+        assert_eq!(changed, vec!["cgroup.procs", "cgroup.threads"]);
+
+        let owner = |p: &std::path::Path| {
+            let m = fs::symlink_metadata(p).unwrap();
+            (m.uid(), m.gid())
+        };
+        assert_eq!(owner(&dir), (1234, 5678));
+        assert_eq!(owner(&dir.join("cgroup.procs")), (1234, 5678));
+        // Limits stay with the delegator, and symlinks are not followed.
+        assert_eq!(owner(&dir.join("memory.max")), (0, 0));
+        assert_eq!(owner(&outside), (0, 0));
+
+        let err = chown_delegated(&dir, &["../x.y".to_string()], 1234, 5678).unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::InvalidPath);
+
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&outside).unwrap();
+    }
+}
@@ -7,11 +7,13 @@
 //!
 //! See the Kernel's documentation for more information about this subsystem, found at:
 //!  [Documentation/cgroup-v1/devices.txt](https://www.kernel.org/doc/Documentation/cgroup-v1/devices.txt)
+use std::convert::TryFrom;
 use std::io::{Read, Write};
 use std::path::PathBuf;
 
 use log::*;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::ErrorKind::*;
 use crate::fs::error::*;
 
@@ -28,6 +30,7 @@ use crate::fs::{
 pub struct DevicesController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
 }
 
 /// An enum holding the different types of devices that can be manipulated using this controller.
@@ -165,6 +168,10 @@ impl ControllerInternal for DevicesController {
         &self.base
     }
 
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
     fn apply(&self, res: &Resources) -> Result<()> {
         // get the resources that apply to this controller
         let res: &DeviceResources = &res.devices;
@@ -187,17 +194,15 @@ impl ControllIdentifier for DevicesController {
     }
 }
 
-impl<'a> From<&'a Subsystem> for &'a DevicesController {
-    fn from(sub: &'a Subsystem) -> &'a DevicesController {
-        unsafe {
-            match sub {
-                Subsystem::Devices(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a DevicesController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a DevicesController> {
+        match sub {
+            Subsystem::Devices(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
@@ -208,6 +213,7 @@ impl DevicesController {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
         }
     }
 
@@ -0,0 +1,275 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+//! Symlink-safe access to the interface files of a control group.
+//!
+//! The directory of a control group may be writable by an unprivileged party, e.g. a container
+//! that was delegated its sub-hierarchy. Such a party could replace a directory or an interface
+//! file with a symlink and redirect our privileged writes outside of the cgroup filesystem. Every
+//! controller therefore keeps an `O_PATH` file descriptor of its directory, and interface files
+//! are opened relative to it with `openat2(RESOLVE_BENEATH | RESOLVE_NO_SYMLINKS)`.
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::fs::error::ErrorKind::*;
+use crate::fs::error::*;
+
+/// Whether `path` stays below the directory it is relative to, i.e. it is relative and has no
+/// `..` component.
+pub(crate) fn is_beneath(path: &Path) -> bool {
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+fn cstring(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Opens `path` relative to `dirfd` with `openat2(2)`, falling back to `openat(2)` with
+/// `O_NOFOLLOW` on kernels older than 5.6.
+fn openat2(dirfd: RawFd, path: &Path, flags: libc::c_int, resolve: u64) -> io::Result<OwnedFd> {
+    let c_path = cstring(path)?;
+    let mode = if flags & libc::O_CREAT != 0 { 0o644 } else { 0 };
+
+    let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+    how.flags = (flags | libc::O_CLOEXEC) as u64;
+    how.mode = mode;
+    how.resolve = resolve;
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            dirfd,
+            c_path.as_ptr(),
+            &how as *const libc::open_how,
+            std::mem::size_of::<libc::open_how>(),
+        )
+    };
+    if fd >= 0 {
+        return Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) });
+    }
+
+    let err = io::Error::last_os_error();
+    if err.raw_os_error() != Some(libc::ENOSYS) {
+        return Err(err);
+    }
+    let fd = unsafe {
+        libc::openat(
+            dirfd,
+            c_path.as_ptr(),
+            flags | libc::O_CLOEXEC | libc::O_NOFOLLOW,
+            mode,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Opens the directory `dir` without following symlinks. Unlike the `O_PATH` file descriptor
+/// kept by `DirFd`, the returned one can be passed to `clone3(CLONE_INTO_CGROUP)` or BPF.
+pub(crate) fn open_dir(dir: &Path) -> Result<File> {
+    if dir.components().any(|c| c == Component::ParentDir) {
+        return Err(Error::new(InvalidPath));
+    }
+    openat2(
+        libc::AT_FDCWD,
+        dir,
+        libc::O_RDONLY | libc::O_DIRECTORY,
+        libc::RESOLVE_NO_SYMLINKS,
+    )
+    .map(File::from)
+    .map_err(|e| Error::with_cause(ReadFailed(dir.display().to_string()), e))
+}
+
+/// Opens the directory `path` below the directory `root` with `O_PATH`, without following
+/// symlinks or leaving `root`, e.g. to mount onto `/proc/self/fd/<fd>`. `root` itself is trusted.
+///
+/// The components are opened one at a time, so that the `openat(2)` fallback of older kernels
+/// refuses the symlinks in the middle of the path as well.
+pub(crate) fn open_beneath(root: &Path, path: &Path) -> Result<File> {
+    let rel = path
+        .strip_prefix(root)
+        .map_err(|_| Error::new(InvalidPath))?;
+    if !is_beneath(rel) {
+        return Err(Error::new(InvalidPath));
+    }
+
+    let flags = libc::O_PATH | libc::O_DIRECTORY;
+    let map_err = |e| Error::with_cause(ReadFailed(path.display().to_string()), e);
+    let mut fd = openat2(libc::AT_FDCWD, root, flags, 0).map_err(map_err)?;
+    for c in rel.components() {
+        fd = openat2(
+            fd.as_raw_fd(),
+            Path::new(c.as_os_str()),
+            flags,
+            libc::RESOLVE_BENEATH | libc::RESOLVE_NO_SYMLINKS,
+        )
+        .map_err(map_err)?;
+    }
+    Ok(File::from(fd))
+}
+
+/// An `O_PATH` file descriptor of the directory of a controller, opened on first use.
+///
+/// Cloning yields an empty cache, since the clone may enter another control group.
+#[derive(Debug, Default)]
+pub struct DirFd(Mutex<Option<(PathBuf, OwnedFd)>>);
+
+impl Clone for DirFd {
+    fn clone(&self) -> Self {
+        DirFd::default()
+    }
+}
+
+impl DirFd {
+    /// Runs `f` with a file descriptor of the directory `dir`, reusing the cached one if it
+    /// refers to the same path.
+    fn with_fd<T>(&self, dir: &Path, f: impl Fn(RawFd) -> io::Result<T>) -> io::Result<T> {
+        let mut cache = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((path, fd)) = cache.as_ref() {
+            if path == dir {
+                match f(fd.as_raw_fd()) {
+                    // The directory may have been removed and created again since it was opened.
+                    Err(e) if e.raw_os_error() == Some(libc::ENOENT) => (),
+                    ret => return ret,
+                }
+            }
+        }
+
+        let fd = self.open_dir(dir)?;
+        let ret = f(fd.as_raw_fd());
+        *cache = Some((dir.to_path_buf(), fd));
+        ret
+    }
+
+    /// Closes the cached file descriptor, e.g. before the directory is removed, so that it
+    /// doesn't pin the removed control group.
+    pub(crate) fn reset(&self) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    fn open_dir(&self, dir: &Path) -> io::Result<OwnedFd> {
+        openat2(
+            libc::AT_FDCWD,
+            dir,
+            libc::O_PATH | libc::O_DIRECTORY,
+            libc::RESOLVE_NO_SYMLINKS,
+        )
+    }
+
+    /// Opens the file `name` of the directory `dir` for reading, or for writing if `w` is set.
+    ///
+    /// Fails with `InvalidPath` if `dir` has a `..` component or `name` isn't beneath `dir`.
+    pub(crate) fn open(&self, dir: &Path, name: &str, w: bool) -> Result<File> {
+        let name = Path::new(name);
+        if dir.components().any(|c| c == Component::ParentDir) || !is_beneath(name) {
+            return Err(Error::new(InvalidPath));
+        }
+
+        let flags = if w {
+            libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC
+        } else {
+            libc::O_RDONLY
+        };
+        let path = dir.join(name);
+        self.with_fd(dir, |fd| {
+            openat2(
+                fd,
+                name,
+                flags,
+                libc::RESOLVE_BENEATH | libc::RESOLVE_NO_SYMLINKS,
+            )
+        })
+        .map(File::from)
+        .map_err(|e| {
+            if w {
+                Error::with_cause(
+                    WriteFailed(path.display().to_string(), "[CREATE FILE]".to_string()),
+                    e,
+                )
+            } else {
+                Error::with_cause(ReadFailed(path.display().to_string()), e)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::path::Path;
+
+    use crate::fs::dirfd::{is_beneath, open_beneath, DirFd};
+    use crate::fs::error::ErrorKind;
+
+    #[test]
+    fn test_is_beneath() {
+        assert!(is_beneath(Path::new("a/b")));
+        assert!(is_beneath(Path::new("./a")));
+        assert!(!is_beneath(Path::new("/a")));
+        assert!(!is_beneath(Path::new("a/../../b")));
+    }
+
+    #[test]
+    fn test_dirfd_open() {
+        let dir = std::env::temp_dir().join(format!("cgroups-rs-dirfd-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dirfd = DirFd::default();
+
+        let mut f = dirfd.open(&dir, "memory.max", true).unwrap();
+        f.write_all(b"max").unwrap();
+        let mut s = String::new();
+        dirfd
+            .open(&dir, "memory.max", false)
+            .unwrap()
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "max");
+
+        // Escaping paths and symlinks are refused.
+        let err = dirfd.open(&dir, "../memory.max", false).unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::InvalidPath);
+        let err = dirfd
+            .open(&dir.join(".."), "memory.max", false)
+            .unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::InvalidPath);
+        std::os::unix::fs::symlink("/etc/hostname", dir.join("link")).unwrap();
+        assert!(dirfd.open(&dir, "link", false).is_err());
+
+        // The directory is opened again after being re-created.
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        dirfd.open(&dir, "memory.max", true).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_beneath() {
+        let root = std::env::temp_dir().join(format!("cgroups-rs-beneath-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("sys/fs")).unwrap();
+
+        open_beneath(&root, &root).unwrap();
+        open_beneath(&root, &root.join("sys/fs")).unwrap();
+        let err = open_beneath(&root, Path::new("/sys")).unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::InvalidPath);
+        let err = open_beneath(&root, &root.join("sys/../..")).unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::InvalidPath);
+
+        // Symlinks are refused anywhere in the path.
+        std::os::unix::fs::symlink("/sys", root.join("link")).unwrap();
+        assert!(open_beneath(&root, &root.join("link")).is_err());
+        assert!(open_beneath(&root, &root.join("link/fs")).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
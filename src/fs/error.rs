@@ -6,6 +6,10 @@
 
 use std::error::Error as StdError;
 use std::fmt;
+use std::io;
+use std::path::Path;
+
+use nix::errno::Errno;
 
 /// The different types of errors that can occur while manipulating control groups.
 #[derive(thiserror::Error, Debug, Eq, PartialEq)]
@@ -55,6 +59,12 @@ pub enum ErrorKind {
     #[error("specified controller is not in the list of supported controllers")]
     SpecifiedControllers,
 
+    /// Writing to `cgroup.subtree_control` of a control group failed, e.g. because of the
+    /// no-internal-process rule (`EBUSY`), because the controller isn't enabled in the parent
+    /// (`ENOENT`) or because it can't be used in a threaded subtree (`EOPNOTSUPP`).
+    #[error("unable to write \"{1}\" to cgroup.subtree_control of {0}: {2}")]
+    SubtreeControl(String, String, nix::errno::Errno),
+
     /// Using method in wrong cgroup version.
     #[error("using method in wrong cgroup version")]
     CgroupVersion,
@@ -63,6 +73,14 @@ pub enum ErrorKind {
     #[error("using method in wrong cgroup mode.")]
     CgroupMode,
 
+    /// The process exited while acting on it, and its PID may have been recycled.
+    #[error("process {0} exited")]
+    ProcessExited(u64),
+
+    /// A subsystem was converted to the controller of another subsystem.
+    #[error("unexpected subsystem {0}")]
+    UnexpectedSubsystem(String),
+
     /// Subsystems is empty.
     #[error("subsystems is empty")]
     SubsystemsEmpty,
@@ -72,6 +90,37 @@ pub enum ErrorKind {
     Other,
 }
 
+/// The class of the errno an operation failed with, for the ones callers usually handle.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ErrnoClass {
+    /// `EBUSY`, e.g. the control group isn't empty, or the no-internal-process rule.
+    Busy,
+    /// `ENOENT`, e.g. the control group or the interface file doesn't exist, or the controller
+    /// isn't enabled.
+    NotFound,
+    /// `EACCES` or `EPERM`.
+    PermissionDenied,
+    /// `EINVAL`, e.g. a value out of range or in the wrong format.
+    InvalidArgument,
+    /// `ENOSPC`, e.g. an empty cpuset, or the maximum depth or number of descendants reached.
+    NoSpace,
+    /// Any other errno.
+    Other(Errno),
+}
+
+impl From<Errno> for ErrnoClass {
+    fn from(errno: Errno) -> Self {
+        match errno {
+            Errno::EBUSY => ErrnoClass::Busy,
+            Errno::ENOENT => ErrnoClass::NotFound,
+            Errno::EACCES | Errno::EPERM => ErrnoClass::PermissionDenied,
+            Errno::EINVAL => ErrnoClass::InvalidArgument,
+            Errno::ENOSPC => ErrnoClass::NoSpace,
+            errno => ErrnoClass::Other(errno),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Error {
     kind: ErrorKind,
@@ -122,6 +171,122 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         &self.kind
     }
+
+    /// The interface file that couldn't be read or written, as given to the failing operation:
+    /// a file name like `memory.max`, or a path.
+    pub fn file(&self) -> Option<&str> {
+        match &self.kind {
+            ErrorKind::WriteFailed(file, _) | ErrorKind::ReadFailed(file) => Some(file),
+            ErrorKind::SubtreeControl(..) => Some("cgroup.subtree_control"),
+            _ => None,
+        }
+    }
+
+    /// The value that couldn't be written.
+    pub fn value(&self) -> Option<&str> {
+        match &self.kind {
+            ErrorKind::WriteFailed(_, value) | ErrorKind::SubtreeControl(_, value, _) => {
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+
+    /// The controller of the interface file that couldn't be read or written, i.e. the prefix of
+    /// its name, e.g. `memory` for `memory.max`, or `cgroup` for the core interface files.
+    pub fn controller(&self) -> Option<&str> {
+        let name = Path::new(self.file()?).file_name()?.to_str()?;
+        name.split_once('.').map(|(controller, _)| controller)
+    }
+
+    /// The errno the operation failed with, found in the chain of causes.
+    pub fn errno(&self) -> Option<Errno> {
+        if let ErrorKind::SubtreeControl(_, _, errno) = &self.kind {
+            return Some(*errno);
+        }
+        let mut cause = self.source();
+        while let Some(e) = cause {
+            if let Some(e) = e.downcast_ref::<io::Error>() {
+                if let Some(raw) = e.raw_os_error() {
+                    return Some(Errno::from_i32(raw));
+                }
+            } else if let Some(errno) = e.downcast_ref::<Errno>() {
+                return Some(*errno);
+            } else if let Some(e) = e.downcast_ref::<Error>() {
+                return e.errno();
+            }
+            cause = e.source();
+        }
+        None
+    }
+
+    /// The class of `errno()`.
+    pub fn errno_class(&self) -> Option<ErrnoClass> {
+        self.errno().map(ErrnoClass::from)
+    }
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::io;
+    use std::path::PathBuf;
+
+    use nix::errno::Errno;
+
+    use crate::fs::error::{ErrnoClass, Error, ErrorKind};
+    use crate::fs::memory::MemController;
+    use crate::fs::pid::PidController;
+    use crate::fs::Subsystem;
+
+    #[test]
+    fn test_error_details() {
+        let err = Error::with_cause(
+            ErrorKind::WriteFailed("/sys/fs/cgroup/a/memory.max".to_string(), "1".to_string()),
+            io::Error::from_raw_os_error(libc::EBUSY),
+        );
+        assert_eq!(err.file(), Some("/sys/fs/cgroup/a/memory.max"));
+        assert_eq!(err.value(), Some("1"));
+        assert_eq!(err.controller(), Some("memory"));
+        assert_eq!(err.errno(), Some(Errno::EBUSY));
+        assert_eq!(err.errno_class(), Some(ErrnoClass::Busy));
+
+        // Through nested errors.
+        let err = Error::with_cause(
+            ErrorKind::Common("nested".to_string()),
+            Error::with_cause(ErrorKind::FsError, Errno::ENOSPC),
+        );
+        assert_eq!(err.errno_class(), Some(ErrnoClass::NoSpace));
+        assert_eq!(err.file(), None);
+
+        let err = Error::new(ErrorKind::SubtreeControl(
+            "a".to_string(),
+            "+cpu".to_string(),
+            Errno::EOPNOTSUPP,
+        ));
+        assert_eq!(err.controller(), Some("cgroup"));
+        assert_eq!(err.value(), Some("+cpu"));
+        assert_eq!(
+            err.errno_class(),
+            Some(ErrnoClass::Other(Errno::EOPNOTSUPP))
+        );
+
+        assert_eq!(
+            Error::new(ErrorKind::ReadFailed("tasks".to_string())).controller(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_subsystem_conversion() {
+        let sub = Subsystem::Pid(PidController::new(PathBuf::new(), PathBuf::new(), true));
+        assert!(<&PidController>::try_from(&sub).is_ok());
+        let err = <&MemController>::try_from(&sub).unwrap_err();
+        assert_eq!(
+            *err.kind(),
+            ErrorKind::UnexpectedSubsystem("pids".to_string())
+        );
+    }
+}
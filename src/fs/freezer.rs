@@ -8,9 +8,11 @@
 //!
 //! See the Kernel's documentation for more information about this subsystem, found at:
 //!  [Documentation/cgroup-v1/freezer-subsystem.txt](https://www.kernel.org/doc/Documentation/cgroup-v1/freezer-subsystem.txt)
+use std::convert::TryFrom;
 use std::io::{Read, Write};
 use std::path::PathBuf;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::ErrorKind::*;
 use crate::fs::error::*;
 use crate::fs::{ControllIdentifier, ControllerInternal, Controllers, Resources, Subsystem};
@@ -28,6 +30,7 @@ use crate::FreezerState;
 pub struct FreezerController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
     v2: bool,
 }
 
@@ -45,6 +48,10 @@ impl ControllerInternal for FreezerController {
         &self.base
     }
 
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
     fn apply(&self, _res: &Resources) -> Result<()> {
         Ok(())
     }
@@ -56,17 +63,15 @@ impl ControllIdentifier for FreezerController {
     }
 }
 
-impl<'a> From<&'a Subsystem> for &'a FreezerController {
-    fn from(sub: &'a Subsystem) -> &'a FreezerController {
-        unsafe {
-            match sub {
-                Subsystem::Freezer(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a FreezerController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a FreezerController> {
+        match sub {
+            Subsystem::Freezer(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
@@ -77,6 +82,7 @@ impl FreezerController {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
             v2,
         }
     }
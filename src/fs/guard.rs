@@ -0,0 +1,118 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+//! This module contains a guard that deletes a control group when it goes out of scope.
+//!
+//! `Cgroup` itself does not implement `Drop`, so that a control group can outlive its handle and
+//! be re-loaded later. Short-lived control groups, e.g. the ones of sandboxed jobs, can be wrapped
+//! in a `CgroupGuard` instead, so that they're cleaned up even if the job panics.
+use std::ops::Deref;
+
+use log::warn;
+
+use crate::fs::cgroup::{DeleteOptions, DeleteProcs, DeleteReport};
+use crate::fs::error::*;
+use crate::fs::Cgroup;
+
+/// What to do with the processes still in a control group when its guard is dropped.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RemainingProcs {
+    /// Kill the processes with `SIGKILL`.
+    Kill,
+    /// Move the processes to the parent control group.
+    MoveToParent,
+    /// Leave the processes alone and log a warning. The control group is leaked if there are
+    /// any processes left.
+    Leak,
+}
+
+impl RemainingProcs {
+    pub(crate) fn delete_options(&self) -> DeleteOptions<'static> {
+        match self {
+            RemainingProcs::Kill => DeleteOptions {
+                procs: DeleteProcs::Kill,
+                ..Default::default()
+            },
+            RemainingProcs::MoveToParent => DeleteOptions {
+                procs: DeleteProcs::MoveToParent,
+                ..Default::default()
+            },
+            // Retrying is pointless as long as the processes are alive.
+            RemainingProcs::Leak => DeleteOptions {
+                procs: DeleteProcs::Keep,
+                retries: 0,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Owns a control group and deletes it, including its descendants, when dropped.
+///
+/// ## Example:
+///
+/// ```text
+/// let cg = CgroupGuard::new(Cgroup::new(h, "job")?, RemainingProcs::Kill);
+/// cg.add_task_by_tgid(CgroupPid::from(&child))?;
+/// // [...] the control group is deleted at the end of the scope, even on panic.
+/// ```
+#[derive(Debug)]
+pub struct CgroupGuard {
+    cgroup: Option<Cgroup>,
+    procs: RemainingProcs,
+}
+
+impl CgroupGuard {
+    /// Takes ownership of `cgroup`, deleting it on drop and handling the processes left in it
+    /// according to `procs`.
+    pub fn new(cgroup: Cgroup, procs: RemainingProcs) -> Self {
+        CgroupGuard {
+            cgroup: Some(cgroup),
+            procs,
+        }
+    }
+
+    /// Gives up the ownership of the control group, which is not deleted anymore.
+    pub fn release(mut self) -> Cgroup {
+        self.cgroup.take().unwrap()
+    }
+
+    /// Deletes the control group now, returning the outcome instead of logging it.
+    pub fn delete(mut self) -> Result<DeleteReport> {
+        let cgroup = self.cgroup.take().unwrap();
+        cgroup.delete_recursive(&self.procs.delete_options())
+    }
+}
+
+impl Deref for CgroupGuard {
+    type Target = Cgroup;
+
+    fn deref(&self) -> &Cgroup {
+        self.cgroup.as_ref().unwrap()
+    }
+}
+
+impl Drop for CgroupGuard {
+    fn drop(&mut self) {
+        let cgroup = match self.cgroup.take() {
+            Some(cgroup) => cgroup,
+            None => return,
+        };
+
+        match cgroup.delete_recursive(&self.procs.delete_options()) {
+            Ok(report) => {
+                for failure in report.failed {
+                    warn!(
+                        "failed to delete cgroup {}, processes left: {:?}: {}",
+                        failure.path.display(),
+                        failure.procs,
+                        failure.error
+                    );
+                }
+            }
+            Err(e) => warn!("failed to delete cgroup {}: {}", cgroup.path(), e),
+        }
+    }
+}
@@ -6,10 +6,13 @@
 
 //! This module represents the various control group hierarchies the Linux kernel supports.
 
+use std::ffi::OsString;
 use std::fs;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::os::unix::ffi::OsStringExt;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use crate::fs::blkio::BlkIoController;
 use crate::fs::cpu::CpuController;
@@ -19,6 +22,7 @@ use crate::fs::devices::DevicesController;
 use crate::fs::freezer::FreezerController;
 use crate::fs::hugetlb::HugeTlbController;
 use crate::fs::memory::MemController;
+use crate::fs::misc::MiscController;
 use crate::fs::net_cls::NetClsController;
 use crate::fs::net_prio::NetPrioController;
 use crate::fs::perf_event::PerfEventController;
@@ -28,62 +32,152 @@ use crate::fs::systemd::SystemdController;
 use crate::fs::{Controllers, Hierarchy, Subsystem};
 
 use crate::fs::cgroup::Cgroup;
+use crate::fs::error::ErrorKind::*;
+use crate::fs::error::*;
 
 /// Process mounts information.
 ///
 /// See `proc(5)` for format details.
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
 pub struct Mountinfo {
+    /// Unique identifier of the mount.
+    pub mount_id: u32,
+    /// Identifier of the parent mount, or of the mount itself for the root of the mount tree.
+    pub parent_id: u32,
+    /// Major and minor numbers of the device of the file system (`st_dev`).
+    pub dev: (u32, u32),
     /// Mount root directory of the file system.
     pub mount_root: PathBuf,
     /// Mount pathname relative to the process's root.
     pub mount_point: PathBuf,
+    /// Per-mount options.
+    pub mount_opts: Vec<String>,
+    /// Optional fields, e.g. `shared:X` or `master:X`.
+    pub optional_fields: Vec<String>,
     /// Filesystem type (main type with optional sub-type).
     pub fs_type: (String, Option<String>),
+    /// Filesystem-specific information, e.g. the device, or "none".
+    pub mount_source: String,
     /// Superblock options.
     pub super_opts: Vec<String>,
 }
 
+impl Mountinfo {
+    /// Whether this is a cgroup v1 or a cgroup v2 mount.
+    pub fn is_cgroup(&self) -> bool {
+        self.fs_type.0 == "cgroup" || self.fs_type.0 == "cgroup2"
+    }
+}
+
+impl FromStr for Mountinfo {
+    type Err = Error;
+
+    /// Parses a line of a mountinfo file.
+    fn from_str(line: &str) -> Result<Self> {
+        let mut fields = line.split_whitespace();
+        let mut next = || fields.next().ok_or_else(|| Error::new(ParseError));
+
+        let mount_id = next()?
+            .parse()
+            .map_err(|e| Error::with_cause(ParseError, e))?;
+        let parent_id = next()?
+            .parse()
+            .map_err(|e| Error::with_cause(ParseError, e))?;
+        let dev = match next()?.split_once(':') {
+            Some((major, minor)) => (
+                major
+                    .parse()
+                    .map_err(|e| Error::with_cause(ParseError, e))?,
+                minor
+                    .parse()
+                    .map_err(|e| Error::with_cause(ParseError, e))?,
+            ),
+            None => return Err(Error::new(ParseError)),
+        };
+        let mount_root = unescape_path(next()?);
+        let mount_point = unescape_path(next()?);
+        let mount_opts = next()?.split(',').map(String::from).collect();
+
+        // The optional fields are terminated by a single hyphen.
+        let mut optional_fields = vec![];
+        loop {
+            match next()? {
+                "-" => break,
+                field => optional_fields.push(field.to_string()),
+            }
+        }
+
+        let fs_type = next()?;
+        let fs_type = match fs_type.split_once('.') {
+            Some((main, sub)) => (main.to_string(), Some(sub.to_string())),
+            None => (fs_type.to_string(), None),
+        };
+        let mount_source = String::from_utf8_lossy(&unescape(next()?)).into_owned();
+        let super_opts = next()?.split(',').map(String::from).collect();
+
+        Ok(Mountinfo {
+            mount_id,
+            parent_id,
+            dev,
+            mount_root,
+            mount_point,
+            mount_opts,
+            optional_fields,
+            fs_type,
+            mount_source,
+            super_opts,
+        })
+    }
+}
+
+/// Decodes the octal escapes the kernel uses in mountinfo, e.g. `\040` for a space.
+fn unescape(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut r = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let oct = bytes.get(i + 1..i + 4).unwrap_or_default();
+        // At most \377, so that the value fits in a byte.
+        if bytes[i] == b'\\'
+            && oct.len() == 3
+            && oct[0] <= b'3'
+            && oct.iter().all(|b| (b'0'..=b'7').contains(b))
+        {
+            r.push(oct.iter().fold(0, |acc, b| acc * 8 + (b - b'0')));
+            i += 4;
+        } else {
+            r.push(bytes[i]);
+            i += 1;
+        }
+    }
+    r
+}
+
+fn unescape_path(s: &str) -> PathBuf {
+    PathBuf::from(OsString::from_vec(unescape(s)))
+}
+
+/// Parses a line of a mountinfo file, see `Mountinfo::from_str()`.
 pub(crate) fn parse_mountinfo_for_line(line: &str) -> Option<Mountinfo> {
-    let s_values: Vec<_> = line.split(" - ").collect();
-    if s_values.len() != 2 {
-        return None;
-    }
-
-    let s0_values: Vec<_> = s_values[0].trim().split(' ').collect();
-    let s1_values: Vec<_> = s_values[1].trim().split(' ').collect();
-    if s0_values.len() < 6 || s1_values.len() < 3 {
-        return None;
-    }
-    let mount_point = PathBuf::from(s0_values[4]);
-    let mount_root = PathBuf::from(s0_values[3]);
-    let fs_type_values: Vec<_> = s1_values[0].trim().split('.').collect();
-    let fs_type = match fs_type_values.len() {
-        1 => (fs_type_values[0].to_string(), None),
-        2 => (
-            fs_type_values[0].to_string(),
-            Some(fs_type_values[1].to_string()),
-        ),
-        _ => return None,
-    };
+    line.parse().ok()
+}
 
-    let super_opts: Vec<String> = s1_values[2].trim().split(',').map(String::from).collect();
-    Some(Mountinfo {
-        mount_root,
-        mount_point,
-        fs_type,
-        super_opts,
-    })
+/// Parses the content of a mountinfo file, skipping malformed lines.
+pub fn parse_mountinfo(content: &str) -> Vec<Mountinfo> {
+    content
+        .lines()
+        .filter_map(parse_mountinfo_for_line)
+        .collect()
 }
 
-/// Parses the provided mountinfo file.
+/// Parses the provided mountinfo file, keeping the cgroup v1 and v2 mounts.
 fn mountinfo_file(file: &mut File) -> Vec<Mountinfo> {
     let mut r = Vec::new();
     for line in BufReader::new(file).lines() {
         match line {
             Ok(line) => {
                 if let Some(mi) = parse_mountinfo_for_line(&line) {
-                    if mi.fs_type.0 == "cgroup" {
+                    if mi.is_cgroup() {
                         r.push(mi);
                     }
                 }
@@ -113,6 +207,17 @@ pub struct V2 {
     root: String,
 }
 
+/// The hybrid layout: the controllers are bound to v1 hierarchies, and a cgroup v2 hierarchy
+/// without controllers is mounted next to them, usually at `/sys/fs/cgroup/unified`.
+///
+/// Control groups behave as on v1, but are mirrored in the unified hierarchy, which provides the
+/// v2-only features like `cgroup.events`, `cgroup.kill` and attaching BPF programs.
+#[derive(Debug, Clone)]
+pub struct Hybrid {
+    v1: V1,
+    unified: PathBuf,
+}
+
 impl Hierarchy for V1 {
     fn v2(&self) -> bool {
         false
@@ -165,7 +270,10 @@ impl Hierarchy for V1 {
             )));
         }
         if let Some((point, root)) = self.get_mount_point(Controllers::Rdma) {
-            subs.push(Subsystem::Rdma(RdmaController::new(point, root)));
+            subs.push(Subsystem::Rdma(RdmaController::new(point, root, false)));
+        }
+        if let Some((point, root)) = self.get_mount_point(Controllers::Misc) {
+            subs.push(Subsystem::Misc(MiscController::new(point, root, false)));
         }
         if let Some((point, root)) = self.get_mount_point(Controllers::Systemd) {
             subs.push(Subsystem::Systemd(SystemdController::new(
@@ -273,6 +381,20 @@ impl Hierarchy for V2 {
                         true,
                     )));
                 }
+                "rdma" => {
+                    subs.push(Subsystem::Rdma(RdmaController::new(
+                        self.root(),
+                        PathBuf::from(""),
+                        true,
+                    )));
+                }
+                "misc" => {
+                    subs.push(Subsystem::Misc(MiscController::new(
+                        self.root(),
+                        PathBuf::from(""),
+                        true,
+                    )));
+                }
                 _ => {}
             }
         }
@@ -295,6 +417,34 @@ impl Hierarchy for V2 {
     }
 }
 
+impl Hierarchy for Hybrid {
+    fn v2(&self) -> bool {
+        false
+    }
+
+    fn subsystems(&self) -> Vec<Subsystem> {
+        self.v1.subsystems()
+    }
+
+    fn root_control_group(&self) -> Cgroup {
+        Cgroup::load(auto(), "")
+    }
+
+    fn parent_control_group(&self, path: &str) -> Cgroup {
+        let path = Path::new(path);
+        let parent_path = path.parent().unwrap().to_string_lossy().to_string();
+        Cgroup::load(auto(), parent_path)
+    }
+
+    fn root(&self) -> PathBuf {
+        self.v1.root()
+    }
+
+    fn unified_root(&self) -> Option<PathBuf> {
+        Some(self.unified.clone())
+    }
+}
+
 impl V1 {
     /// Finds where control groups are mounted to and returns a hierarchy in which control groups
     /// can be created.
@@ -320,6 +470,27 @@ impl Default for V1 {
     }
 }
 
+impl Hybrid {
+    /// Finds where the v1 hierarchies and the unified hierarchy are mounted to and returns a
+    /// hierarchy in which control groups can be created.
+    pub fn new() -> Hybrid {
+        let v1 = V1::new();
+        let unified = v1
+            .mountinfo
+            .iter()
+            .find(|m| m.fs_type.0 == "cgroup2")
+            .map(|m| m.mount_point.clone())
+            .unwrap_or_else(|| PathBuf::from(HYBRID_UNIFIED_MOUNTPOINT));
+        Hybrid { v1, unified }
+    }
+}
+
+impl Default for Hybrid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl V2 {
     /// Finds where control groups are mounted to and returns a hierarchy in which control groups
     /// can be created.
@@ -338,10 +509,13 @@ impl Default for V2 {
 
 pub const UNIFIED_MOUNTPOINT: &str = "/sys/fs/cgroup";
 
-pub fn is_cgroup2_unified_mode() -> bool {
+/// Where the unified hierarchy is mounted to in hybrid mode.
+pub const HYBRID_UNIFIED_MOUNTPOINT: &str = "/sys/fs/cgroup/unified";
+
+fn is_cgroup2_mount(path: &str) -> bool {
     use nix::sys::statfs;
 
-    let path = std::path::Path::new(UNIFIED_MOUNTPOINT);
+    let path = std::path::Path::new(path);
     let fs_stat = match statfs::statfs(path) {
         Ok(fs_stat) => fs_stat,
         Err(_) => return false,
@@ -350,9 +524,21 @@ pub fn is_cgroup2_unified_mode() -> bool {
     fs_stat.filesystem_type() == statfs::CGROUP2_SUPER_MAGIC
 }
 
+pub fn is_cgroup2_unified_mode() -> bool {
+    is_cgroup2_mount(UNIFIED_MOUNTPOINT)
+}
+
+/// Whether the v1 hierarchies are used together with a unified hierarchy mounted to
+/// `/sys/fs/cgroup/unified`, see `Hybrid`.
+pub fn is_cgroup2_hybrid_mode() -> bool {
+    !is_cgroup2_unified_mode() && is_cgroup2_mount(HYBRID_UNIFIED_MOUNTPOINT)
+}
+
 pub fn auto() -> Box<dyn Hierarchy> {
     if is_cgroup2_unified_mode() {
         Box::new(V2::new())
+    } else if is_cgroup2_hybrid_mode() {
+        Box::new(Hybrid::new())
     } else {
         Box::new(V1::new())
     }
@@ -362,26 +548,66 @@ pub fn auto() -> Box<dyn Hierarchy> {
 mod tests {
     use super::*;
 
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
     #[test]
     fn test_parse_mount() {
         let mountinfo = vec![
             ("29 26 0:26 / /sys/fs/cgroup/cpuset,cpu,cpuacct rw,nosuid,nodev,noexec,relatime shared:10 - cgroup cgroup rw,cpuset,cpu,cpuacct",
-             Mountinfo{mount_root: PathBuf::from("/"), mount_point: PathBuf::from("/sys/fs/cgroup/cpuset,cpu,cpuacct"), fs_type: ("cgroup".to_string(), None), super_opts: vec![
-                "rw".to_string(),
-                "cpuset".to_string(),
-                "cpu".to_string(),
-                "cpuacct".to_string(),
-             ]}),
+             Mountinfo {
+                mount_id: 29,
+                parent_id: 26,
+                dev: (0, 26),
+                mount_root: PathBuf::from("/"),
+                mount_point: PathBuf::from("/sys/fs/cgroup/cpuset,cpu,cpuacct"),
+                mount_opts: strings(&["rw", "nosuid", "nodev", "noexec", "relatime"]),
+                optional_fields: strings(&["shared:10"]),
+                fs_type: ("cgroup".to_string(), None),
+                mount_source: "cgroup".to_string(),
+                super_opts: strings(&["rw", "cpuset", "cpu", "cpuacct"]),
+             }),
             ("121 1731 0:42 / /shm rw,nosuid,nodev,noexec,relatime shared:68 master:66 - tmpfs shm rw,size=65536k",
-             Mountinfo{mount_root: PathBuf::from("/"), mount_point: PathBuf::from("/shm"), fs_type: ("tmpfs".to_string(), None), super_opts: vec![
-                "rw".to_string(),
-                "size=65536k".to_string(),
-             ]}),
+             Mountinfo {
+                mount_id: 121,
+                parent_id: 1731,
+                dev: (0, 42),
+                mount_root: PathBuf::from("/"),
+                mount_point: PathBuf::from("/shm"),
+                mount_opts: strings(&["rw", "nosuid", "nodev", "noexec", "relatime"]),
+                optional_fields: strings(&["shared:68", "master:66"]),
+                fs_type: ("tmpfs".to_string(), None),
+                mount_source: "shm".to_string(),
+                super_opts: strings(&["rw", "size=65536k"]),
+             }),
             ("121 1731 0:42 / /shm rw,nosuid,nodev,noexec,relatime shared:68 master:66 - tmpfs.123 shm rw,size=65536k",
-             Mountinfo{mount_root: PathBuf::from("/"), mount_point: PathBuf::from("/shm"), fs_type: ("tmpfs".to_string(), Some("123".to_string())), super_opts: vec![
-                "rw".to_string(),
-                "size=65536k".to_string(),
-             ]}),
+             Mountinfo {
+                mount_id: 121,
+                parent_id: 1731,
+                dev: (0, 42),
+                mount_root: PathBuf::from("/"),
+                mount_point: PathBuf::from("/shm"),
+                mount_opts: strings(&["rw", "nosuid", "nodev", "noexec", "relatime"]),
+                optional_fields: strings(&["shared:68", "master:66"]),
+                fs_type: ("tmpfs".to_string(), Some("123".to_string())),
+                mount_source: "shm".to_string(),
+                super_opts: strings(&["rw", "size=65536k"]),
+             }),
+            // No optional fields, and a cgroup2 mount.
+            ("42 32 0:38 / /sys/fs/cgroup/unified rw,relatime - cgroup2 cgroup2 rw,nsdelegate",
+             Mountinfo {
+                mount_id: 42,
+                parent_id: 32,
+                dev: (0, 38),
+                mount_root: PathBuf::from("/"),
+                mount_point: PathBuf::from("/sys/fs/cgroup/unified"),
+                mount_opts: strings(&["rw", "relatime"]),
+                optional_fields: vec![],
+                fs_type: ("cgroup2".to_string(), None),
+                mount_source: "cgroup2".to_string(),
+                super_opts: strings(&["rw", "nsdelegate"]),
+             }),
         ];
 
         for mi in mountinfo {
@@ -389,4 +615,39 @@ mod tests {
             assert_eq!(info, mi.1)
         }
     }
+
+    #[test]
+    fn test_parse_mount_escapes() {
+        // A mount point with a space, a tab, a newline, a backslash and " - " in it.
+        let info = parse_mountinfo_for_line(
+            r"36 25 0:31 /a\134b /mnt/my\040cg\011x\012\040-\040y rw - cgroup my\040src rw,memory",
+        )
+        .unwrap();
+        assert_eq!(info.mount_root, PathBuf::from("/a\\b"));
+        assert_eq!(info.mount_point, PathBuf::from("/mnt/my cg\tx\n - y"));
+        assert_eq!(info.mount_source, "my src");
+        assert_eq!(info.super_opts, strings(&["rw", "memory"]));
+        assert!(info.is_cgroup());
+
+        // Invalid escapes are kept as is.
+        assert_eq!(unescape(r"\400\04\x"), b"\\400\\04\\x".to_vec());
+    }
+
+    #[test]
+    fn test_parse_mount_malformed() {
+        for line in [
+            "",
+            "29 26 0:26 / /sys/fs/cgroup rw shared:10 cgroup cgroup rw",
+            "29 26 0-26 / /sys/fs/cgroup rw - cgroup cgroup rw",
+            "x 26 0:26 / /sys/fs/cgroup rw - cgroup cgroup rw",
+            "29 26 0:26 / /sys/fs/cgroup rw - cgroup cgroup",
+        ] {
+            assert!(parse_mountinfo_for_line(line).is_none(), "{}", line);
+        }
+
+        let content = "garbage\n42 32 0:38 / /sys/fs/cgroup rw - cgroup2 cgroup2 rw\n";
+        let mounts = parse_mountinfo(content);
+        assert_eq!(mounts.len(), 1);
+        assert_eq!(mounts[0].mount_point, PathBuf::from("/sys/fs/cgroup"));
+    }
 }
@@ -9,15 +9,19 @@
 //! See the Kernel's documentation for more information about this subsystem, found at:
 //!  [Documentation/cgroup-v1/hugetlb.txt](https://www.kernel.org/doc/Documentation/cgroup-v1/hugetlb.txt)
 use log::warn;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::io::Write;
 use std::path::PathBuf;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::ErrorKind::*;
 use crate::fs::error::*;
-use crate::fs::{flat_keyed_to_vec, read_u64_from};
+use crate::fs::{flat_keyed_to_vec, parse_max_value, read_string_from, read_u64_from};
 
 use crate::fs::{
-    ControllIdentifier, ControllerInternal, Controllers, HugePageResources, Resources, Subsystem,
+    ControllIdentifier, ControllerInternal, Controllers, HugePageResources, MaxValue, Resources,
+    Subsystem,
 };
 
 /// A controller that allows controlling the `hugetlb` subsystem of a Cgroup.
@@ -28,6 +32,7 @@ use crate::fs::{
 pub struct HugeTlbController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
     sizes: Vec<String>,
     v2: bool,
 }
@@ -46,6 +51,10 @@ impl ControllerInternal for HugeTlbController {
         &self.base
     }
 
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
     fn is_v2(&self) -> bool {
         self.v2
     }
@@ -61,27 +70,95 @@ impl ControllerInternal for HugeTlbController {
             }
         }
 
+        for i in &res.rsvd_limits {
+            let _ = self.set_rsvd_limit_in_bytes(&i.size, i.limit);
+            if self.rsvd_limit_in_bytes(&i.size)? != i.limit {
+                return Err(Error::new(Other));
+            }
+        }
+
         Ok(())
     }
 }
 
+/// Contains statistics about the NUMA locality of the hugepages of a certain size used by the
+/// control group's tasks.
+#[derive(Default, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HugeTlbNumaStat {
+    /// Total amount of memory backed by hugepages, in bytes.
+    pub total: u64,
+    /// Total amount of memory backed by hugepages, in bytes, broken down by NUMA node ID.
+    pub total_per_node: BTreeMap<u32, u64>,
+    /// Same as `total`, but includes the descendant control groups' number as well.
+    /// Only for cgroup v1, on v2 `total` is always hierarchical.
+    pub hierarchical_total: u64,
+    /// Same as `total_per_node`, but includes the descendant control groups' number as well.
+    /// Only for cgroup v1, on v2 `total_per_node` is always hierarchical.
+    pub hierarchical_total_per_node: BTreeMap<u32, u64>,
+}
+
+fn parse_hugetlb_numa_stat(s: String) -> Result<HugeTlbNumaStat> {
+    // lines:
+    // total=2097152 N0=2097152 N1=0
+    // hierarchical_total=2097152 N0=2097152 N1=0 (cgroup v1 only)
+    let parse_field = |field: &str| -> Result<(String, u64)> {
+        let (k, v) = field
+            .split_once('=')
+            .ok_or_else(|| Error::new(ParseError))?;
+        let v = v
+            .parse::<u64>()
+            .map_err(|e| Error::with_cause(ParseError, e))?;
+        Ok((k.to_string(), v))
+    };
+    let parse_line = |line: &str| -> Result<(String, u64, BTreeMap<u32, u64>)> {
+        let mut fields = line.split_whitespace();
+        let (key, total) = parse_field(fields.next().ok_or_else(|| Error::new(ParseError))?)?;
+        let mut per_node = BTreeMap::new();
+        for field in fields {
+            let (node, value) = parse_field(field)?;
+            let node = node
+                .strip_prefix('N')
+                .and_then(|n| n.parse::<u32>().ok())
+                .ok_or_else(|| Error::new(ParseError))?;
+            per_node.insert(node, value);
+        }
+        Ok((key, total, per_node))
+    };
+
+    let mut stat = HugeTlbNumaStat::default();
+    for line in s.lines().filter(|l| !l.trim().is_empty()) {
+        let (key, total, per_node) = parse_line(line)?;
+        match key.as_str() {
+            "total" => {
+                stat.total = total;
+                stat.total_per_node = per_node;
+            }
+            "hierarchical_total" => {
+                stat.hierarchical_total = total;
+                stat.hierarchical_total_per_node = per_node;
+            }
+            _ => {}
+        }
+    }
+    Ok(stat)
+}
+
 impl ControllIdentifier for HugeTlbController {
     fn controller_type() -> Controllers {
         Controllers::HugeTlb
     }
 }
 
-impl<'a> From<&'a Subsystem> for &'a HugeTlbController {
-    fn from(sub: &'a Subsystem) -> &'a HugeTlbController {
-        unsafe {
-            match sub {
-                Subsystem::HugeTlb(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a HugeTlbController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a HugeTlbController> {
+        match sub {
+            Subsystem::HugeTlb(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
@@ -93,6 +170,7 @@ impl HugeTlbController {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
             sizes,
             v2,
         }
@@ -165,6 +243,93 @@ impl HugeTlbController {
         .and_then(read_u64_from)
     }
 
+    /// Get the limit (in bytes) of how much memory backed by hugepages of a certain size
+    /// (`hugetlb_size`) can be reserved, e.g. at `mmap()` time. Returns `u64::MAX` when there is
+    /// no limit.
+    pub fn rsvd_limit_in_bytes(&self, hugetlb_size: &str) -> Result<u64> {
+        let mut file_name = format!("hugetlb.{}.rsvd.limit_in_bytes", hugetlb_size);
+        if self.v2 {
+            file_name = format!("hugetlb.{}.rsvd.max", hugetlb_size);
+        }
+        self.open_path(&file_name, false)
+            .and_then(read_string_from)
+            .and_then(|s| parse_max_value(&s))
+            .map(|v| match v {
+                MaxValue::Max => u64::MAX,
+                MaxValue::Value(v) => v as u64,
+            })
+    }
+
+    /// Get the amount of memory (in bytes) backed by hugepages of a certain size
+    /// (`hugetlb_size`) that is reserved, including the pages which are not faulted in yet.
+    pub fn rsvd_usage_in_bytes(&self, hugetlb_size: &str) -> Result<u64> {
+        let mut file_name = format!("hugetlb.{}.rsvd.usage_in_bytes", hugetlb_size);
+        if self.v2 {
+            file_name = format!("hugetlb.{}.rsvd.current", hugetlb_size);
+        }
+        self.open_path(&file_name, false).and_then(read_u64_from)
+    }
+
+    /// Get the maximum observed reservation of memory backed by hugepages of a certain size
+    /// (`hugetlb_size`).
+    ///
+    /// Only for cgroup v1.
+    pub fn rsvd_max_usage_in_bytes(&self, hugetlb_size: &str) -> Result<u64> {
+        if self.v2 {
+            return Err(Error::new(CgroupVersion));
+        }
+        self.open_path(
+            &format!("hugetlb.{}.rsvd.max_usage_in_bytes", hugetlb_size),
+            false,
+        )
+        .and_then(read_u64_from)
+    }
+
+    /// Check how many times has the reservation limit of `hugetlb_size` hugepages been hit.
+    ///
+    /// Only for cgroup v1.
+    pub fn rsvd_failcnt(&self, hugetlb_size: &str) -> Result<u64> {
+        if self.v2 {
+            return Err(Error::new(CgroupVersion));
+        }
+        self.open_path(&format!("hugetlb.{}.rsvd.failcnt", hugetlb_size), false)
+            .and_then(read_u64_from)
+    }
+
+    /// Whether the kernel supports the reservation accounting of `hugetlb_size` hugepages.
+    pub fn rsvd_supported(&self, hugetlb_size: &str) -> bool {
+        let mut file_name = format!("hugetlb.{}.rsvd.limit_in_bytes", hugetlb_size);
+        if self.v2 {
+            file_name = format!("hugetlb.{}.rsvd.max", hugetlb_size);
+        }
+        self.open_path(&file_name, false).is_ok()
+    }
+
+    /// Get the NUMA locality of the memory backed by hugepages of a certain size
+    /// (`hugetlb_size`).
+    pub fn numa_stat(&self, hugetlb_size: &str) -> Result<HugeTlbNumaStat> {
+        self.open_path(&format!("hugetlb.{}.numa_stat", hugetlb_size), false)
+            .and_then(read_string_from)
+            .and_then(parse_hugetlb_numa_stat)
+    }
+
+    /// Set the limit (in bytes) of how much memory backed by hugepages of a certain size
+    /// (`hugetlb_size`) can be reserved.
+    ///
+    /// Unlike `set_limit_in_bytes()`, which makes the tasks receive a `SIGBUS` when they fault in
+    /// a page over the limit, this makes the reservation (e.g. `mmap()`) fail instead.
+    pub fn set_rsvd_limit_in_bytes(&self, hugetlb_size: &str, limit: u64) -> Result<()> {
+        let mut file_name = format!("hugetlb.{}.rsvd.limit_in_bytes", hugetlb_size);
+        if self.v2 {
+            file_name = format!("hugetlb.{}.rsvd.max", hugetlb_size);
+        }
+        self.open_path(&file_name, true).and_then(|mut file| {
+            file.write_all(limit.to_string().as_ref()).map_err(|e| {
+                Error::with_cause(WriteFailed(file_name.to_string(), limit.to_string()), e)
+            })
+        })
+    }
+
     /// Set the limit (in bytes) of how much memory can be backed by hugepages of a certain size
     /// (`hugetlb_size`).
     pub fn set_limit_in_bytes(&self, hugetlb_size: &str, limit: u64) -> Result<()> {
@@ -318,6 +483,38 @@ fn custom_size(mut size: f64, base: f64, m: &[String]) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_hugetlb_numa_stat() {
+        let ok = parse_hugetlb_numa_stat(
+            "total=4194304 N0=2097152 N1=2097152\nhierarchical_total=6291456 N0=4194304 N1=2097152\n"
+                .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            ok,
+            HugeTlbNumaStat {
+                total: 4194304,
+                total_per_node: vec![(0, 2097152), (1, 2097152)].into_iter().collect(),
+                hierarchical_total: 6291456,
+                hierarchical_total_per_node: vec![(0, 4194304), (1, 2097152)].into_iter().collect(),
+            }
+        );
+
+        // cgroup v2 only has the first line
+        let ok = parse_hugetlb_numa_stat("total=0 N0=0 N2=0\n".to_string()).unwrap();
+        assert_eq!(
+            ok,
+            HugeTlbNumaStat {
+                total_per_node: vec![(0, 0), (2, 0)].into_iter().collect(),
+                ..Default::default()
+            }
+        );
+
+        assert!(parse_hugetlb_numa_stat("total=abc N0=0\n".to_string()).is_err());
+        assert!(parse_hugetlb_numa_stat("total 0\n".to_string()).is_err());
+        assert!(parse_hugetlb_numa_stat("total=0 X0=0\n".to_string()).is_err());
+    }
+
     #[test]
     fn test_binary_size_valid() {
         let m = get_binary_size_map();
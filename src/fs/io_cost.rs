@@ -0,0 +1,406 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+//! This module contains the configuration of the `io.cost` controller of cgroup v2.
+//!
+//! The `io.cost` controller distributes the I/O capacity of a device according to `io.weight`. Its
+//! QoS parameters (`io.cost.qos`) and cost model (`io.cost.model`) are per-device and only exist
+//! in the root control group.
+//!
+//! See the Kernel's documentation for more information, found at:
+//!  [Documentation/admin-guide/cgroup-v2.rst](https://www.kernel.org/doc/Documentation/admin-guide/cgroup-v2.rst)
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use crate::fs::blkio::BlkIoController;
+use crate::fs::error::ErrorKind::*;
+use crate::fs::error::*;
+use crate::fs::{read_string_from, Cgroup, ControllerInternal};
+
+/// Who controls the parameters of a device.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IoCostCtrl {
+    /// The kernel picks the parameters, e.g. from the device's characteristics.
+    Auto,
+    /// The parameters were set by the user.
+    User,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for IoCostCtrl {
+    fn default() -> Self {
+        IoCostCtrl::Auto
+    }
+}
+
+impl fmt::Display for IoCostCtrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IoCostCtrl::Auto => write!(f, "auto"),
+            IoCostCtrl::User => write!(f, "user"),
+        }
+    }
+}
+
+impl FromStr for IoCostCtrl {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(IoCostCtrl::Auto),
+            "user" => Ok(IoCostCtrl::User),
+            _ => Err(Error::new(ParseError)),
+        }
+    }
+}
+
+/// The QoS parameters of a device, as found in `io.cost.qos`.
+///
+/// The device is considered saturated when more than `rpct` percent of the reads take longer than
+/// `rlat` (or the same for writes), in which case the vrate is lowered, but never out of
+/// `[min, max]`.
+#[derive(PartialEq, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IoCostQos {
+    /// The major number of the device.
+    pub major: u64,
+    /// The minor number of the device.
+    pub minor: u64,
+    /// Whether the `io.cost` controller is enabled for the device.
+    pub enable: bool,
+    /// Whether the parameters below are set by the kernel or by the user. When writing `Auto`,
+    /// the parameters below are ignored.
+    pub ctrl: IoCostCtrl,
+    /// Read latency percentile, in the range `[0, 100]`.
+    pub rpct: f64,
+    /// Read latency threshold, in microseconds.
+    pub rlat: u64,
+    /// Write latency percentile, in the range `[0, 100]`.
+    pub wpct: f64,
+    /// Write latency threshold, in microseconds.
+    pub wlat: u64,
+    /// Minimum scaling percentage of the vrate.
+    pub min: f64,
+    /// Maximum scaling percentage of the vrate.
+    pub max: f64,
+}
+
+/// The linear cost model of a device, as found in `io.cost.model`.
+#[derive(Eq, PartialEq, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IoCostModel {
+    /// The major number of the device.
+    pub major: u64,
+    /// The minor number of the device.
+    pub minor: u64,
+    /// Whether the coefficients below are set by the kernel or by the user. When writing `Auto`,
+    /// the coefficients below are ignored.
+    pub ctrl: IoCostCtrl,
+    /// Maximum sequential read bytes per second.
+    pub rbps: u64,
+    /// Maximum sequential read IOs per second.
+    pub rseqiops: u64,
+    /// Maximum random read IOs per second.
+    pub rrandiops: u64,
+    /// Maximum sequential write bytes per second.
+    pub wbps: u64,
+    /// Maximum sequential write IOs per second.
+    pub wseqiops: u64,
+    /// Maximum random write IOs per second.
+    pub wrandiops: u64,
+}
+
+/// A handle to the `io.cost` configuration of the root control group.
+///
+/// ## Example:
+///
+/// ```text
+/// let io_cost = IoCost::new(&hier.root_control_group())?;
+/// io_cost.set_qos(&IoCostQos {
+///     major: 259,
+///     minor: 0,
+///     enable: true,
+///     ctrl: IoCostCtrl::User,
+///     rpct: 95.0,
+///     rlat: 5000,
+///     wpct: 95.0,
+///     wlat: 5000,
+///     min: 50.0,
+///     max: 150.0,
+/// })?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct IoCost {
+    controller: BlkIoController,
+}
+
+impl IoCost {
+    /// Creates a handle for `cg`, which must be the root control group of a cgroup v2 hierarchy
+    /// with the `io` controller available.
+    pub fn new(cg: &Cgroup) -> Result<Self> {
+        if !cg.is_root() {
+            return Err(Error::new(InvalidOperation));
+        }
+        if !cg.v2() {
+            return Err(Error::new(CgroupVersion));
+        }
+        let controller: &BlkIoController = cg.controller_of().ok_or(Error::new(SubsystemsEmpty))?;
+
+        Ok(IoCost {
+            controller: controller.clone(),
+        })
+    }
+
+    /// The QoS parameters of all the devices known to the `io.cost` controller.
+    pub fn qos(&self) -> Result<Vec<IoCostQos>> {
+        self.controller
+            .open_path("io.cost.qos", false)
+            .and_then(read_string_from)
+            .and_then(|s| parse_io_cost_qos(&s))
+    }
+
+    /// The QoS parameters of the device `major:minor`, if known to the `io.cost` controller.
+    pub fn qos_for_device(&self, major: u64, minor: u64) -> Result<Option<IoCostQos>> {
+        Ok(self
+            .qos()?
+            .into_iter()
+            .find(|q| q.major == major && q.minor == minor))
+    }
+
+    /// Set the QoS parameters of a device.
+    pub fn set_qos(&self, qos: &IoCostQos) -> Result<()> {
+        let mut content = format!(
+            "{}:{} enable={} ctrl={}",
+            qos.major, qos.minor, qos.enable as u8, qos.ctrl
+        );
+        // any parameter switches the kernel to ctrl=user
+        if qos.ctrl == IoCostCtrl::User {
+            content.push_str(&format!(
+                " rpct={:.2} rlat={} wpct={:.2} wlat={} min={:.2} max={:.2}",
+                qos.rpct, qos.rlat, qos.wpct, qos.wlat, qos.min, qos.max
+            ));
+        }
+        self.write("io.cost.qos", content)
+    }
+
+    /// The cost models of all the devices known to the `io.cost` controller.
+    pub fn model(&self) -> Result<Vec<IoCostModel>> {
+        self.controller
+            .open_path("io.cost.model", false)
+            .and_then(read_string_from)
+            .and_then(|s| parse_io_cost_model(&s))
+    }
+
+    /// The cost model of the device `major:minor`, if known to the `io.cost` controller.
+    pub fn model_for_device(&self, major: u64, minor: u64) -> Result<Option<IoCostModel>> {
+        Ok(self
+            .model()?
+            .into_iter()
+            .find(|m| m.major == major && m.minor == minor))
+    }
+
+    /// Set the cost model of a device.
+    pub fn set_model(&self, model: &IoCostModel) -> Result<()> {
+        let mut content = format!("{}:{} ctrl={}", model.major, model.minor, model.ctrl);
+        // any coefficient switches the kernel to ctrl=user
+        if model.ctrl == IoCostCtrl::User {
+            content.push_str(&format!(
+                " model=linear rbps={} rseqiops={} rrandiops={} wbps={} wseqiops={} wrandiops={}",
+                model.rbps,
+                model.rseqiops,
+                model.rrandiops,
+                model.wbps,
+                model.wseqiops,
+                model.wrandiops
+            ));
+        }
+        self.write("io.cost.model", content)
+    }
+
+    fn write(&self, file_name: &str, content: String) -> Result<()> {
+        self.controller
+            .open_path(file_name, true)
+            .and_then(|mut file| {
+                file.write_all(content.as_ref())
+                    .map_err(|e| Error::with_cause(WriteFailed(file_name.to_string(), content), e))
+            })
+    }
+}
+
+/// The major and minor numbers of a device, followed by its key=value pairs.
+type IoCostLine<'a> = (u64, u64, Vec<(&'a str, &'a str)>);
+
+/// Splits a line of `io.cost.qos` or `io.cost.model` into the device and its key=value pairs.
+fn parse_io_cost_line(line: &str) -> Result<IoCostLine<'_>> {
+    let mut fields = line.split_whitespace();
+    let device = fields.next().ok_or_else(|| Error::new(ParseError))?;
+    let (major, minor) = device
+        .split_once(':')
+        .ok_or_else(|| Error::new(ParseError))?;
+    let major = major
+        .parse::<u64>()
+        .map_err(|e| Error::with_cause(ParseError, e))?;
+    let minor = minor
+        .parse::<u64>()
+        .map_err(|e| Error::with_cause(ParseError, e))?;
+    let pairs = fields
+        .map(|x| x.split_once('=').ok_or_else(|| Error::new(ParseError)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok((major, minor, pairs))
+}
+
+fn parse_field<T: FromStr>(value: &str) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    value.parse().map_err(|e| Error::with_cause(ParseError, e))
+}
+
+fn parse_io_cost_qos(s: &str) -> Result<Vec<IoCostQos>> {
+    // line:
+    // 8:16 enable=1 ctrl=user rpct=95.00 rlat=75000 wpct=95.00 wlat=150000 min=50.00 max=150.00
+    s.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (major, minor, pairs) = parse_io_cost_line(line)?;
+            let mut qos = IoCostQos {
+                major,
+                minor,
+                ..Default::default()
+            };
+            for (key, value) in pairs {
+                match key {
+                    "enable" => qos.enable = parse_field::<u8>(value)? != 0,
+                    "ctrl" => qos.ctrl = value.parse()?,
+                    "rpct" => qos.rpct = parse_field(value)?,
+                    "rlat" => qos.rlat = parse_field(value)?,
+                    "wpct" => qos.wpct = parse_field(value)?,
+                    "wlat" => qos.wlat = parse_field(value)?,
+                    "min" => qos.min = parse_field(value)?,
+                    "max" => qos.max = parse_field(value)?,
+                    _ => {}
+                }
+            }
+            Ok(qos)
+        })
+        .collect()
+}
+
+fn parse_io_cost_model(s: &str) -> Result<Vec<IoCostModel>> {
+    // line:
+    // 8:16 ctrl=auto model=linear rbps=174019176 rseqiops=41708 rrandiops=370 wbps=178075866 wseqiops=42705 wrandiops=378
+    s.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (major, minor, pairs) = parse_io_cost_line(line)?;
+            let mut model = IoCostModel {
+                major,
+                minor,
+                ..Default::default()
+            };
+            for (key, value) in pairs {
+                match key {
+                    "ctrl" => model.ctrl = value.parse()?,
+                    // linear is the only model the kernel implements
+                    "model" if value != "linear" => return Err(Error::new(ParseError)),
+                    "rbps" => model.rbps = parse_field(value)?,
+                    "rseqiops" => model.rseqiops = parse_field(value)?,
+                    "rrandiops" => model.rrandiops = parse_field(value)?,
+                    "wbps" => model.wbps = parse_field(value)?,
+                    "wseqiops" => model.wseqiops = parse_field(value)?,
+                    "wrandiops" => model.wrandiops = parse_field(value)?,
+                    _ => {}
+                }
+            }
+            Ok(model)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::error::ErrorKind;
+    use crate::fs::io_cost::{parse_io_cost_model, parse_io_cost_qos, IoCost};
+    use crate::fs::io_cost::{IoCostCtrl, IoCostModel, IoCostQos};
+    use crate::fs::Cgroup;
+
+    #[test]
+    fn test_parse_io_cost_qos() {
+        let ok = parse_io_cost_qos(
+            "\
+8:16 enable=1 ctrl=user rpct=95.00 rlat=75000 wpct=95.00 wlat=150000 min=50.00 max=150.00
+259:0 enable=0 ctrl=auto rpct=0.00 rlat=250000 wpct=0.00 wlat=250000 min=1.00 max=10000.00
+",
+        )
+        .unwrap();
+        assert_eq!(
+            ok,
+            vec![
+                IoCostQos {
+                    major: 8,
+                    minor: 16,
+                    enable: true,
+                    ctrl: IoCostCtrl::User,
+                    rpct: 95.0,
+                    rlat: 75000,
+                    wpct: 95.0,
+                    wlat: 150000,
+                    min: 50.0,
+                    max: 150.0,
+                },
+                IoCostQos {
+                    major: 259,
+                    minor: 0,
+                    enable: false,
+                    ctrl: IoCostCtrl::Auto,
+                    rpct: 0.0,
+                    rlat: 250000,
+                    wpct: 0.0,
+                    wlat: 250000,
+                    min: 1.0,
+                    max: 10000.0,
+                },
+            ]
+        );
+
+        assert!(parse_io_cost_qos("8:16 enable=1 ctrl=manual\n").is_err());
+        assert!(parse_io_cost_qos("8:16 enable=1 rlat=fast\n").is_err());
+        assert!(parse_io_cost_qos("sda enable=1\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_io_cost_model() {
+        let ok = parse_io_cost_model(
+            "8:16 ctrl=auto model=linear rbps=174019176 rseqiops=41708 rrandiops=370 wbps=178075866 wseqiops=42705 wrandiops=378\n",
+        )
+        .unwrap();
+        assert_eq!(
+            ok,
+            vec![IoCostModel {
+                major: 8,
+                minor: 16,
+                ctrl: IoCostCtrl::Auto,
+                rbps: 174019176,
+                rseqiops: 41708,
+                rrandiops: 370,
+                wbps: 178075866,
+                wseqiops: 42705,
+                wrandiops: 378,
+            }]
+        );
+
+        assert!(parse_io_cost_model("8:16 ctrl=user model=quadratic\n").is_err());
+    }
+
+    #[test]
+    fn test_io_cost_rejects_non_root() {
+        let h = crate::fs::hierarchies::auto();
+        let cg = Cgroup::load(h, "test_io_cost_rejects_non_root");
+        let err = IoCost::new(&cg).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::InvalidOperation);
+    }
+}
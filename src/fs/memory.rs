@@ -10,10 +10,12 @@
 //!  [Documentation/cgroup-v1/memory.txt](https://www.kernel.org/doc/Documentation/cgroup-v1/memory.txt)
 use log::warn;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::io::Write;
 use std::path::PathBuf;
 use std::sync::mpsc::Receiver;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::ErrorKind::*;
 use crate::fs::error::*;
 use crate::fs::events;
@@ -22,8 +24,8 @@ use crate::fs::{read_i64_from, read_string_from, read_u64_from};
 use crate::fs::flat_keyed_to_hashmap;
 
 use crate::fs::{
-    ControllIdentifier, ControllerInternal, Controllers, CustomizedAttribute, MaxValue,
-    MemoryResources, Resources, Subsystem,
+    parse_pressure, ControllIdentifier, ControllerInternal, Controllers, CustomizedAttribute,
+    MaxValue, MemoryResources, PressureStat, Resources, Subsystem,
 };
 
 /// A controller that allows controlling the `memory` subsystem of a Cgroup.
@@ -35,6 +37,7 @@ use crate::fs::{
 pub struct MemController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
     v2: bool,
 }
 
@@ -499,6 +502,10 @@ impl ControllerInternal for MemController {
         &self.base
     }
 
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
     fn is_v2(&self) -> bool {
         self.v2
     }
@@ -528,6 +535,7 @@ impl MemController {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
             v2,
         }
     }
@@ -575,6 +583,27 @@ impl MemController {
         Ok(m)
     }
 
+    /// Returns the current memory usage of the control group's tasks, in bytes.
+    pub fn usage_in_bytes(&self) -> Result<u64> {
+        let file_name = if self.v2 {
+            "memory.current"
+        } else {
+            "memory.usage_in_bytes"
+        };
+        self.open_path(file_name, false).and_then(read_u64_from)
+    }
+
+    /// Returns the memory pressure stall information of the control group, read from
+    /// `memory.pressure`. Only available in cgroup v2, on kernels built with PSI support.
+    pub fn memory_pressure(&self) -> Result<PressureStat> {
+        if !self.v2 {
+            return Err(Error::new(CgroupVersion));
+        }
+        self.open_path("memory.pressure", false)
+            .and_then(read_string_from)
+            .and_then(|s| parse_pressure(&s))
+    }
+
     fn memory_stat_v2(&self) -> Memory {
         // NOTE: get_mem() always returns T, but let's
         // still do `unwrap_or` for safety.
@@ -956,6 +985,21 @@ impl MemController {
         })
     }
 
+    /// Set which charges are moved along with the tasks that are moved into the control group:
+    /// bit 0 for anonymous pages, bit 1 for file pages. Only supported for v1 cgroups, v2 never
+    /// moves charges.
+    pub fn set_move_charge_at_immigrate(&self, bits: u64) -> Result<()> {
+        if self.v2 {
+            return Err(Error::new(CgroupVersion));
+        }
+        let file_name = "memory.move_charge_at_immigrate";
+        self.open_path(file_name, true).and_then(|mut file| {
+            file.write_all(bits.to_string().as_ref()).map_err(|e| {
+                Error::with_cause(WriteFailed(file_name.to_string(), bits.to_string()), e)
+            })
+        })
+    }
+
     pub fn disable_oom_killer(&self) -> Result<()> {
         self.open_path("memory.oom_control", true)
             .and_then(|mut file| {
@@ -985,17 +1029,15 @@ impl ControllIdentifier for MemController {
 
 impl CustomizedAttribute for MemController {}
 
-impl<'a> From<&'a Subsystem> for &'a MemController {
-    fn from(sub: &'a Subsystem) -> &'a MemController {
-        unsafe {
-            match sub {
-                Subsystem::Mem(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a MemController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a MemController> {
+        match sub {
+            Subsystem::Mem(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
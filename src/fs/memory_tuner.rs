@@ -0,0 +1,228 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+//! This module contains a feedback loop that adapts `memory.high` of a control group to the
+//! memory pressure its tasks experience, in the spirit of Meta's
+//! [senpai](https://github.com/facebookincubator/senpai).
+//!
+//! Every interval the tuner reads `memory.pressure` and compares the stall time accumulated since
+//! the previous step with the configured target. As long as the tasks stall less than the target,
+//! `memory.high` is lowered so that the kernel reclaims cold memory. As soon as they stall more,
+//! it is raised again. Over time `memory.high` converges towards the working set of the workload.
+//!
+//! Only cgroup v2 is supported, and the kernel must be built with PSI support.
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use crate::fs::error::ErrorKind::*;
+use crate::fs::error::*;
+use crate::fs::memory::{MemController, SetMemory};
+use crate::fs::{Cgroup, ControllerInternal, MaxValue};
+
+/// Tunables of a `MemoryHighTuner`. The defaults match the ones of senpai.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryHighTunerConfig {
+    /// Time between two adjustments of `memory.high`.
+    pub interval: Duration,
+    /// Stall time (`some` line of `memory.pressure`) per interval the tuner aims for.
+    pub pressure_target: Duration,
+    /// `memory.high` is never set below this value, in bytes.
+    pub min_high: u64,
+    /// Maximum ratio by which `memory.high` is lowered in a single step.
+    pub max_probe: f64,
+    /// Maximum ratio by which `memory.high` is raised in a single step.
+    pub max_backoff: f64,
+    /// How aggressively `memory.high` is lowered while the pressure is below target.
+    pub coeff_probe: f64,
+    /// How aggressively `memory.high` is raised while the pressure is above target.
+    pub coeff_backoff: f64,
+}
+
+impl Default for MemoryHighTunerConfig {
+    fn default() -> Self {
+        MemoryHighTunerConfig {
+            interval: Duration::from_secs(6),
+            pressure_target: Duration::from_millis(10),
+            min_high: 100 * 1024 * 1024,
+            max_probe: 0.01,
+            max_backoff: 1.0,
+            coeff_probe: 10.0,
+            coeff_backoff: 20.0,
+        }
+    }
+}
+
+/// The outcome of a single `MemoryHighTuner::step()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryHighTune {
+    /// Stall time since the previous step, in microseconds.
+    pub pressure_delta: u64,
+    /// Memory usage of the control group at the time of the step, in bytes.
+    pub usage: u64,
+    /// The value written to `memory.high`, in bytes.
+    pub high: u64,
+}
+
+/// Adjusts `memory.high` of a control group until its memory pressure reaches a target.
+///
+/// ## Example:
+///
+/// ```text
+/// let (stop_tx, stop_rx) = std::sync::mpsc::channel();
+/// let mut tuner = MemoryHighTuner::new(&cg, MemoryHighTunerConfig::default())?;
+/// std::thread::spawn(move || tuner.run(&stop_rx));
+/// // [...]
+/// stop_tx.send(()).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct MemoryHighTuner {
+    controller: MemController,
+    config: MemoryHighTunerConfig,
+    last_total: Option<u64>,
+    high: u64,
+}
+
+impl MemoryHighTuner {
+    /// Creates a tuner for the memory controller of `cg`.
+    ///
+    /// Nothing is written to the control group until `step()` or `run()` is called.
+    pub fn new(cg: &Cgroup, config: MemoryHighTunerConfig) -> Result<Self> {
+        if !cg.v2() {
+            return Err(Error::new(CgroupVersion));
+        }
+        let controller: &MemController = cg.controller_of().ok_or(Error::new(SubsystemsEmpty))?;
+
+        Ok(MemoryHighTuner {
+            controller: controller.clone(),
+            config,
+            last_total: None,
+            high: 0,
+        })
+    }
+
+    /// The configuration of the tuner.
+    pub fn config(&self) -> &MemoryHighTunerConfig {
+        &self.config
+    }
+
+    /// The last value written to `memory.high`, or 0 if `step()` was never called.
+    pub fn high(&self) -> u64 {
+        self.high
+    }
+
+    /// Samples the memory pressure and adjusts `memory.high` once.
+    ///
+    /// The first step only records the pressure and sets `memory.high` to the current usage,
+    /// since the stall rate is not known yet.
+    pub fn step(&mut self) -> Result<MemoryHighTune> {
+        let total = self.controller.memory_pressure()?.some.total;
+        let usage = self.controller.usage_in_bytes()?;
+        let ceiling = match self.controller.get_max_value("memory.max")? {
+            MaxValue::Max => i64::MAX as u64,
+            MaxValue::Value(v) => v.max(0) as u64,
+        };
+
+        let (pressure_delta, high) = match self.last_total {
+            None => (0, usage),
+            Some(last) => {
+                let delta = total.saturating_sub(last);
+                (
+                    delta,
+                    next_memory_high(self.high, usage, delta, &self.config),
+                )
+            }
+        };
+        // The floor gives way to memory.max, the kernel wouldn't allow more anyway.
+        let high = high.max(self.config.min_high).min(ceiling);
+
+        self.controller.set_mem(SetMemory {
+            high: Some(MaxValue::Value(high as i64)),
+            ..Default::default()
+        })?;
+        self.last_total = Some(total);
+        self.high = high;
+
+        Ok(MemoryHighTune {
+            pressure_delta,
+            usage,
+            high,
+        })
+    }
+
+    /// Calls `step()` every interval until a message is received on `stop` or its sender is
+    /// dropped. `memory.high` is left at its last value, see `reset()`.
+    pub fn run(&mut self, stop: &Receiver<()>) -> Result<()> {
+        loop {
+            self.step()?;
+            match stop.recv_timeout(self.config.interval) {
+                Err(RecvTimeoutError::Timeout) => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Removes the limit set by the tuner by writing `max` to `memory.high`.
+    pub fn reset(&mut self) -> Result<()> {
+        self.controller.set_mem(SetMemory {
+            high: Some(MaxValue::Max),
+            ..Default::default()
+        })?;
+        self.last_total = None;
+        self.high = 0;
+        Ok(())
+    }
+}
+
+/// Computes the next value of `memory.high` from the current one and the stall time (in
+/// microseconds) observed during the last interval.
+///
+/// The adjustment grows with the cube of the relative distance to the target, so that the value
+/// settles close to the target and reacts quickly to sudden stalls.
+fn next_memory_high(
+    high: u64,
+    usage: u64,
+    pressure_delta: u64,
+    config: &MemoryHighTunerConfig,
+) -> u64 {
+    let target = (config.pressure_target.as_micros() as f64).max(1.0);
+    let delta = pressure_delta as f64;
+
+    if delta <= target {
+        // Probing below the usage is what causes reclaim, anything above is just slack.
+        let high = high.min(usage) as f64;
+        let error = (target - delta) / target;
+        let adj = (config.coeff_probe * error.powi(3)).min(config.max_probe);
+        (high * (1.0 - adj)) as u64
+    } else {
+        let error = (delta - target) / target;
+        let adj = (config.coeff_backoff * error.powi(3)).min(config.max_backoff);
+        (high as f64 * (1.0 + adj)) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::memory_tuner::{next_memory_high, MemoryHighTunerConfig};
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    #[test]
+    fn test_next_memory_high() {
+        let config = MemoryHighTunerConfig::default();
+
+        // no pressure at all: probe down by max_probe
+        let probed = (GIB as f64 * 0.99) as u64;
+        assert_eq!(next_memory_high(GIB, GIB, 0, &config), probed);
+        // probing starts from the usage if it's lower than memory.high
+        assert_eq!(next_memory_high(2 * GIB, GIB, 0, &config), probed);
+        // exactly on target: keep the value
+        assert_eq!(next_memory_high(GIB, GIB, 10_000, &config), GIB);
+        // slightly above target: back off a bit
+        let high = next_memory_high(GIB, GIB, 11_000, &config);
+        assert!(high > GIB && high < GIB + GIB / 10);
+        // heavy stalls: back off by max_backoff
+        assert_eq!(next_memory_high(GIB, GIB, 1_000_000, &config), 2 * GIB);
+    }
+}
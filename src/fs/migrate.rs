@@ -0,0 +1,223 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+//! This module contains the migration of all the processes of a control group to another one.
+//!
+//! The source is frozen first, so that its processes can't fork children that would be left
+//! behind, then its processes are moved in rounds until it's empty: a process that forked before
+//! the freeze took effect, or failed to move, is moved in the next round. Processes are
+//! identified by pidfds, opened before checking they're still in the source, so that a recycled
+//! PID is never moved on kernels 5.3+. At last, the destination is thawed, and so is the source
+//! unless it was frozen beforehand.
+//!
+//! See `Cgroup::migrate()`.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::fs::cgroup::{next_backoff, Cgroup, CgroupType};
+use crate::fs::error::ErrorKind::*;
+use crate::fs::error::*;
+use crate::fs::freezer::FreezerController;
+use crate::fs::memory::MemController;
+use crate::pidfd::open_members;
+use crate::{CgroupPid, FreezerState};
+
+/// Options of `Cgroup::migrate()`.
+#[derive(Debug, Clone, Copy)]
+pub struct MigrateOptions {
+    /// Freeze the source while migrating, and thaw the destination afterwards, as well as the
+    /// source unless it was already frozen. Requires the freezer controller on v1.
+    pub freeze: bool,
+    /// How long to wait for the source to be frozen before the first round. Processes forking
+    /// meanwhile are moved in later rounds.
+    pub freeze_wait: Duration,
+    /// The value to write to `memory.move_charge_at_immigrate` of the destination before moving
+    /// the processes, e.g. 3 to move the charges of both anonymous and file pages. Only
+    /// supported for v1 cgroups, on kernels that didn't remove charge moving yet.
+    pub move_charge: Option<u64>,
+    /// How many rounds of moves are made before giving up on the processes left in the source.
+    pub rounds: u32,
+    /// The delay between two rounds, doubled after every round up to `cgroup::MAX_BACKOFF`.
+    pub backoff: Duration,
+}
+
+impl Default for MigrateOptions {
+    fn default() -> Self {
+        MigrateOptions {
+            freeze: true,
+            freeze_wait: Duration::from_secs(1),
+            move_charge: None,
+            rounds: 8,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+/// A process or thread `Cgroup::migrate()` failed to move.
+#[derive(Debug)]
+pub struct MigrateFailure {
+    /// The process, or the thread on threaded subtrees.
+    pub pid: CgroupPid,
+    /// The error of the last attempt.
+    pub error: Error,
+}
+
+/// The outcome of `Cgroup::migrate()`.
+#[derive(Debug, Default)]
+pub struct MigrateReport {
+    /// The processes, or the threads on threaded subtrees, that were moved.
+    pub moved: Vec<CgroupPid>,
+    /// The processes or threads left in the source.
+    pub failed: Vec<MigrateFailure>,
+    /// Whether threads were moved instead of processes.
+    pub threads: bool,
+    /// How many rounds of moves were made.
+    pub rounds: u32,
+}
+
+impl MigrateReport {
+    /// Whether the source was emptied.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+fn freezer(cg: &Cgroup) -> Result<&FreezerController> {
+    cg.controller_of::<FreezerController>()
+        .ok_or_else(|| Error::from_string("no freezer controller attached".to_string()))
+}
+
+fn is_frozen(cg: &Cgroup) -> bool {
+    if cg.v2() {
+        cg.events().map(|e| e.frozen).unwrap_or(false)
+    } else {
+        matches!(
+            freezer(cg).and_then(|f| f.state()),
+            Ok(FreezerState::Frozen)
+        )
+    }
+}
+
+/// Moves the processes, or threads, of `pids` that are still in `src`. Returns the outcome for
+/// each of them, `Ok(false)` if it exited before it could be moved. Processes that exited or left
+/// `src` before a handle to them was opened are left out.
+fn move_round(
+    src: &Cgroup,
+    dest: &Cgroup,
+    pids: &[CgroupPid],
+    threads: bool,
+) -> Vec<(CgroupPid, Result<bool>)> {
+    let move_all = |add: &dyn Fn(CgroupPid) -> Result<()>| {
+        pids.iter()
+            .map(|pid| (*pid, add(*pid).map(|_| true)))
+            .collect()
+    };
+    if threads {
+        // Threads can't be opened as pidfds before kernel 6.9.
+        return move_all(&|pid| dest.add_task(pid));
+    }
+    // A PID recycled since it was listed isn't listed anymore once the handle is opened, unless
+    // the new process is in `src` too.
+    let pidfds = match open_members(pids, || src.procs()) {
+        Some(pidfds) => pidfds,
+        None => return move_all(&|pid| dest.add_task_by_tgid(pid)),
+    };
+    pidfds
+        .iter()
+        .map(|pidfd| {
+            let pid = pidfd.pid();
+            let ret = match dest.add_task_by_pidfd(pidfd) {
+                Err(e) if *e.kind() == ProcessExited(pid.pid) => Ok(false),
+                ret => ret.map(|_| true),
+            };
+            (pid, ret)
+        })
+        .collect()
+}
+
+pub(crate) fn migrate(src: &Cgroup, dest: &Cgroup, opts: &MigrateOptions) -> Result<MigrateReport> {
+    // Threaded cgroups only accept threads.
+    let threads = dest.v2() && dest.cgroup_type()? == CgroupType::Threaded;
+    let list = || if threads { src.tasks() } else { src.procs() };
+
+    if let Some(bits) = opts.move_charge {
+        dest.controller_of::<MemController>()
+            .ok_or_else(|| Error::from_string("no memory controller attached".to_string()))?
+            .set_move_charge_at_immigrate(bits)?;
+    }
+
+    let mut was_frozen = false;
+    if opts.freeze {
+        let f = freezer(src)?;
+        was_frozen = !matches!(f.state()?, FreezerState::Thawed);
+        f.freeze()?;
+        // Freezing may take a while, processes forking meanwhile are moved in later rounds.
+        let deadline = Instant::now() + opts.freeze_wait;
+        let mut delay = opts.backoff;
+        while !is_frozen(src) {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            std::thread::sleep(delay.min(left));
+            delay = next_backoff(delay);
+        }
+    }
+
+    let mut report = MigrateReport {
+        threads,
+        ..Default::default()
+    };
+    let mut errors = HashMap::new();
+    let mut delay = opts.backoff;
+    while report.rounds < opts.rounds {
+        let pids = list();
+        if pids.is_empty() {
+            break;
+        }
+        if report.rounds > 0 {
+            std::thread::sleep(delay);
+            delay = next_backoff(delay);
+        }
+        report.rounds += 1;
+
+        for (pid, ret) in move_round(src, dest, &pids, threads) {
+            match ret {
+                Ok(moved) => {
+                    errors.remove(&pid);
+                    if moved {
+                        report.moved.push(pid);
+                    }
+                }
+                Err(e) => {
+                    errors.insert(pid, e);
+                }
+            }
+        }
+    }
+    report.moved.sort();
+    report.moved.dedup();
+    report.failed = list()
+        .into_iter()
+        .map(|pid| MigrateFailure {
+            pid,
+            error: errors.remove(&pid).unwrap_or_else(|| {
+                Error::from_string(format!("{} is still in the source", pid.pid))
+            }),
+        })
+        .collect();
+
+    if opts.freeze {
+        // The destination is thawed even if the source can't be.
+        let src_thawed = if was_frozen {
+            Ok(())
+        } else {
+            freezer(src).and_then(|f| f.thaw())
+        };
+        let dest_thawed = freezer(dest).map_or(Ok(()), |f| f.thaw());
+        src_thawed.and(dest_thawed)?;
+    }
+    Ok(report)
+}
@@ -0,0 +1,225 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+//! This module contains the implementation of the `misc` cgroup subsystem.
+//!
+//! The `misc` controller limits scalar resources which can't be abstracted like the other
+//! resources, e.g. the ASIDs used by AMD SEV and SEV-ES or Intel TDX guests.
+//!
+//! See the Kernel's documentation for more information about this subsystem, found at:
+//!  [Documentation/admin-guide/cgroup-v2.rst](https://www.kernel.org/doc/Documentation/admin-guide/cgroup-v2.rst)
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::fs::dirfd::DirFd;
+use crate::fs::error::ErrorKind::*;
+use crate::fs::error::*;
+
+use crate::fs::{
+    parse_max_value, read_string_from, ControllIdentifier, ControllerInternal, Controllers,
+    CustomizedAttribute, MaxValue, MiscResources, Resources, Subsystem,
+};
+
+/// A controller that allows controlling the `misc` subsystem of a Cgroup.
+///
+/// In essence, it limits the usage of scalar resources (e.g. `sev`, `sev_es`, `tdx`) by the
+/// control group's tasks.
+#[derive(Debug, Clone)]
+pub struct MiscController {
+    base: PathBuf,
+    path: PathBuf,
+    dir: DirFd,
+    v2: bool,
+}
+
+impl ControllerInternal for MiscController {
+    fn control_type(&self) -> Controllers {
+        Controllers::Misc
+    }
+    fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+    fn get_path_mut(&mut self) -> &mut PathBuf {
+        &mut self.path
+    }
+    fn get_base(&self) -> &PathBuf {
+        &self.base
+    }
+
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
+    fn is_v2(&self) -> bool {
+        self.v2
+    }
+
+    fn apply(&self, res: &Resources) -> Result<()> {
+        // get the resources that apply to this controller
+        let res: &MiscResources = &res.misc;
+
+        for limit in &res.limits {
+            self.set_max(&limit.name, limit.max)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ControllIdentifier for MiscController {
+    fn controller_type() -> Controllers {
+        Controllers::Misc
+    }
+}
+
+impl<'a> TryFrom<&'a Subsystem> for &'a MiscController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a MiscController> {
+        match sub {
+            Subsystem::Misc(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
+        }
+    }
+}
+
+/// Parses the `<resource> <value>` lines of `misc.capacity` and `misc.current`.
+fn parse_misc_values(s: String) -> Result<HashMap<String, u64>> {
+    s.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(
+            |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [name, value] => value
+                    .parse::<u64>()
+                    .map(|v| (name.to_string(), v))
+                    .map_err(|e| Error::with_cause(ParseError, e)),
+                _ => Err(Error::new(ParseError)),
+            },
+        )
+        .collect()
+}
+
+/// Parses the `<resource> <max>` lines of `misc.max`.
+fn parse_misc_max(s: String) -> Result<HashMap<String, MaxValue>> {
+    s.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(
+            |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [name, value] => parse_max_value(value).map(|v| (name.to_string(), v)),
+                _ => Err(Error::new(ParseError)),
+            },
+        )
+        .collect()
+}
+
+/// Parses the `<resource>.max <count>` lines of `misc.events`.
+fn parse_misc_events(s: String) -> Result<HashMap<String, u64>> {
+    parse_misc_values(s).map(|events| {
+        events
+            .into_iter()
+            .filter_map(|(k, v)| k.strip_suffix(".max").map(|k| (k.to_string(), v)))
+            .collect()
+    })
+}
+
+impl MiscController {
+    /// Constructs a new `MiscController` with `root` serving as the root of the control group.
+    pub fn new(point: PathBuf, root: PathBuf, v2: bool) -> Self {
+        Self {
+            base: root,
+            path: point,
+            dir: DirFd::default(),
+            v2,
+        }
+    }
+
+    /// The total amount of each resource available on the host.
+    ///
+    /// Note that `misc.capacity` only exists in the root control group.
+    pub fn capacity(&self) -> Result<HashMap<String, u64>> {
+        self.open_path("misc.capacity", false)
+            .and_then(read_string_from)
+            .and_then(parse_misc_values)
+    }
+
+    /// The current usage of each resource by the control group and its descendants.
+    pub fn current(&self) -> Result<HashMap<String, u64>> {
+        self.open_path("misc.current", false)
+            .and_then(read_string_from)
+            .and_then(parse_misc_values)
+    }
+
+    /// The maximum usage of each resource by the control group and its descendants.
+    pub fn max(&self) -> Result<HashMap<String, MaxValue>> {
+        self.open_path("misc.max", false)
+            .and_then(read_string_from)
+            .and_then(parse_misc_max)
+    }
+
+    /// Limit the usage of the resource `name` (e.g. `sev`) by the control group and its
+    /// descendants.
+    pub fn set_max(&self, name: &str, max: MaxValue) -> Result<()> {
+        let content = format!("{} {}", name, max);
+        self.open_path("misc.max", true).and_then(|mut file| {
+            file.write_all(content.as_ref())
+                .map_err(|e| Error::with_cause(WriteFailed("misc.max".to_string(), content), e))
+        })
+    }
+
+    /// The number of times the usage of each resource was about to go over its maximum.
+    pub fn events(&self) -> Result<HashMap<String, u64>> {
+        self.open_path("misc.events", false)
+            .and_then(read_string_from)
+            .and_then(parse_misc_events)
+    }
+}
+
+impl CustomizedAttribute for MiscController {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::fs::misc::{parse_misc_events, parse_misc_max, parse_misc_values};
+    use crate::fs::MaxValue;
+
+    #[test]
+    fn test_parse_misc_values() {
+        let ok = parse_misc_values("sev 50\nsev_es 10\n".to_string()).unwrap();
+        assert_eq!(
+            ok,
+            HashMap::from([("sev".to_string(), 50), ("sev_es".to_string(), 10)])
+        );
+        assert_eq!(parse_misc_values("".to_string()).unwrap(), HashMap::new());
+        assert!(parse_misc_values("sev max\n".to_string()).is_err());
+        assert!(parse_misc_values("sev\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_parse_misc_max() {
+        let ok = parse_misc_max("sev 10\nsev_es max\n".to_string()).unwrap();
+        assert_eq!(
+            ok,
+            HashMap::from([
+                ("sev".to_string(), MaxValue::Value(10)),
+                ("sev_es".to_string(), MaxValue::Max)
+            ])
+        );
+        assert!(parse_misc_max("sev ten\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_parse_misc_events() {
+        let ok = parse_misc_events("sev.max 3\nsev_es.max 0\n".to_string()).unwrap();
+        assert_eq!(
+            ok,
+            HashMap::from([("sev".to_string(), 3), ("sev_es".to_string(), 0)])
+        );
+    }
+}
@@ -35,16 +35,26 @@ macro_rules! update {
 pub mod blkio;
 pub mod cgroup;
 pub mod cgroup_builder;
+pub mod command;
 pub mod cpu;
 pub mod cpuacct;
 pub mod cpuset;
+pub mod delegate;
 pub mod devices;
+mod dirfd;
 pub mod error;
 pub mod events;
 pub mod freezer;
+pub mod guard;
 pub mod hierarchies;
 pub mod hugetlb;
+pub mod io_cost;
 pub mod memory;
+pub mod memory_tuner;
+pub mod migrate;
+pub mod misc;
+pub mod mount;
+pub mod namespace;
 pub mod net_cls;
 pub mod net_prio;
 pub mod perf_event;
@@ -62,6 +72,7 @@ use crate::fs::error::*;
 use crate::fs::freezer::FreezerController;
 use crate::fs::hugetlb::HugeTlbController;
 use crate::fs::memory::MemController;
+use crate::fs::misc::MiscController;
 use crate::fs::net_cls::NetClsController;
 use crate::fs::net_prio::NetPrioController;
 use crate::fs::perf_event::PerfEventController;
@@ -101,6 +112,8 @@ pub enum Subsystem {
     HugeTlb(HugeTlbController),
     /// Controller for the `Rdma` subsystem, see `RdmaController` for more information.
     Rdma(RdmaController),
+    /// Controller for the `Misc` subsystem, see `MiscController` for more information.
+    Misc(MiscController),
     /// Controller for the `Systemd` subsystem, see `SystemdController` for more information.
     Systemd(SystemdController),
 }
@@ -121,6 +134,7 @@ pub enum Controllers {
     NetPrio,
     HugeTlb,
     Rdma,
+    Misc,
     Systemd,
 }
 
@@ -140,6 +154,7 @@ impl fmt::Display for Controllers {
             Controllers::NetPrio => write!(f, "net_prio"),
             Controllers::HugeTlb => write!(f, "hugetlb"),
             Controllers::Rdma => write!(f, "rdma"),
+            Controllers::Misc => write!(f, "misc"),
             Controllers::Systemd => write!(f, "name=systemd"),
         }
     }
@@ -156,6 +171,7 @@ mod sealed {
         fn get_path(&self) -> &PathBuf;
         fn get_path_mut(&mut self) -> &mut PathBuf;
         fn get_base(&self) -> &PathBuf;
+        fn get_dir(&self) -> &crate::fs::dirfd::DirFd;
 
         /// Hooks running after controller crated, if have
         fn post_create(&self) {}
@@ -164,30 +180,10 @@ mod sealed {
             false
         }
 
+        /// Open an interface file of the control group, refusing to follow symlinks or to leave
+        /// the directory of the control group.
         fn open_path(&self, p: &str, w: bool) -> Result<File> {
-            let mut path = self.get_path().clone();
-            path.push(p);
-
-            if w {
-                match File::create(&path) {
-                    Err(e) => Err(Error::with_cause(
-                        ErrorKind::WriteFailed(
-                            path.display().to_string(),
-                            "[CREATE FILE]".to_string(),
-                        ),
-                        e,
-                    )),
-                    Ok(file) => Ok(file),
-                }
-            } else {
-                match File::open(&path) {
-                    Err(e) => Err(Error::with_cause(
-                        ErrorKind::ReadFailed(path.display().to_string()),
-                        e,
-                    )),
-                    Ok(file) => Ok(file),
-                }
-            }
+            self.get_dir().open(self.get_path(), p, w)
         }
 
         fn get_max_value(&self, f: &str) -> Result<MaxValue> {
@@ -352,6 +348,7 @@ where
 
     /// Delete the controller.
     fn delete(&self) -> Result<()> {
+        self.get_dir().reset();
         if !self.get_path().exists() {
             return Ok(());
         }
@@ -534,11 +531,22 @@ pub trait Hierarchy: std::fmt::Debug + Send + Sync {
     fn parent_control_group(&self, path: &str) -> Cgroup;
 
     fn v2(&self) -> bool;
+
+    /// Returns the root directory of the cgroup v2 hierarchy: the root of the hierarchy on v2,
+    /// the unified mount in hybrid mode and `None` on v1.
+    fn unified_root(&self) -> Option<PathBuf> {
+        if self.v2() {
+            Some(self.root())
+        } else {
+            None
+        }
+    }
 }
 
 /// Resource limits for the memory subsystem.
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct MemoryResources {
     /// How much memory (in bytes) can the kernel consume.
     pub kernel_memory_limit: Option<i64>,
@@ -571,6 +579,7 @@ pub struct MemoryResources {
 /// Resources limits on the number of processes.
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct PidResources {
     /// The maximum number of processes that can exist in the control group.
     ///
@@ -583,6 +592,7 @@ pub struct PidResources {
 /// Resources limits about how the tasks can use the CPU.
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct CpuResources {
     // cpuset
     /// A comma-separated list of CPU IDs where the task in the control group can run. Dashes
@@ -632,6 +642,7 @@ pub struct DeviceResource {
 /// Limit the usage of devices for the control group's tasks.
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct DeviceResources {
     /// For each device in the list, the limits in the structure are applied.
     pub devices: Vec<DeviceResource>,
@@ -651,6 +662,7 @@ pub struct NetworkPriority {
 /// control group.
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct NetworkResources {
     /// The networking class identifier to attach to the packets.
     ///
@@ -674,9 +686,12 @@ pub struct HugePageResource {
 /// Provides the ability to set consumption limit on each type of hugepages.
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct HugePageResources {
     /// Set a limit of consumption for each hugepages type.
     pub limits: Vec<HugePageResource>,
+    /// Set a limit of reservation for each hugepages type.
+    pub rsvd_limits: Vec<HugePageResource>,
 }
 
 /// Weight for a particular block device.
@@ -705,9 +720,34 @@ pub struct BlkIoDeviceThrottleResource {
     pub rate: u64,
 }
 
+/// Weight of a particular block device in `io.weight`.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlkIoDeviceWeightResource {
+    /// The major number of the device.
+    pub major: u64,
+    /// The minor number of the device.
+    pub minor: u64,
+    /// The weight, in the range `[1, 10000]`.
+    pub weight: u16,
+}
+
+/// Latency target of a particular block device in `io.latency`.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlkIoDeviceLatencyResource {
+    /// The major number of the device.
+    pub major: u64,
+    /// The minor number of the device.
+    pub minor: u64,
+    /// The latency target, in microseconds.
+    pub target: u64,
+}
+
 /// General block I/O resource limits.
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct BlkIoResources {
     /// The weight of the control group against descendant nodes.
     pub weight: Option<u16>,
@@ -723,6 +763,14 @@ pub struct BlkIoResources {
     pub throttle_write_bps_device: Vec<BlkIoDeviceThrottleResource>,
     /// Throttled write IO operations per second can be provided for each device.
     pub throttle_write_iops_device: Vec<BlkIoDeviceThrottleResource>,
+    /// The default weight in `io.weight` (cgroup v2 only).
+    pub io_weight: Option<u16>,
+    /// Per-device weights in `io.weight` (cgroup v2 only).
+    pub io_weight_device: Vec<BlkIoDeviceWeightResource>,
+    /// Per-device latency targets in `io.latency` (cgroup v2 only).
+    pub latency_device: Vec<BlkIoDeviceLatencyResource>,
+    /// The I/O priority class policy in `io.prio.class` (cgroup v2 only).
+    pub prio_class: Option<crate::fs::blkio::IoPrioClass>,
 
     /// Customized key-value attributes
     /// # Usage:
@@ -734,9 +782,66 @@ pub struct BlkIoResources {
     pub attrs: HashMap<String, String>,
 }
 
+/// A limit on a scalar resource of the `misc` controller.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MiscResource {
+    /// The name of the resource, e.g. `sev` or `sev_es`.
+    pub name: String,
+    /// The maximum usage of the resource.
+    pub max: MaxValue,
+}
+
+/// Provides the ability to limit the usage of scalar resources, e.g. SEV ASIDs.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct MiscResources {
+    /// Set a limit for each resource.
+    pub limits: Vec<MiscResource>,
+}
+
+/// Limits on the sub-hierarchy below a control group, enforced by the core of cgroup v2.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct HierarchyResources {
+    /// The maximum allowed depth of the sub-hierarchy (`cgroup.max.depth`).
+    pub max_depth: Option<MaxValue>,
+    /// The maximum allowed number of descendants (`cgroup.max.descendants`).
+    pub max_descendants: Option<MaxValue>,
+}
+
+/// Limits of the RDMA/IB resources of a particular device.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RdmaResource {
+    /// The name of the device, e.g. `mlx4_0`.
+    pub device: String,
+    /// The maximum number of HCA handles.
+    pub hca_handle: Option<MaxValue>,
+    /// The maximum number of HCA objects.
+    pub hca_object: Option<MaxValue>,
+}
+
+/// Provides the ability to limit the RDMA/IB resources of each device.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RdmaResources {
+    /// Set a limit for each device.
+    pub limits: Vec<RdmaResource>,
+}
+
 /// The resource limits and constraints that will be set on the control group.
+///
+/// When deserialized, the fields left out of it, and of its collections of limits like
+/// `MemoryResources`, are left unset like with `Resources::default()`, so that a spec only lists
+/// the limits it sets. The entries of the lists, e.g. `DeviceResource`, must be complete since
+/// they have no meaningful default.
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct Resources {
     /// Memory usage related limits.
     pub memory: MemoryResources,
@@ -752,10 +857,18 @@ pub struct Resources {
     pub hugepages: HugePageResources,
     /// Block device I/O related limits.
     pub blkio: BlkIoResources,
+    /// Miscellaneous scalar resources related limits.
+    pub misc: MiscResources,
+    /// RDMA/IB resources related limits.
+    pub rdma: RdmaResources,
+    /// Limits on the sub-hierarchy of the control group.
+    pub hierarchy: HierarchyResources,
 }
 
 impl Subsystem {
     fn enter(self, path: &Path) -> Self {
+        // Pushing an absolute path would replace the path of the controller.
+        let path = path.strip_prefix("/").unwrap_or(path);
         match self {
             Subsystem::Pid(mut cont) => Subsystem::Pid({
                 cont.get_path_mut().push(path);
@@ -809,6 +922,10 @@ impl Subsystem {
                 cont.get_path_mut().push(path);
                 cont
             }),
+            Subsystem::Misc(mut cont) => Subsystem::Misc({
+                cont.get_path_mut().push(path);
+                cont
+            }),
             Subsystem::Systemd(mut cont) => Subsystem::Systemd({
                 cont.get_path_mut().push(path);
                 cont
@@ -831,10 +948,31 @@ impl Subsystem {
             Subsystem::NetPrio(cont) => cont,
             Subsystem::HugeTlb(cont) => cont,
             Subsystem::Rdma(cont) => cont,
+            Subsystem::Misc(cont) => cont,
             Subsystem::Systemd(cont) => cont,
         }
     }
 
+    pub(crate) fn get_dir(&self) -> &dirfd::DirFd {
+        match self {
+            Subsystem::Pid(cont) => cont.get_dir(),
+            Subsystem::Mem(cont) => cont.get_dir(),
+            Subsystem::CpuSet(cont) => cont.get_dir(),
+            Subsystem::CpuAcct(cont) => cont.get_dir(),
+            Subsystem::Cpu(cont) => cont.get_dir(),
+            Subsystem::Devices(cont) => cont.get_dir(),
+            Subsystem::Freezer(cont) => cont.get_dir(),
+            Subsystem::NetCls(cont) => cont.get_dir(),
+            Subsystem::BlkIo(cont) => cont.get_dir(),
+            Subsystem::PerfEvent(cont) => cont.get_dir(),
+            Subsystem::NetPrio(cont) => cont.get_dir(),
+            Subsystem::HugeTlb(cont) => cont.get_dir(),
+            Subsystem::Rdma(cont) => cont.get_dir(),
+            Subsystem::Misc(cont) => cont.get_dir(),
+            Subsystem::Systemd(cont) => cont.get_dir(),
+        }
+    }
+
     pub fn controller_name(&self) -> String {
         self.to_controller().control_type().to_string()
     }
@@ -886,6 +1024,73 @@ pub fn parse_max_value(s: &str) -> Result<MaxValue> {
     }
 }
 
+/// One line of a pressure stall information (PSI) file, such as `memory.pressure`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PressureData {
+    /// Share of wall time stalled over the last 10 seconds, in percent.
+    pub avg10: f64,
+    /// Share of wall time stalled over the last 60 seconds, in percent.
+    pub avg60: f64,
+    /// Share of wall time stalled over the last 300 seconds, in percent.
+    pub avg300: f64,
+    /// Total stall time in microseconds.
+    pub total: u64,
+}
+
+/// The content of a PSI file, see `Documentation/accounting/psi.rst`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PressureStat {
+    /// Time in which at least some tasks were stalled.
+    pub some: PressureData,
+    /// Time in which all non-idle tasks were stalled simultaneously. Left as
+    /// default if the kernel doesn't report it.
+    pub full: PressureData,
+}
+
+// Pressure
+//  some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n
+//  full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n
+pub fn parse_pressure(s: &str) -> Result<PressureStat> {
+    let mut stat = PressureStat::default();
+    for line in s.lines() {
+        let mut parts = line.split_whitespace();
+        let data = match parts.next() {
+            Some("some") => &mut stat.some,
+            Some("full") => &mut stat.full,
+            _ => continue,
+        };
+        for item in parts {
+            let (key, value) = item.split_once('=').ok_or(Error::new(ParseError))?;
+            match key {
+                "avg10" => {
+                    data.avg10 = value
+                        .parse()
+                        .map_err(|e| Error::with_cause(ParseError, e))?
+                }
+                "avg60" => {
+                    data.avg60 = value
+                        .parse()
+                        .map_err(|e| Error::with_cause(ParseError, e))?
+                }
+                "avg300" => {
+                    data.avg300 = value
+                        .parse()
+                        .map_err(|e| Error::with_cause(ParseError, e))?
+                }
+                "total" => {
+                    data.total = value
+                        .parse()
+                        .map_err(|e| Error::with_cause(ParseError, e))?
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(stat)
+}
+
 // Flat keyed
 //  KEY0 VAL0\n
 //  KEY1 VAL1\n
@@ -993,3 +1198,42 @@ fn read_u64_from(file: File) -> Result<u64> {
 fn read_i64_from(file: File) -> Result<i64> {
     read_from::<i64>(file)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::{parse_pressure, PressureData, PressureStat};
+
+    static GOOD_PRESSURE_VAL: &str = "\
+some avg10=1.50 avg60=0.25 avg300=0.05 total=123456
+full avg10=0.00 avg60=0.10 avg300=0.00 total=7890
+";
+
+    #[test]
+    fn test_parse_pressure() {
+        let ok = parse_pressure(GOOD_PRESSURE_VAL).unwrap();
+        assert_eq!(
+            ok,
+            PressureStat {
+                some: PressureData {
+                    avg10: 1.5,
+                    avg60: 0.25,
+                    avg300: 0.05,
+                    total: 123456,
+                },
+                full: PressureData {
+                    avg10: 0.0,
+                    avg60: 0.1,
+                    avg300: 0.0,
+                    total: 7890,
+                },
+            }
+        );
+
+        // "full" is missing on older kernels
+        let ok = parse_pressure("some avg10=0.00 avg60=0.00 avg300=0.00 total=42\n").unwrap();
+        assert_eq!(ok.some.total, 42);
+        assert_eq!(ok.full, PressureData::default());
+
+        assert!(parse_pressure("some avg10=abc avg60=0.00 avg300=0.00 total=0").is_err());
+    }
+}
@@ -0,0 +1,549 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+//! This module contains helpers to mount the cgroup filesystems in the root filesystem of a
+//! container.
+//!
+//! On v2, a cgroup2 filesystem is mounted to `/sys/fs/cgroup`. On v1, a tmpfs is mounted there,
+//! with one directory per hierarchy, e.g. `memory` or `cpu,cpuacct`, symlinks for co-mounted
+//! controllers (`cpu` and `cpuacct` pointing to `cpu,cpuacct`) and, in hybrid mode, the unified
+//! hierarchy at `unified`.
+//!
+//! If the caller runs in the cgroup namespace of the container, fresh cgroup filesystems are
+//! mounted, rooted at the control group of the container. Otherwise the directories of the
+//! control group are bind mounted.
+//!
+//! `plan()` computes the steps without touching the system, e.g. to log them or to perform them
+//! in another process, and `MountPlan::perform()` runs them.
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::fs::dirfd::open_beneath;
+use crate::fs::error::ErrorKind::*;
+use crate::fs::error::*;
+use crate::fs::hierarchies::{mountinfo_self, Mountinfo};
+use crate::fs::{Cgroup, Hierarchy};
+
+/// Where the cgroup filesystems are mounted to, relative to the root filesystem.
+pub const CGROUP_MOUNT_DIR: &str = "sys/fs/cgroup";
+
+const MOUNT_FLAGS: libc::c_ulong =
+    libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC | libc::MS_RELATIME;
+
+/// Options of the cgroup mounts of a container.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct MountOptions {
+    /// Mount the cgroup filesystems read-only.
+    pub readonly: bool,
+    /// Mount fresh cgroup filesystems instead of bind mounting the directories of the control
+    /// group. Only meaningful if the caller runs in the cgroup namespace of the container.
+    pub cgroupns: bool,
+    /// Mount the cgroup2 filesystem with `nsdelegate`. Ignored by the kernel outside of the
+    /// initial cgroup namespace.
+    pub nsdelegate: bool,
+}
+
+impl Default for MountOptions {
+    fn default() -> Self {
+        MountOptions {
+            readonly: true,
+            cgroupns: false,
+            nsdelegate: true,
+        }
+    }
+}
+
+/// A step of a `MountPlan`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MountStep {
+    /// Create a directory and its parents.
+    Mkdir(PathBuf),
+    /// Call `mount(2)`. Empty strings are passed as NULL.
+    Mount {
+        source: String,
+        target: PathBuf,
+        fs_type: String,
+        flags: libc::c_ulong,
+        data: String,
+    },
+    /// Create the symlink `link` pointing to `target`.
+    Symlink { link: PathBuf, target: PathBuf },
+}
+
+/// The steps to mount the cgroup filesystems in a root filesystem.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MountPlan {
+    /// The root filesystem of the container.
+    pub rootfs: PathBuf,
+    /// The steps, in order.
+    pub steps: Vec<MountStep>,
+}
+
+/// Computes the mounts of the cgroup filesystems of `cg`, in the hierarchy `hier`, in the root
+/// filesystem `rootfs`.
+pub fn plan(
+    cg: &Cgroup,
+    hier: &dyn Hierarchy,
+    rootfs: &Path,
+    opts: &MountOptions,
+) -> Result<MountPlan> {
+    let target = rootfs.join(CGROUP_MOUNT_DIR);
+    let rel = cg.path().trim_start_matches('/');
+    let steps = if hier.v2() {
+        v2_steps(&hier.root().join(rel), &target, opts)
+    } else {
+        let dirs = cg
+            .subsystems()
+            .iter()
+            .map(|sub| sub.to_controller().path().to_path_buf())
+            .collect::<Vec<_>>();
+        let unified = hier.unified_root().map(|root| root.join(rel));
+        v1_steps(&mountinfo_self(), &dirs, unified.as_deref(), &target, opts)
+    };
+    Ok(MountPlan {
+        rootfs: rootfs.to_path_buf(),
+        steps,
+    })
+}
+
+/// Computes and performs the mounts of the cgroup filesystems of `cg`, see `plan()`.
+pub fn mount(
+    cg: &Cgroup,
+    hier: &dyn Hierarchy,
+    rootfs: &Path,
+    opts: &MountOptions,
+) -> Result<MountPlan> {
+    let plan = plan(cg, hier, rootfs, opts)?;
+    plan.perform()?;
+    Ok(plan)
+}
+
+/// The steps to mount a cgroup2 filesystem, or to bind mount `dir`, to `target`.
+fn v2_steps(dir: &Path, target: &Path, opts: &MountOptions) -> Vec<MountStep> {
+    let mut flags = MOUNT_FLAGS;
+    if opts.readonly {
+        flags |= libc::MS_RDONLY;
+    }
+
+    let mut steps = vec![MountStep::Mkdir(target.to_path_buf())];
+    if opts.cgroupns {
+        steps.push(MountStep::Mount {
+            source: "cgroup2".to_string(),
+            target: target.to_path_buf(),
+            fs_type: "cgroup2".to_string(),
+            flags,
+            data: if opts.nsdelegate {
+                "nsdelegate".to_string()
+            } else {
+                String::new()
+            },
+        });
+    } else {
+        steps.extend(bind_steps(dir, target, flags));
+    }
+    steps
+}
+
+fn bind_steps(dir: &Path, target: &Path, flags: libc::c_ulong) -> Vec<MountStep> {
+    vec![
+        MountStep::Mount {
+            source: dir.to_string_lossy().to_string(),
+            target: target.to_path_buf(),
+            fs_type: String::new(),
+            flags: libc::MS_BIND | libc::MS_REC,
+            data: String::new(),
+        },
+        // The flags of a bind mount can only be changed by remounting it.
+        MountStep::Mount {
+            source: String::new(),
+            target: target.to_path_buf(),
+            fs_type: String::new(),
+            flags: libc::MS_BIND | libc::MS_REMOUNT | flags,
+            data: String::new(),
+        },
+    ]
+}
+
+/// The steps to mount a tmpfs to `target`, with the v1 hierarchies in which `dirs`, the
+/// directories of the control group, are in.
+fn v1_steps(
+    mounts: &[Mountinfo],
+    dirs: &[PathBuf],
+    unified: Option<&Path>,
+    target: &Path,
+    opts: &MountOptions,
+) -> Vec<MountStep> {
+    let mut flags = MOUNT_FLAGS;
+    if opts.readonly {
+        flags |= libc::MS_RDONLY;
+    }
+
+    let mut steps = vec![
+        MountStep::Mkdir(target.to_path_buf()),
+        MountStep::Mount {
+            source: "tmpfs".to_string(),
+            target: target.to_path_buf(),
+            fs_type: "tmpfs".to_string(),
+            flags: MOUNT_FLAGS,
+            data: "mode=755".to_string(),
+        },
+    ];
+
+    let mut seen = HashSet::new();
+    let mut links = vec![];
+    for dir in dirs {
+        // The deepest mount the directory is in.
+        let mi = match mounts
+            .iter()
+            .filter(|m| m.fs_type.0 == "cgroup" && dir.starts_with(&m.mount_point))
+            .max_by_key(|m| m.mount_point.components().count())
+        {
+            Some(mi) => mi,
+            None => continue,
+        };
+        let name = match mi.mount_point.file_name() {
+            Some(name) => PathBuf::from(name),
+            None => continue,
+        };
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+
+        let sub_target = target.join(&name);
+        steps.push(MountStep::Mkdir(sub_target.clone()));
+        if opts.cgroupns {
+            let data = mi
+                .super_opts
+                .iter()
+                .filter(|o| *o != "rw" && *o != "ro")
+                .filter(|o| !o.contains('=') || o.starts_with("name="))
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(",");
+            steps.push(MountStep::Mount {
+                source: "cgroup".to_string(),
+                target: sub_target,
+                fs_type: "cgroup".to_string(),
+                flags,
+                data,
+            });
+        } else {
+            steps.extend(bind_steps(dir, &sub_target, flags));
+        }
+
+        // Co-mounted controllers, e.g. `cpu -> cpu,cpuacct`.
+        let name = name.to_string_lossy().to_string();
+        if name.contains(',') {
+            for controller in name.split(',') {
+                links.push(MountStep::Symlink {
+                    link: target.join(controller),
+                    target: PathBuf::from(&name),
+                });
+            }
+        }
+    }
+    steps.extend(links);
+
+    if let Some(unified) = unified {
+        let sub_target = target.join("unified");
+        let mut unified_steps = v2_steps(unified, &sub_target, opts);
+        if let Some(MountStep::Mount { data, .. }) = unified_steps.get_mut(1) {
+            if opts.cgroupns {
+                // nsdelegate can't be set on a cgroup2 filesystem in hybrid mode.
+                data.clear();
+            }
+        }
+        steps.extend(unified_steps);
+    }
+
+    if opts.readonly {
+        steps.push(MountStep::Mount {
+            source: String::new(),
+            target: target.to_path_buf(),
+            fs_type: String::new(),
+            flags: libc::MS_REMOUNT | flags,
+            data: "mode=755".to_string(),
+        });
+    }
+    steps
+}
+
+fn cstring(s: &[u8]) -> Result<Option<CString>> {
+    if s.is_empty() {
+        return Ok(None);
+    }
+    CString::new(s)
+        .map(Some)
+        .map_err(|e| Error::with_cause(InvalidPath, e))
+}
+
+impl MountPlan {
+    /// Opens the directory `dir`, see `dirfd::open_beneath()`. Fails with `InvalidPath` if it
+    /// isn't in the root filesystem, and if one of its components below the root filesystem is a
+    /// symlink, which the container could have planted to redirect the mounts.
+    fn open_target(&self, dir: &Path) -> Result<File> {
+        open_beneath(&self.rootfs, dir)
+    }
+
+    /// Creates the directory `dir` and its missing parents, each one in its opened parent.
+    fn mkdir(&self, dir: &Path) -> Result<()> {
+        let rel = dir
+            .strip_prefix(&self.rootfs)
+            .map_err(|_| Error::new(InvalidPath))?;
+        let mut parent = self.rootfs.clone();
+        for c in rel.components() {
+            let fd = self.open_target(&parent)?;
+            let name = cstring(c.as_os_str().as_bytes())?.ok_or_else(|| Error::new(InvalidPath))?;
+            if unsafe { libc::mkdirat(fd.as_raw_fd(), name.as_ptr(), 0o755) } != 0 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() != Some(libc::EEXIST) {
+                    return Err(Error::with_cause(FsError, err));
+                }
+            }
+            parent.push(c);
+        }
+        self.open_target(dir).map(|_| ())
+    }
+
+    /// Performs the steps of the plan in order, stopping at the first one that fails.
+    pub fn perform(&self) -> Result<()> {
+        for step in &self.steps {
+            match step {
+                MountStep::Mkdir(dir) => self.mkdir(dir)?,
+                MountStep::Mount {
+                    source,
+                    target,
+                    fs_type,
+                    flags,
+                    data,
+                } => {
+                    // Mount onto the opened directory, so that it can't be swapped for a symlink.
+                    let fd = self.open_target(target)?;
+                    let source = cstring(source.as_bytes())?;
+                    let target_c = cstring(format!("/proc/self/fd/{}", fd.as_raw_fd()).as_bytes())?;
+                    let fs_type = cstring(fs_type.as_bytes())?;
+                    let data = cstring(data.as_bytes())?;
+                    let ptr =
+                        |s: &Option<CString>| s.as_ref().map_or(std::ptr::null(), |s| s.as_ptr());
+                    let ret = unsafe {
+                        libc::mount(
+                            ptr(&source),
+                            ptr(&target_c),
+                            ptr(&fs_type),
+                            *flags,
+                            ptr(&data) as *const libc::c_void,
+                        )
+                    };
+                    if ret != 0 {
+                        return Err(Error::with_cause(
+                            Common(format!("unable to mount {}", target.display())),
+                            io::Error::last_os_error(),
+                        ));
+                    }
+                }
+                MountStep::Symlink { link, target } => {
+                    let (parent, name) = match (link.parent(), link.file_name()) {
+                        (Some(parent), Some(name)) => (parent, name),
+                        _ => return Err(Error::new(InvalidPath)),
+                    };
+                    let fd = self.open_target(parent)?;
+                    let name = cstring(name.as_bytes())?.ok_or_else(|| Error::new(InvalidPath))?;
+                    let target = cstring(target.as_os_str().as_bytes())?
+                        .ok_or_else(|| Error::new(InvalidPath))?;
+                    if unsafe { libc::symlinkat(target.as_ptr(), fd.as_raw_fd(), name.as_ptr()) }
+                        != 0
+                    {
+                        return Err(Error::with_cause(FsError, io::Error::last_os_error()));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::fs::hierarchies::Mountinfo;
+    use crate::fs::mount::{v1_steps, v2_steps, MountOptions, MountPlan, MountStep};
+
+    fn mount(point: &str, opts: &[&str]) -> Mountinfo {
+        Mountinfo {
+            mount_root: PathBuf::from("/"),
+            mount_point: PathBuf::from(point),
+            fs_type: ("cgroup".to_string(), None),
+            super_opts: opts.iter().map(|o| o.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn targets(steps: &[MountStep]) -> Vec<String> {
+        steps
+            .iter()
+            .map(|s| match s {
+                MountStep::Mkdir(dir) => format!("mkdir {}", dir.display()),
+                MountStep::Mount {
+                    source,
+                    target,
+                    fs_type,
+                    data,
+                    ..
+                } => format!("mount {} {} {} {}", fs_type, source, target.display(), data),
+                MountStep::Symlink { link, target } => {
+                    format!("ln {} {}", target.display(), link.display())
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_v2_steps() {
+        let target = Path::new("/rootfs/sys/fs/cgroup");
+        let opts = MountOptions {
+            cgroupns: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            targets(&v2_steps(Path::new("/sys/fs/cgroup/ctr"), target, &opts)),
+            vec![
+                "mkdir /rootfs/sys/fs/cgroup",
+                "mount cgroup2 cgroup2 /rootfs/sys/fs/cgroup nsdelegate",
+            ]
+        );
+
+        let steps = v2_steps(
+            Path::new("/sys/fs/cgroup/ctr"),
+            target,
+            &MountOptions::default(),
+        );
+        assert_eq!(
+            targets(&steps),
+            vec![
+                "mkdir /rootfs/sys/fs/cgroup",
+                "mount  /sys/fs/cgroup/ctr /rootfs/sys/fs/cgroup ",
+                "mount   /rootfs/sys/fs/cgroup ",
+            ]
+        );
+        match &steps[2] {
+            MountStep::Mount { flags, .. } => assert!(flags & libc::MS_RDONLY != 0),
+            _ => panic!("unexpected step"),
+        }
+    }
+
+    #[test]
+    fn test_v1_steps() {
+        let mounts = vec![
+            mount("/sys/fs/cgroup/cpu,cpuacct", &["rw", "cpu", "cpuacct"]),
+            mount("/sys/fs/cgroup/memory", &["rw", "memory"]),
+            mount(
+                "/sys/fs/cgroup/systemd",
+                &["rw", "xattr", "release_agent=/x", "name=systemd"],
+            ),
+        ];
+        let dirs = vec![
+            PathBuf::from("/sys/fs/cgroup/cpu,cpuacct/ctr"),
+            PathBuf::from("/sys/fs/cgroup/cpu,cpuacct/ctr"),
+            PathBuf::from("/sys/fs/cgroup/memory/ctr"),
+            PathBuf::from("/sys/fs/cgroup/systemd/ctr"),
+        ];
+        let target = Path::new("/rootfs/sys/fs/cgroup");
+        let opts = MountOptions {
+            readonly: false,
+            cgroupns: true,
+            nsdelegate: true,
+        };
+        assert_eq!(
+            targets(&v1_steps(
+                &mounts,
+                &dirs,
+                Some(Path::new("/sys/fs/cgroup/unified/ctr")),
+                target,
+                &opts
+            )),
+            vec![
+                "mkdir /rootfs/sys/fs/cgroup",
+                "mount tmpfs tmpfs /rootfs/sys/fs/cgroup mode=755",
+                "mkdir /rootfs/sys/fs/cgroup/cpu,cpuacct",
+                "mount cgroup cgroup /rootfs/sys/fs/cgroup/cpu,cpuacct cpu,cpuacct",
+                "mkdir /rootfs/sys/fs/cgroup/memory",
+                "mount cgroup cgroup /rootfs/sys/fs/cgroup/memory memory",
+                "mkdir /rootfs/sys/fs/cgroup/systemd",
+                "mount cgroup cgroup /rootfs/sys/fs/cgroup/systemd xattr,name=systemd",
+                "ln cpu,cpuacct /rootfs/sys/fs/cgroup/cpu",
+                "ln cpu,cpuacct /rootfs/sys/fs/cgroup/cpuacct",
+                "mkdir /rootfs/sys/fs/cgroup/unified",
+                "mount cgroup2 cgroup2 /rootfs/sys/fs/cgroup/unified ",
+            ]
+        );
+
+        // Bind mounts of the directories, and the tmpfs made read-only at last.
+        let steps = v1_steps(&mounts[1..2], &dirs, None, target, &MountOptions::default());
+        assert_eq!(
+            targets(&steps),
+            vec![
+                "mkdir /rootfs/sys/fs/cgroup",
+                "mount tmpfs tmpfs /rootfs/sys/fs/cgroup mode=755",
+                "mkdir /rootfs/sys/fs/cgroup/memory",
+                "mount  /sys/fs/cgroup/memory/ctr /rootfs/sys/fs/cgroup/memory ",
+                "mount   /rootfs/sys/fs/cgroup/memory ",
+                "mount   /rootfs/sys/fs/cgroup mode=755",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_perform_refuses_symlinks() {
+        let rootfs = std::env::temp_dir().join(format!("cgroups-rs-mount-{}", std::process::id()));
+        std::fs::create_dir_all(rootfs.join("sys/fs")).unwrap();
+        std::os::unix::fs::symlink("/tmp", rootfs.join("sys/fs/cgroup")).unwrap();
+
+        let plan = MountPlan {
+            rootfs: rootfs.clone(),
+            steps: vec![MountStep::Mkdir(rootfs.join("sys/fs/cgroup/memory"))],
+        };
+        assert!(plan.perform().is_err());
+        let plan = MountPlan {
+            rootfs: rootfs.clone(),
+            steps: vec![MountStep::Mkdir(PathBuf::from("/elsewhere"))],
+        };
+        assert!(plan.perform().is_err());
+        let plan = MountPlan {
+            rootfs: rootfs.clone(),
+            steps: vec![MountStep::Mount {
+                source: "tmpfs".to_string(),
+                target: rootfs.join("sys/fs/cgroup"),
+                fs_type: "tmpfs".to_string(),
+                flags: 0,
+                data: String::new(),
+            }],
+        };
+        assert!(plan.perform().is_err());
+        assert!(!Path::new("/tmp/memory").exists());
+
+        // Directories and symlinks are created in the opened parents.
+        std::fs::remove_file(rootfs.join("sys/fs/cgroup")).unwrap();
+        let plan = MountPlan {
+            rootfs: rootfs.clone(),
+            steps: vec![
+                MountStep::Mkdir(rootfs.join("sys/fs/cgroup/cpu,cpuacct")),
+                MountStep::Symlink {
+                    link: rootfs.join("sys/fs/cgroup/cpu"),
+                    target: PathBuf::from("cpu,cpuacct"),
+                },
+            ],
+        };
+        plan.perform().unwrap();
+        assert!(rootfs.join("sys/fs/cgroup/cpu/").is_dir());
+
+        std::fs::remove_dir_all(&rootfs).unwrap();
+    }
+}
@@ -0,0 +1,311 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+//! This module contains helpers for cgroup namespaces.
+//!
+//! In a cgroup namespace, `/proc/<pid>/cgroup` shows paths relative to the root of the namespace,
+//! i.e. the control group the namespace was created in. The root field of mountinfo shows the
+//! root of each cgroup mount relative to the same namespace root: e.g. `/` for a mount made in
+//! the namespace, and `/../..` for a mount of the whole hierarchy made outside of it, with the
+//! namespace root two levels below the root of the hierarchy.
+//!
+//! `NamespacePaths` uses both to translate between the paths of the namespace and the paths
+//! relative to the mount points, i.e. the ones `Cgroup::load()` expects.
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::fs::cgroup::get_cgroups_relative_paths;
+use crate::fs::error::ErrorKind::*;
+use crate::fs::error::*;
+use crate::fs::hierarchies::{parse_mountinfo, Mountinfo};
+
+/// The inode number of the initial cgroup namespace (`CGROUP_NS_INIT_INO`).
+pub const INIT_CGROUP_NS_INO: u64 = 0xEFFF_FFFB;
+
+/// Whether the current process runs in a cgroup namespace other than the initial one.
+pub fn is_namespaced() -> bool {
+    fs::metadata("/proc/self/ns/cgroup")
+        .map(|m| m.ino() != INIT_CGROUP_NS_INO)
+        .unwrap_or(false)
+}
+
+/// Moves the current process into a new cgroup namespace, rooted at its current control groups.
+///
+/// Only the calling thread is moved if the process has several. Requires `CAP_SYS_ADMIN` and
+/// kernel 4.6+. See `CgroupCommandExt::cgroup_ns()` to do so for a child process.
+pub fn unshare() -> Result<()> {
+    if unsafe { libc::unshare(libc::CLONE_NEWCGROUP) } != 0 {
+        return Err(Error::with_cause(
+            Common("unable to unshare the cgroup namespace".to_string()),
+            io::Error::last_os_error(),
+        ));
+    }
+    Ok(())
+}
+
+/// The namespace roots found by `mapping_for()`, keyed by the inode of the cgroup namespace, and
+/// the ID and the point of the mount.
+type NamespaceRoots = HashMap<(u64, u32, PathBuf), PathBuf>;
+
+static NAMESPACE_ROOTS: Mutex<Option<NamespaceRoots>> = Mutex::new(None);
+
+/// How the paths of a namespace map to the paths below a mount point.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Mapping {
+    /// The root of the mount, relative to the namespace root. Stripped from namespace paths.
+    ns_prefix: PathBuf,
+    /// The namespace root, relative to the mount point. Prepended to namespace paths.
+    mount_prefix: PathBuf,
+}
+
+/// Translates between the cgroup paths of the namespace of the current process and the paths
+/// relative to the cgroup mount points.
+///
+/// Subsystems are keyed like in `/proc/self/cgroup`, e.g. `memory` or `name=systemd` for v1
+/// and `""` for the v2 hierarchy. Outside of a cgroup namespace, or with cgroupfs mounted in the
+/// namespace, the mapping is the identity, except for mounts of a subtree like the ones of
+/// containers.
+#[derive(Debug, Clone, Default)]
+pub struct NamespacePaths {
+    mappings: HashMap<String, Mapping>,
+}
+
+impl NamespacePaths {
+    /// Builds the mapping of the current process from `/proc/self/cgroup` and
+    /// `/proc/self/mountinfo`.
+    pub fn current() -> Result<Self> {
+        let cgroups = get_cgroups_relative_paths()?;
+        let content = fs::read_to_string("/proc/self/mountinfo")
+            .map_err(|e| Error::with_cause(ReadFailed("/proc/self/mountinfo".to_string()), e))?;
+        Ok(Self::from_parts(
+            &parse_mountinfo(&content),
+            &cgroups,
+            std::process::id(),
+        ))
+    }
+
+    fn from_parts(mounts: &[Mountinfo], cgroups: &HashMap<String, String>, pid: u32) -> Self {
+        let mut mappings = HashMap::new();
+        for mi in mounts.iter().filter(|m| m.is_cgroup()) {
+            let keys = if mi.fs_type.0 == "cgroup2" {
+                vec![String::new()]
+            } else {
+                mi.super_opts
+                    .iter()
+                    .filter(|o| !o.is_empty() && cgroups.contains_key(*o))
+                    .cloned()
+                    .collect()
+            };
+            for key in keys {
+                if mappings.contains_key(&key) {
+                    continue;
+                }
+                let own = match cgroups.get(&key) {
+                    Some(own) => own,
+                    None => continue,
+                };
+                // Mounts from which the namespace root can't be reached are skipped.
+                if let Some(mapping) = mapping_for(mi, own, pid) {
+                    mappings.insert(key, mapping);
+                }
+            }
+        }
+        NamespacePaths { mappings }
+    }
+
+    fn mapping(&self, subsystem: &str) -> Result<&Mapping> {
+        self.mappings
+            .get(subsystem)
+            .ok_or_else(|| Error::from_string(format!("no cgroup mount found for {:?}", subsystem)))
+    }
+
+    /// Translates `path`, as seen in `/proc/<pid>/cgroup` from the namespace, to the path
+    /// relative to the mount point of `subsystem`.
+    ///
+    /// Fails with `InvalidPath` if the control group isn't visible through the mount.
+    pub fn to_hierarchy_path(&self, subsystem: &str, path: &str) -> Result<String> {
+        let m = self.mapping(subsystem)?;
+        let rel = Path::new(path.trim_start_matches('/'));
+        let rel = rel
+            .strip_prefix(&m.ns_prefix)
+            .map_err(|_| Error::new(InvalidPath))?;
+        Ok(absolute(&m.mount_prefix, rel))
+    }
+
+    /// Translates `path`, relative to the mount point of `subsystem`, to the path seen in
+    /// `/proc/<pid>/cgroup` from the namespace.
+    ///
+    /// Fails with `InvalidPath` if the control group is outside of the namespace.
+    pub fn to_namespace_path(&self, subsystem: &str, path: &str) -> Result<String> {
+        let m = self.mapping(subsystem)?;
+        let rel = Path::new(path.trim_start_matches('/'));
+        let rel = rel
+            .strip_prefix(&m.mount_prefix)
+            .map_err(|_| Error::new(InvalidPath))?;
+        Ok(absolute(&m.ns_prefix, rel))
+    }
+}
+
+/// Joins `prefix` and `rel` into an absolute path, without a trailing slash if `rel` is empty.
+fn absolute(prefix: &Path, rel: &Path) -> String {
+    let mut path = PathBuf::from("/");
+    for p in [prefix, rel] {
+        if !p.as_os_str().is_empty() {
+            path.push(p);
+        }
+    }
+    path.to_string_lossy().to_string()
+}
+
+/// Finds how the paths of the namespace map to the mount `mi`, `own` being the control group of
+/// the process `pid` in the namespace.
+fn mapping_for(mi: &Mountinfo, own: &str, pid: u32) -> Option<Mapping> {
+    let mut components = mi
+        .mount_root
+        .components()
+        .filter(|c| *c != Component::RootDir)
+        .peekable();
+    let mut ups = 0;
+    while components.peek() == Some(&Component::ParentDir) {
+        components.next();
+        ups += 1;
+    }
+    let rest = components.collect::<PathBuf>();
+
+    if ups == 0 {
+        return Some(Mapping {
+            ns_prefix: rest,
+            mount_prefix: PathBuf::new(),
+        });
+    }
+    if !rest.as_os_str().is_empty() {
+        // The mount is in another branch of the hierarchy.
+        return None;
+    }
+
+    // The namespace root is `ups` levels below the mount point: it's the directory at that
+    // depth in which the process is found at its own path. It's looked for once per namespace and
+    // mount, and then only checked, since the hierarchy may be large.
+    let own = Path::new(own.trim_start_matches('/'));
+    let contains = |root: &Path| {
+        fs::read_to_string(root.join(own).join("cgroup.procs"))
+            .map(|procs| procs.lines().any(|l| l.trim() == pid.to_string()))
+            .unwrap_or(false)
+    };
+    let key = (
+        fs::metadata("/proc/self/ns/cgroup")
+            .map(|m| m.ino())
+            .unwrap_or_default(),
+        mi.mount_id,
+        mi.mount_point.clone(),
+    );
+    let mut roots = NAMESPACE_ROOTS.lock().unwrap_or_else(|e| e.into_inner());
+    let roots = roots.get_or_insert_with(HashMap::new);
+    let root = match roots.get(&key).filter(|root| contains(root)) {
+        Some(root) => root.clone(),
+        None => {
+            let root = find_root(&mi.mount_point, ups, &contains)?;
+            roots.insert(key, root.clone());
+            root
+        }
+    };
+    root.strip_prefix(&mi.mount_point)
+        .ok()
+        .map(|mount_prefix| Mapping {
+            ns_prefix: PathBuf::new(),
+            mount_prefix: mount_prefix.to_path_buf(),
+        })
+}
+
+/// Finds the first directory `depth` levels below `dir` for which `found` holds, depth first.
+fn find_root(dir: &Path, depth: usize, found: &dyn Fn(&Path) -> bool) -> Option<PathBuf> {
+    if depth == 0 {
+        return Some(dir.to_path_buf()).filter(|d| found(d));
+    }
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+        .find_map(|e| find_root(&e.path(), depth - 1, found))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use crate::fs::error::ErrorKind;
+    use crate::fs::hierarchies::Mountinfo;
+    use crate::fs::namespace::NamespacePaths;
+
+    fn mount(root: &str, point: &Path, fs_type: &str, opts: &[&str]) -> Mountinfo {
+        Mountinfo {
+            mount_root: PathBuf::from(root),
+            mount_point: point.to_path_buf(),
+            fs_type: (fs_type.to_string(), None),
+            super_opts: opts.iter().map(|o| o.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_namespace_paths() {
+        let dir = std::env::temp_dir().join(format!("cgroups-rs-cgroupns-{}", std::process::id()));
+        // The namespace root is "a/b" in the v2 hierarchy, and the process is in "c" below it.
+        // A decoy "a/x/c" doesn't contain the process.
+        fs::create_dir_all(dir.join("a/b/c")).unwrap();
+        fs::create_dir_all(dir.join("a/x/c")).unwrap();
+        fs::write(dir.join("a/b/c/cgroup.procs"), "1\n4242\n").unwrap();
+        fs::write(dir.join("a/x/c/cgroup.procs"), "1\n").unwrap();
+
+        let cgroups = HashMap::from([
+            (String::new(), "/c".to_string()),
+            ("memory".to_string(), "/kubepods/pod1".to_string()),
+            ("pids".to_string(), "/".to_string()),
+        ]);
+        let mounts = vec![
+            mount("/../..", &dir, "cgroup2", &["rw", "nsdelegate"]),
+            mount(
+                "/kubepods",
+                &PathBuf::from("/sys/fs/cgroup/memory"),
+                "cgroup",
+                &["rw", "memory"],
+            ),
+            mount("/../other", &dir, "cgroup", &["rw", "pids"]),
+        ];
+        let ns = NamespacePaths::from_parts(&mounts, &cgroups, 4242);
+
+        assert_eq!(ns.to_hierarchy_path("", "/c/d").unwrap(), "/a/b/c/d");
+        assert_eq!(ns.to_hierarchy_path("", "/").unwrap(), "/a/b");
+        assert_eq!(ns.to_namespace_path("", "/a/b/c").unwrap(), "/c");
+        let err = ns.to_namespace_path("", "/a/x/c").unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::InvalidPath);
+
+        assert_eq!(
+            ns.to_hierarchy_path("memory", "/kubepods/pod1").unwrap(),
+            "/pod1"
+        );
+        assert_eq!(
+            ns.to_namespace_path("memory", "pod1").unwrap(),
+            "/kubepods/pod1"
+        );
+        let err = ns.to_hierarchy_path("memory", "/system.slice").unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::InvalidPath);
+
+        assert!(ns.to_hierarchy_path("pids", "/").is_err());
+
+        // The namespace root found is checked before it's reused.
+        fs::rename(dir.join("a/b"), dir.join("a/y")).unwrap();
+        let ns = NamespacePaths::from_parts(&mounts, &cgroups, 4242);
+        assert_eq!(ns.to_hierarchy_path("", "/c/d").unwrap(), "/a/y/c/d");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
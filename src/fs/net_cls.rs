@@ -7,9 +7,11 @@
 //!
 //! See the Kernel's documentation for more information about this subsystem, found at:
 //!  [Documentation/cgroup-v1/net_cls.txt](https://www.kernel.org/doc/Documentation/cgroup-v1/net_cls.txt)
+use std::convert::TryFrom;
 use std::io::Write;
 use std::path::PathBuf;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::ErrorKind::*;
 use crate::fs::error::*;
 
@@ -27,6 +29,7 @@ use crate::fs::{
 pub struct NetClsController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
 }
 
 impl ControllerInternal for NetClsController {
@@ -43,6 +46,10 @@ impl ControllerInternal for NetClsController {
         &self.base
     }
 
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
     fn apply(&self, res: &Resources) -> Result<()> {
         // get the resources that apply to this controller
         let res: &NetworkResources = &res.network;
@@ -59,17 +66,15 @@ impl ControllIdentifier for NetClsController {
     }
 }
 
-impl<'a> From<&'a Subsystem> for &'a NetClsController {
-    fn from(sub: &'a Subsystem) -> &'a NetClsController {
-        unsafe {
-            match sub {
-                Subsystem::NetCls(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a NetClsController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a NetClsController> {
+        match sub {
+            Subsystem::NetCls(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
@@ -80,6 +85,7 @@ impl NetClsController {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
         }
     }
 
@@ -8,9 +8,11 @@
 //! See the Kernel's documentation for more information about this subsystem, found at:
 //!  [Documentation/cgroup-v1/net_prio.txt](https://www.kernel.org/doc/Documentation/cgroup-v1/net_prio.txt)
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::io::{BufRead, BufReader, Write};
 use std::path::PathBuf;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::ErrorKind::*;
 use crate::fs::error::*;
 
@@ -28,6 +30,7 @@ use crate::fs::{
 pub struct NetPrioController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
 }
 
 impl ControllerInternal for NetPrioController {
@@ -44,6 +47,10 @@ impl ControllerInternal for NetPrioController {
         &self.base
     }
 
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
     fn apply(&self, res: &Resources) -> Result<()> {
         // get the resources that apply to this controller
         let res: &NetworkResources = &res.network;
@@ -62,17 +69,15 @@ impl ControllIdentifier for NetPrioController {
     }
 }
 
-impl<'a> From<&'a Subsystem> for &'a NetPrioController {
-    fn from(sub: &'a Subsystem) -> &'a NetPrioController {
-        unsafe {
-            match sub {
-                Subsystem::NetPrio(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a NetPrioController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a NetPrioController> {
+        match sub {
+            Subsystem::NetPrio(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
@@ -83,6 +88,7 @@ impl NetPrioController {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
         }
     }
 
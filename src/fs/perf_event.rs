@@ -7,8 +7,10 @@
 //!
 //! See the Kernel's documentation for more information about this subsystem, found at:
 //!  [tools/perf/Documentation/perf-record.txt](https://raw.githubusercontent.com/torvalds/linux/master/tools/perf/Documentation/perf-record.txt)
+use std::convert::TryFrom;
 use std::path::PathBuf;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::*;
 
 use crate::fs::{ControllIdentifier, ControllerInternal, Controllers, Resources, Subsystem};
@@ -21,6 +23,7 @@ use crate::fs::{ControllIdentifier, ControllerInternal, Controllers, Resources,
 pub struct PerfEventController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
 }
 
 impl ControllerInternal for PerfEventController {
@@ -37,6 +40,10 @@ impl ControllerInternal for PerfEventController {
         &self.base
     }
 
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
     fn apply(&self, _res: &Resources) -> Result<()> {
         Ok(())
     }
@@ -48,17 +55,15 @@ impl ControllIdentifier for PerfEventController {
     }
 }
 
-impl<'a> From<&'a Subsystem> for &'a PerfEventController {
-    fn from(sub: &'a Subsystem) -> &'a PerfEventController {
-        unsafe {
-            match sub {
-                Subsystem::PerfEvent(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a PerfEventController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a PerfEventController> {
+        match sub {
+            Subsystem::PerfEvent(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
@@ -69,6 +74,7 @@ impl PerfEventController {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
         }
     }
 }
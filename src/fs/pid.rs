@@ -8,9 +8,11 @@
 //!
 //! See the Kernel's documentation for more information about this subsystem, found at:
 //!  [Documentation/cgroups-v1/pids.txt](https://www.kernel.org/doc/Documentation/cgroup-v1/pids.txt)
+use std::convert::TryFrom;
 use std::io::{Read, Write};
 use std::path::PathBuf;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::ErrorKind::*;
 use crate::fs::error::*;
 
@@ -25,6 +27,7 @@ use crate::fs::{
 pub struct PidController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
     v2: bool,
 }
 
@@ -42,6 +45,10 @@ impl ControllerInternal for PidController {
         &self.base
     }
 
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
     fn is_v2(&self) -> bool {
         self.v2
     }
@@ -74,17 +81,15 @@ impl ControllIdentifier for PidController {
     }
 }
 
-impl<'a> From<&'a Subsystem> for &'a PidController {
-    fn from(sub: &'a Subsystem) -> &'a PidController {
-        unsafe {
-            match sub {
-                Subsystem::Pid(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a PidController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a PidController> {
+        match sub {
+            Subsystem::Pid(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
@@ -96,6 +101,7 @@ impl PidController {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
             v2,
         }
     }
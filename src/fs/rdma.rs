@@ -7,14 +7,19 @@
 //!
 //! See the Kernel's documentation for more information about this subsystem, found at:
 //!  [Documentation/cgroup-v1/rdma.txt](https://www.kernel.org/doc/Documentation/cgroup-v1/rdma.txt)
+use std::convert::TryFrom;
 use std::io::Write;
 use std::path::PathBuf;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::ErrorKind::*;
 use crate::fs::error::*;
 
-use crate::fs::read_string_from;
-use crate::fs::{ControllIdentifier, ControllerInternal, Controllers, Resources, Subsystem};
+use crate::fs::{parse_max_value, read_string_from};
+use crate::fs::{
+    ControllIdentifier, ControllerInternal, Controllers, MaxValue, RdmaResources, Resources,
+    Subsystem,
+};
 
 /// A controller that allows controlling the `rdma` subsystem of a Cgroup.
 ///
@@ -24,6 +29,91 @@ use crate::fs::{ControllIdentifier, ControllerInternal, Controllers, Resources,
 pub struct RdmaController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
+    v2: bool,
+}
+
+/// The usage of the RDMA/IB resources of a device, as found in `rdma.current`.
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RdmaUsage {
+    /// The name of the device, e.g. `mlx4_0`.
+    pub device: String,
+    /// The number of HCA handles in use.
+    pub hca_handle: u64,
+    /// The number of HCA objects in use.
+    pub hca_object: u64,
+}
+
+/// The limits of the RDMA/IB resources of a device, as found in `rdma.max`.
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RdmaLimit {
+    /// The name of the device, e.g. `mlx4_0`.
+    pub device: String,
+    /// The maximum number of HCA handles.
+    pub hca_handle: MaxValue,
+    /// The maximum number of HCA objects.
+    pub hca_object: MaxValue,
+}
+
+/// Splits the `<device> hca_handle=<value> hca_object=<value>` lines of `rdma.current` and
+/// `rdma.max`, passing each value to `parse`.
+fn parse_rdma<T, F>(s: String, parse: F) -> Result<Vec<(String, T, T)>>
+where
+    F: Fn(&str) -> Result<T>,
+{
+    s.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = fields.next().ok_or_else(|| Error::new(ParseError))?;
+            let (mut handle, mut object) = (None, None);
+            for field in fields {
+                match field.split_once('=') {
+                    Some(("hca_handle", v)) => handle = Some(parse(v)?),
+                    Some(("hca_object", v)) => object = Some(parse(v)?),
+                    Some(_) => {}
+                    None => return Err(Error::new(ParseError)),
+                }
+            }
+            match (handle, object) {
+                (Some(handle), Some(object)) => Ok((device.to_string(), handle, object)),
+                _ => Err(Error::new(ParseError)),
+            }
+        })
+        .collect()
+}
+
+fn parse_rdma_current(s: String) -> Result<Vec<RdmaUsage>> {
+    // line:
+    // mlx4_0 hca_handle=1 hca_object=20
+    let entries = parse_rdma(s, |v| {
+        v.parse::<u64>()
+            .map_err(|e| Error::with_cause(ParseError, e))
+    })?;
+    Ok(entries
+        .into_iter()
+        .map(|(device, hca_handle, hca_object)| RdmaUsage {
+            device,
+            hca_handle,
+            hca_object,
+        })
+        .collect())
+}
+
+fn parse_rdma_max(s: String) -> Result<Vec<RdmaLimit>> {
+    // line:
+    // mlx4_0 hca_handle=2 hca_object=max
+    let entries = parse_rdma(s, parse_max_value)?;
+    Ok(entries
+        .into_iter()
+        .map(|(device, hca_handle, hca_object)| RdmaLimit {
+            device,
+            hca_handle,
+            hca_object,
+        })
+        .collect())
 }
 
 impl ControllerInternal for RdmaController {
@@ -40,7 +130,22 @@ impl ControllerInternal for RdmaController {
         &self.base
     }
 
-    fn apply(&self, _res: &Resources) -> Result<()> {
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
+    fn is_v2(&self) -> bool {
+        self.v2
+    }
+
+    fn apply(&self, res: &Resources) -> Result<()> {
+        // get the resources that apply to this controller
+        let res: &RdmaResources = &res.rdma;
+
+        for limit in &res.limits {
+            self.set_max(&limit.device, limit.hca_handle, limit.hca_object)?;
+        }
+
         Ok(())
     }
 }
@@ -51,47 +156,126 @@ impl ControllIdentifier for RdmaController {
     }
 }
 
-impl<'a> From<&'a Subsystem> for &'a RdmaController {
-    fn from(sub: &'a Subsystem) -> &'a RdmaController {
-        unsafe {
-            match sub {
-                Subsystem::Rdma(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a RdmaController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a RdmaController> {
+        match sub {
+            Subsystem::Rdma(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
 
 impl RdmaController {
     /// Constructs a new `RdmaController` with `root` serving as the root of the control group.
-    pub fn new(point: PathBuf, root: PathBuf) -> Self {
+    pub fn new(point: PathBuf, root: PathBuf, v2: bool) -> Self {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
+            v2,
         }
     }
 
-    /// Returns the current usage of RDMA/IB specific resources.
-    pub fn current(&self) -> Result<String> {
+    /// Returns the current usage of RDMA/IB specific resources, per device.
+    pub fn current(&self) -> Result<Vec<RdmaUsage>> {
         self.open_path("rdma.current", false)
             .and_then(read_string_from)
+            .and_then(parse_rdma_current)
     }
 
-    /// Returns the max usage of RDMA/IB specific resources.
-    pub fn max(&self) -> Result<String> {
-        self.open_path("rdma.max", false).and_then(read_string_from)
+    /// Returns the max usage of RDMA/IB specific resources, per device.
+    pub fn max(&self) -> Result<Vec<RdmaLimit>> {
+        self.open_path("rdma.max", false)
+            .and_then(read_string_from)
+            .and_then(parse_rdma_max)
     }
 
-    /// Set a maximum usage for each RDMA/IB resource.
-    pub fn set_max(&self, max: &str) -> Result<()> {
+    /// Set the maximum usage of the RDMA/IB resources of `device`. A resource set to `None` keeps
+    /// its current limit.
+    pub fn set_max(
+        &self,
+        device: &str,
+        hca_handle: Option<MaxValue>,
+        hca_object: Option<MaxValue>,
+    ) -> Result<()> {
+        if hca_handle.is_none() && hca_object.is_none() {
+            return Ok(());
+        }
+        let mut content = device.to_string();
+        if let Some(max) = hca_handle {
+            content.push_str(&format!(" hca_handle={}", max));
+        }
+        if let Some(max) = hca_object {
+            content.push_str(&format!(" hca_object={}", max));
+        }
+
         self.open_path("rdma.max", true).and_then(|mut file| {
-            file.write_all(max.as_ref()).map_err(|e| {
-                Error::with_cause(WriteFailed("rdma.max".to_string(), max.to_string()), e)
-            })
+            file.write_all(content.as_ref())
+                .map_err(|e| Error::with_cause(WriteFailed("rdma.max".to_string(), content), e))
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::error::ErrorKind;
+    use crate::fs::rdma::{parse_rdma_current, parse_rdma_max, RdmaLimit, RdmaUsage};
+    use crate::fs::MaxValue;
+
+    #[test]
+    fn test_parse_rdma_current() {
+        let ok = parse_rdma_current(
+            "mlx4_0 hca_handle=1 hca_object=20\nocrdma1 hca_handle=0 hca_object=0\n".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            ok,
+            vec![
+                RdmaUsage {
+                    device: "mlx4_0".to_string(),
+                    hca_handle: 1,
+                    hca_object: 20,
+                },
+                RdmaUsage {
+                    device: "ocrdma1".to_string(),
+                    hca_handle: 0,
+                    hca_object: 0,
+                },
+            ]
+        );
+
+        let err =
+            parse_rdma_current("mlx4_0 hca_handle=max hca_object=20\n".to_string()).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::ParseError);
+        assert!(parse_rdma_current("mlx4_0 hca_handle=1\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_parse_rdma_max() {
+        let ok = parse_rdma_max(
+            "mlx4_0 hca_handle=2 hca_object=2000\nocrdma1 hca_handle=3 hca_object=max\n"
+                .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            ok,
+            vec![
+                RdmaLimit {
+                    device: "mlx4_0".to_string(),
+                    hca_handle: MaxValue::Value(2),
+                    hca_object: MaxValue::Value(2000),
+                },
+                RdmaLimit {
+                    device: "ocrdma1".to_string(),
+                    hca_handle: MaxValue::Value(3),
+                    hca_object: MaxValue::Max,
+                },
+            ]
+        );
+        assert!(parse_rdma_max("mlx4_0 2 2000\n".to_string()).is_err());
+    }
+}
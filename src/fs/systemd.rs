@@ -5,8 +5,10 @@
 
 //! This module contains the implementation of the `systemd` cgroup subsystem.
 //!
+use std::convert::TryFrom;
 use std::path::PathBuf;
 
+use crate::fs::dirfd::DirFd;
 use crate::fs::error::*;
 
 use crate::fs::{ControllIdentifier, ControllerInternal, Controllers, Resources, Subsystem};
@@ -17,6 +19,7 @@ use crate::fs::{ControllIdentifier, ControllerInternal, Controllers, Resources,
 pub struct SystemdController {
     base: PathBuf,
     path: PathBuf,
+    dir: DirFd,
     _v2: bool,
 }
 
@@ -34,6 +37,10 @@ impl ControllerInternal for SystemdController {
         &self.base
     }
 
+    fn get_dir(&self) -> &DirFd {
+        &self.dir
+    }
+
     fn apply(&self, _res: &Resources) -> Result<()> {
         Ok(())
     }
@@ -45,17 +52,15 @@ impl ControllIdentifier for SystemdController {
     }
 }
 
-impl<'a> From<&'a Subsystem> for &'a SystemdController {
-    fn from(sub: &'a Subsystem) -> &'a SystemdController {
-        unsafe {
-            match sub {
-                Subsystem::Systemd(c) => c,
-                _ => {
-                    assert_eq!(1, 0);
-                    let v = std::mem::MaybeUninit::uninit();
-                    v.assume_init()
-                }
-            }
+impl<'a> TryFrom<&'a Subsystem> for &'a SystemdController {
+    type Error = Error;
+
+    fn try_from(sub: &'a Subsystem) -> Result<&'a SystemdController> {
+        match sub {
+            Subsystem::Systemd(c) => Ok(c),
+            _ => Err(Error::new(ErrorKind::UnexpectedSubsystem(
+                sub.controller_name(),
+            ))),
         }
     }
 }
@@ -66,6 +71,7 @@ impl SystemdController {
         Self {
             base: root,
             path: point,
+            dir: DirFd::default(),
             _v2: v2,
         }
     }
@@ -9,6 +9,10 @@ pub mod fs;
 pub mod manager;
 #[cfg(feature = "oci")]
 pub use manager::{FsManager, Manager, SystemdManager};
+pub mod pidfd;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+pub use pidfd::Pidfd;
 pub mod stats;
 pub use stats::CgroupStats;
 pub mod systemd;
@@ -29,9 +33,11 @@ pub enum FreezerState {
     Frozen,
 }
 
-/// A structure representing a `pid`. Currently implementations exist for `u64` and
-/// `std::process::Child`.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+/// A structure representing a `pid`. Currently implementations exist for `u64`,
+/// `std::process::Child` and `Pidfd`.
+///
+/// The PID may be recycled once the process exits, see `Pidfd` for a handle that can't be.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 pub struct CgroupPid {
     /// The process identifier
     pub pid: u64,
@@ -52,17 +52,25 @@ pub(crate) fn memory_swap_to_cgroup_v2(memswap_limit: i64, mem_limit: i64) -> Re
 
     // Unset or unknown memory, can't calculate swap.
     if mem_limit == 0 {
-        return Err(Error::InvalidLinuxResource);
+        return Err(Error::InvalidLinuxResource(
+            "memory+swap limit set without a memory limit".to_string(),
+        ));
     }
 
     // Does not make sense to subtract a negative value.
     if mem_limit < 0 {
-        return Err(Error::InvalidLinuxResource);
+        return Err(Error::InvalidLinuxResource(format!(
+            "invalid memory limit {}",
+            mem_limit
+        )));
     }
 
     // Sanity check.
     if memswap_limit < mem_limit {
-        return Err(Error::InvalidLinuxResource);
+        return Err(Error::InvalidLinuxResource(format!(
+            "memory+swap limit {} is lower than the memory limit {}",
+            memswap_limit, mem_limit
+        )));
     }
 
     Ok(memswap_limit - mem_limit)
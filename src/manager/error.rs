@@ -14,8 +14,11 @@ pub enum Error {
     #[error("invalid argument")]
     InvalidArgument,
 
-    #[error("invalid linux resource")]
-    InvalidLinuxResource,
+    #[error("invalid linux resource: {0}")]
+    InvalidLinuxResource(String),
+
+    #[error("process {0} exited")]
+    ProcessExited(u64),
 
     #[error("cgroupfs error: {0}")]
     Cgroupfs(#[from] CgroupfsError),
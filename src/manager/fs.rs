@@ -4,13 +4,14 @@
 //
 
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fs;
 use std::path::Path;
 use std::str::FromStr;
 
 use oci_spec::runtime::{
     LinuxBlockIo, LinuxCpu, LinuxDeviceCgroup, LinuxHugepageLimit, LinuxMemory, LinuxNetwork,
-    LinuxPids, LinuxResources,
+    LinuxPids, LinuxRdma, LinuxResources,
 };
 
 use crate::fs::blkio::{BlkIoController, BlkIoData, IoService, IoStat};
@@ -23,15 +24,21 @@ use crate::fs::error::{Error as FsError, ErrorKind as FsErrorKind, Result as FsR
 use crate::fs::freezer::FreezerController;
 use crate::fs::hugetlb::HugeTlbController;
 use crate::fs::memory::MemController;
+use crate::fs::misc::MiscController;
 use crate::fs::net_cls::NetClsController;
 use crate::fs::net_prio::NetPrioController;
 use crate::fs::pid::PidController;
-use crate::fs::{hierarchies, Cgroup, ControllIdentifier, Controller, MaxValue, Subsystem};
+use crate::fs::rdma::RdmaController;
+use crate::fs::{
+    hierarchies, parse_max_value, Cgroup, ControllIdentifier, Controller, MaxValue, Subsystem,
+};
 use crate::manager::error::Error;
 use crate::manager::{conv, Manager, Result};
+use crate::pidfd::open_members;
 use crate::stats::{
     BlkioCgroupStats, BlkioStat, CpuAcctStats, CpuCgroupStats, CpuThrottlingStats,
-    HugeTlbCgroupStats, HugeTlbStat, MemoryCgroupStats, MemoryStats, PidsCgroupStats,
+    HugeTlbCgroupStats, HugeTlbStat, MemoryCgroupStats, MemoryStats, MiscCgroupStats, MiscStat,
+    PidsCgroupStats, RdmaCgroupStats, RdmaStat,
 };
 use crate::{CgroupPid, CgroupStats, FreezerState};
 
@@ -47,8 +54,10 @@ pub struct FsManager {
     /// Cgroup subsystem paths read from `/proc/self/cgroup`
     /// - cgroup v1: <subsystem> -> <path>
     /// - cgroup v2: "" -> <path>
+    /// - hybrid: both, "" being the path in the unified hierarchy
     paths: HashMap<String, String>,
-    /// Cgroup mountpoints read from `/proc/self/mountinfo`.
+    /// Cgroup mountpoints read from `/proc/self/mountinfo`, with the same
+    /// keys as `paths`.
     mounts: HashMap<String, String>,
     /// Base path of the cgroup filesystem, the complete path would be:
     /// - cgroup v1: "/sys/fs/cgroup/<subsystem>/<base>"
@@ -116,7 +125,7 @@ impl FsManager {
 
     fn controller<'a, T>(&'a self) -> FsResult<&'a T>
     where
-        &'a T: From<&'a Subsystem>,
+        &'a T: TryFrom<&'a Subsystem>,
         T: Controller + ControllIdentifier,
     {
         let controller: &T = self
@@ -229,7 +238,10 @@ impl FsManager {
             if swappiness <= 100 {
                 controller.set_swappiness(swappiness)?;
             } else {
-                return Err(Error::InvalidLinuxResource);
+                return Err(Error::InvalidLinuxResource(format!(
+                    "memory swappiness {} is greater than 100",
+                    swappiness
+                )));
             };
         }
 
@@ -259,12 +271,18 @@ impl FsManager {
 
         // Rejecting: memory+swap limit <= usage
         if memswap_limit > 0 && memswap_limit as u64 <= usage_actual {
-            return Err(Error::InvalidLinuxResource);
+            return Err(Error::InvalidLinuxResource(format!(
+                "memory+swap limit {} is not greater than the usage {}",
+                memswap_limit, usage_actual
+            )));
         }
 
         // Rejecting: memory limit <= usage
         if mem_limit > 0 && mem_limit as u64 <= usage_actual {
-            return Err(Error::InvalidLinuxResource);
+            return Err(Error::InvalidLinuxResource(format!(
+                "memory limit {} is not greater than the usage {}",
+                mem_limit, usage_actual
+            )));
         }
 
         let swap_limit = conv::memory_swap_to_cgroup_v2(memswap_limit, mem_limit)?;
@@ -381,12 +399,52 @@ impl FsManager {
             }
             let page_size = limit.page_size();
             let limit = limit.limit() as u64;
+            // Limit the reservations as well, so that the tasks fail at
+            // mmap() instead of receiving a SIGBUS at fault time.
+            if controller.rsvd_supported(page_size) {
+                controller.set_rsvd_limit_in_bytes(page_size, limit)?;
+            }
             controller.set_limit_in_bytes(page_size, limit)?;
         }
 
         Ok(())
     }
 
+    /// The OCI runtime spec has no section for the `misc` controller, the
+    /// limits are taken from the `misc.max` entry of `unified` instead, one
+    /// `<resource> <max>` pair per line.
+    fn set_misc(&self, unified: &HashMap<String, String>) -> Result<()> {
+        let limits = match unified.get("misc.max") {
+            Some(limits) => limits,
+            None => return Ok(()),
+        };
+        let controller: &MiscController = self.controller()?;
+
+        for line in limits.lines().filter(|l| !l.trim().is_empty()) {
+            let (name, max) = line.trim().split_once(' ').ok_or_else(|| {
+                Error::InvalidLinuxResource(format!("invalid misc.max line {:?}", line))
+            })?;
+            let max = parse_max_value(max).map_err(|_| {
+                Error::InvalidLinuxResource(format!("invalid misc.max value {:?}", max))
+            })?;
+            controller.set_max(name, max)?;
+        }
+
+        Ok(())
+    }
+
+    fn set_rdma(&self, rdma: &HashMap<String, LinuxRdma>) -> Result<()> {
+        let controller: &RdmaController = self.controller()?;
+
+        for (device, limit) in rdma.iter() {
+            let hca_handle = limit.hca_handles().map(|v| MaxValue::Value(v as i64));
+            let hca_object = limit.hca_objects().map(|v| MaxValue::Value(v as i64));
+            controller.set_max(device, hca_handle, hca_object)?;
+        }
+
+        Ok(())
+    }
+
     fn set_network(&self, network: &LinuxNetwork) -> Result<()> {
         if let Some(class_id) = network.class_id() {
             let controller: &NetClsController = self.controller()?;
@@ -409,9 +467,10 @@ impl FsManager {
         let controller: &DevicesController = self.controller()?;
 
         for device in devices.iter() {
-            let devtype =
-                DeviceType::from_char(device.typ().unwrap_or_default().as_str().chars().next())
-                    .ok_or(Error::InvalidLinuxResource)?;
+            let typ = device.typ().unwrap_or_default();
+            let devtype = DeviceType::from_char(typ.as_str().chars().next()).ok_or_else(|| {
+                Error::InvalidLinuxResource(format!("invalid device type {:?}", typ.as_str()))
+            })?;
 
             let perm = device
                 .access()
@@ -453,7 +512,7 @@ impl FsManager {
     /// that, you should do it manually.
     fn set_controller_topdown<T, F>(&self, f: F) -> Result<()>
     where
-        for<'a> &'a T: From<&'a Subsystem>,
+        for<'a> &'a T: TryFrom<&'a Subsystem>,
         T: Controller + ControllIdentifier,
         for<'a> F: Fn(&'a T) -> Result<()>,
     {
@@ -701,6 +760,70 @@ impl FsManager {
         .unwrap_or_default()
     }
 
+    fn misc_cgroup_stats(&self) -> MiscCgroupStats {
+        let controller: &MiscController = match self.controller() {
+            Ok(controller) => controller,
+            Err(_) => return MiscCgroupStats::default(),
+        };
+
+        let limits = controller.max().unwrap_or_default();
+        let events = controller.events().unwrap_or_default();
+        controller
+            .current()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, usage)| {
+                let limit = match limits.get(&name) {
+                    Some(MaxValue::Value(limit)) => *limit,
+                    _ => -1,
+                };
+                let fail_cnt = events.get(&name).copied().unwrap_or_default();
+
+                let stat = MiscStat {
+                    usage,
+                    limit,
+                    fail_cnt,
+                };
+
+                (name, stat)
+            })
+            .collect()
+    }
+
+    fn rdma_cgroup_stats(&self) -> RdmaCgroupStats {
+        let controller: &RdmaController = match self.controller() {
+            Ok(controller) => controller,
+            Err(_) => return RdmaCgroupStats::default(),
+        };
+        let max_to_i64 = |mv: MaxValue| match mv {
+            MaxValue::Value(v) => v,
+            MaxValue::Max => -1,
+        };
+
+        let current = controller
+            .current()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|u| RdmaStat {
+                device: u.device,
+                hca_handles: u.hca_handle as i64,
+                hca_objects: u.hca_object as i64,
+            })
+            .collect();
+        let limit = controller
+            .max()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|l| RdmaStat {
+                device: l.device,
+                hca_handles: max_to_i64(l.hca_handle),
+                hca_objects: max_to_i64(l.hca_object),
+            })
+            .collect();
+
+        RdmaCgroupStats { current, limit }
+    }
+
     fn huge_tlb_cgroup_stats(&self) -> HugeTlbCgroupStats {
         let controller: &HugeTlbController = match self.controller() {
             Ok(controller) => controller,
@@ -714,11 +837,22 @@ impl FsManager {
                 let usage = controller.usage_in_bytes(s).unwrap_or_default();
                 let max_usage = controller.max_usage_in_bytes(s).unwrap_or_default();
                 let fail_cnt = controller.failcnt(s).unwrap_or_default();
+                let rsvd_usage = controller.rsvd_usage_in_bytes(s).unwrap_or_default();
+                let rsvd_max_usage = controller.rsvd_max_usage_in_bytes(s).unwrap_or_default();
+                let rsvd_fail_cnt = controller.rsvd_failcnt(s).unwrap_or_default();
+                let usage_per_node = controller
+                    .numa_stat(s)
+                    .map(|stat| stat.total_per_node)
+                    .unwrap_or_default();
 
                 let stat = HugeTlbStat {
                     usage,
                     max_usage,
                     fail_cnt,
+                    rsvd_usage,
+                    rsvd_max_usage,
+                    rsvd_fail_cnt,
+                    usage_per_node,
                 };
 
                 (s.to_string(), stat)
@@ -791,6 +925,31 @@ impl Manager for FsManager {
         Ok(())
     }
 
+    fn move_procs_to_parent(&mut self) -> Result<()> {
+        let parent = self.cgroup.parent_control_group();
+        let procs = self.cgroup.procs();
+        let moved = match open_members(&procs, || self.cgroup.procs()) {
+            Some(pidfds) => pidfds
+                .iter()
+                .map(|pidfd| parent.add_task_by_pidfd(pidfd))
+                .collect::<Vec<_>>(),
+            // Without pidfds, a recycled PID may be moved.
+            None => procs
+                .iter()
+                .map(|tgid| parent.add_task_by_tgid(*tgid))
+                .collect(),
+        };
+        for ret in moved {
+            match ret {
+                Err(e) if !matches!(e.kind(), FsErrorKind::ProcessExited(_)) => {
+                    return Err(e.into())
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
     fn set(&mut self, resources: &LinuxResources) -> Result<()> {
         if let Some(cpu) = resources.cpu() {
             self.set_cpuset(cpu)?;
@@ -817,10 +976,18 @@ impl Manager for FsManager {
             self.set_network(network)?;
         }
 
+        if let Some(rdma) = resources.rdma() {
+            self.set_rdma(rdma)?;
+        }
+
         if let Some(devices) = resources.devices() {
             self.set_devices(devices)?;
         }
 
+        if let Some(unified) = resources.unified() {
+            self.set_misc(unified)?;
+        }
+
         Ok(())
     }
 
@@ -860,6 +1027,8 @@ impl Manager for FsManager {
             pids: self.pids_cgroup_stats(),
             blkio: self.blkio_cgroup_stats(),
             hugetlb: self.huge_tlb_cgroup_stats(),
+            misc: self.misc_cgroup_stats(),
+            rdma: self.rdma_cgroup_stats(),
         }
     }
 
@@ -911,26 +1080,25 @@ fn parse_cgroup_mountinfo(paths: &HashMap<String, String>) -> Result<HashMap<Str
         .map_err(|err| FsError::with_cause(FsErrorKind::FsError, err))
         .map_err(Error::Cgroupfs)?;
 
-    for line in data.lines() {
-        let parts: Vec<&str> = line.splitn(2, " - ").collect();
-        let part1: Vec<&str> = parts[0].split(' ').collect();
-        let part2: Vec<&str> = parts[1].split(' ').collect();
-
-        if part2.len() != 3 {
+    for mi in hierarchies::parse_mountinfo(&data) {
+        if !mi.is_cgroup() {
             continue;
         }
+        let mountpoint = mi.mount_point.to_string_lossy().to_string();
 
-        let fs_type = part2[0];
-        if fs_type != "cgroup" && fs_type != "cgroup2" {
+        // The v2 hierarchy, or the unified one in hybrid mode, has no
+        // subsystem name in its options.
+        if mi.fs_type.0 == "cgroup2" {
+            if paths.contains_key("") {
+                mounts.insert(String::new(), mountpoint);
+            }
             continue;
         }
 
-        let super_opts: Vec<&str> = part2[2].split(',').collect();
-        for opt in super_opts.iter() {
+        for opt in mi.super_opts.iter() {
             // If opt matchs the one of cgroup subsystems
-            if paths.contains_key(*opt) {
-                let mountpoint = part1[4];
-                mounts.insert(opt.to_string(), mountpoint.to_string());
+            if paths.contains_key(opt) {
+                mounts.insert(opt.to_string(), mountpoint.clone());
             }
         }
     }
@@ -1098,8 +1266,10 @@ mod tests {
     use nix::unistd::Pid;
     use oci_spec::runtime::{LinuxCpuBuilder, LinuxMemoryBuilder, LinuxResourcesBuilder};
 
+    use crate::fs::guard::RemainingProcs;
     use crate::manager::fs::*;
     use crate::manager::tests::{MEMORY_1G, MEMORY_2G, MEMORY_512M};
+    use crate::manager::ManagerGuard;
     use crate::tests::spawn_sleep_inf;
     use crate::{skip_if_cgroups_v1, skip_if_cgroups_v2};
 
@@ -1218,8 +1388,7 @@ mod tests {
         let mut manager = new_manager();
 
         for (subsystem, mountpoint) in manager.mounts() {
-            let subsys = if subsystem.is_empty() {
-                assert!(manager.v2());
+            let subsys = if subsystem.is_empty() && manager.v2() {
                 None
             } else {
                 Some(subsystem.as_str())
@@ -1254,6 +1423,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_guard_move_to_parent() {
+        let mut child = spawn_sleep_inf();
+        let pid = CgroupPid::from(child.id() as u64);
+        let mut manager = new_manager();
+        manager.add_proc(pid).unwrap();
+        let parent = manager.cgroup().parent_control_group();
+
+        let guard = ManagerGuard::new(Box::new(manager), RemainingProcs::MoveToParent);
+        guard.destroy().unwrap();
+        assert_eq!(parent.procs(), vec![pid]);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        clean_cgroups(TEST_BASE);
+    }
+
     #[test]
     fn test_set_cpu() {
         // 1024 shares, every 100ms allows to use 1 CPU
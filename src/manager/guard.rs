@@ -0,0 +1,100 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+use std::ops::{Deref, DerefMut};
+
+use log::warn;
+
+use crate::fs::cgroup::next_backoff;
+use crate::fs::guard::RemainingProcs;
+use crate::manager::{Manager, Result};
+use crate::pidfd::signal_members;
+
+/// Owns a cgroup manager and destroys its cgroups when dropped, see `CgroupGuard`.
+///
+/// With `RemainingProcs::MoveToParent` the processes are moved by
+/// `Manager::move_procs_to_parent()`. Managers that don't support it, e.g. `SystemdManager`, whose
+/// unit would kill them when stopped, leave the cgroups and their processes alone then.
+pub struct ManagerGuard {
+    manager: Option<Box<dyn Manager>>,
+    procs: RemainingProcs,
+}
+
+impl ManagerGuard {
+    /// Takes ownership of `manager`, destroying its cgroups on drop and handling the processes
+    /// left in them according to `procs`.
+    pub fn new(manager: Box<dyn Manager>, procs: RemainingProcs) -> Self {
+        ManagerGuard {
+            manager: Some(manager),
+            procs,
+        }
+    }
+
+    /// Gives up the ownership of the manager, whose cgroups are not destroyed anymore.
+    pub fn release(mut self) -> Box<dyn Manager> {
+        self.manager.take().unwrap()
+    }
+
+    /// Destroys the cgroups now, returning the error instead of logging it.
+    pub fn destroy(mut self) -> Result<()> {
+        let mut manager = self.manager.take().unwrap();
+        destroy(manager.as_mut(), self.procs)
+    }
+}
+
+fn destroy(manager: &mut dyn Manager, procs: RemainingProcs) -> Result<()> {
+    let pids = manager.pids()?;
+    match procs {
+        RemainingProcs::Leak if !pids.is_empty() => {
+            warn!(
+                "leaking cgroup {:?}, processes left: {:?}",
+                manager.cgroup_path(None).ok(),
+                pids
+            );
+            return Ok(());
+        }
+        RemainingProcs::Kill => {
+            signal_members(&pids, || manager.pids().unwrap_or_default(), libc::SIGKILL);
+        }
+        RemainingProcs::MoveToParent if !pids.is_empty() => manager.move_procs_to_parent()?,
+        _ => (),
+    }
+
+    // The killed processes may take a while to exit.
+    let opts = procs.delete_options();
+    let mut delay = opts.backoff;
+    for _ in 0..opts.retries {
+        if manager.destroy().is_ok() {
+            return Ok(());
+        }
+        std::thread::sleep(delay);
+        delay = next_backoff(delay);
+    }
+    manager.destroy()
+}
+
+impl Deref for ManagerGuard {
+    type Target = dyn Manager;
+
+    fn deref(&self) -> &Self::Target {
+        self.manager.as_deref().unwrap()
+    }
+}
+
+impl DerefMut for ManagerGuard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.manager.as_deref_mut().unwrap()
+    }
+}
+
+impl Drop for ManagerGuard {
+    fn drop(&mut self) {
+        if let Some(mut manager) = self.manager.take() {
+            if let Err(e) = destroy(manager.as_mut(), self.procs) {
+                warn!("failed to destroy cgroup: {}", e);
+            }
+        }
+    }
+}
@@ -12,11 +12,13 @@ pub use fs::FsManager;
 mod systemd;
 pub use systemd::SystemdManager;
 mod conv;
+mod guard;
+pub use guard::ManagerGuard;
 
 use oci_spec::runtime::LinuxResources;
 
 use crate::systemd::SLICE_SUFFIX;
-use crate::{CgroupPid, CgroupStats, FreezerState};
+use crate::{CgroupPid, CgroupStats, FreezerState, Pidfd};
 
 /// Check if the cgroups path is a systemd cgroup.
 pub fn is_systemd_cgroup(cgroups_path: &str) -> bool {
@@ -29,6 +31,16 @@ pub trait Manager: Send + Sync {
     /// Add a process specified by its tgid.
     fn add_proc(&mut self, tgid: CgroupPid) -> Result<()>;
 
+    /// Add a process specified by its pidfd, checking that it still runs
+    /// before and after, so that a recycled PID is never added. Fails
+    /// with `Error::ProcessExited` otherwise.
+    fn add_proc_pidfd(&mut self, pidfd: &Pidfd) -> Result<()> {
+        pidfd.with_pid(
+            |pid| self.add_proc(pid),
+            |_| Error::ProcessExited(pidfd.pid().pid),
+        )
+    }
+
     /// Add a thread specified by its pid.
     fn add_thread(&mut self, pid: CgroupPid) -> Result<()>;
 
@@ -41,6 +53,16 @@ pub trait Manager: Send + Sync {
     /// Remove the cgroups.
     fn destroy(&mut self) -> Result<()>;
 
+    /// Move the processes of the cgroups to their parent cgroups, e.g.
+    /// before destroying them without killing the processes. Processes
+    /// that exit meanwhile are skipped.
+    ///
+    /// Not supported by default, failing with `Error::InvalidArgument`:
+    /// e.g. the processes of a systemd unit belong to it.
+    fn move_procs_to_parent(&mut self) -> Result<()> {
+        Err(Error::InvalidArgument)
+    }
+
     /// Set the resources to the cgroups.
     fn set(&mut self, resources: &LinuxResources) -> Result<()>;
 
@@ -49,7 +71,8 @@ pub trait Manager: Send + Sync {
     /// # Arguments
     ///
     /// - `subsystem`: cgroup subsystem, for cgroup v1 the value should not
-    ///   be empty, while for cgroup v2 the only valid value is `None`.
+    ///   be empty, while for cgroup v2 the only valid value is `None`. In
+    ///   hybrid mode, `Some("")` stands for the unified hierarchy.
     fn cgroup_path(&self, subsystem: Option<&str>) -> Result<String>;
 
     /// Enable CPUs, topdown from root in cgroup hierarchy, this would be
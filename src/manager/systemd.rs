@@ -336,6 +336,7 @@ mod tests {
     //! Don't run tests in parallel, use `--test-threads=1`!
     //!
 
+    use std::convert::TryFrom;
     use std::path::Path;
     use std::thread::sleep;
     use std::time::Duration;
@@ -465,7 +466,7 @@ mod tests {
 
     fn controller<'a, T>(fs_manager: &'a FsManager) -> &'a T
     where
-        &'a T: From<&'a Subsystem>,
+        &'a T: TryFrom<&'a Subsystem>,
         T: Controller + ControllIdentifier,
     {
         let controller: &T = fs_manager.cgroup().controller_of().unwrap();
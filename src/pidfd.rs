@@ -0,0 +1,198 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+//! This module contains a process handle backed by a pidfd, which keeps referring to the same
+//! process when its PID is recycled.
+//!
+//! A `CgroupPid` is only a number: by the time it's written to `cgroup.procs` or signaled, the
+//! process may have exited and its PID been given to another process. A `Pidfd` is checked
+//! before and after such operations, and signals are sent through it with
+//! `pidfd_send_signal(2)`, so they can't reach another process. Requires kernel 5.3+.
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+use crate::CgroupPid;
+
+/// A handle to a process, opened with `pidfd_open(2)`.
+#[derive(Debug)]
+pub struct Pidfd {
+    pid: CgroupPid,
+    fd: File,
+}
+
+impl Pidfd {
+    /// Opens a handle to the process `pid`. Fails with `ESRCH` if there is no such process.
+    pub fn open(pid: CgroupPid) -> io::Result<Self> {
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid.pid as libc::pid_t, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Pidfd {
+            pid,
+            fd: unsafe { File::from_raw_fd(fd as RawFd) },
+        })
+    }
+
+    /// A handle to the process `pid` from its pidfd, e.g. returned by `clone3(CLONE_PIDFD)`.
+    pub(crate) fn from_fd(pid: CgroupPid, fd: File) -> Self {
+        Pidfd { pid, fd }
+    }
+
+    /// Opens a handle to a child process.
+    pub fn from_child(child: &std::process::Child) -> io::Result<Self> {
+        Pidfd::open(CgroupPid::from(child))
+    }
+
+    /// The PID the handle was opened with.
+    pub fn pid(&self) -> CgroupPid {
+        self.pid
+    }
+
+    /// Sends the signal `sig` to the process. Fails with `ESRCH` if it exited.
+    pub fn send_signal(&self, sig: libc::c_int) -> io::Result<()> {
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_pidfd_send_signal,
+                self.fd.as_raw_fd(),
+                sig,
+                std::ptr::null::<libc::siginfo_t>(),
+                0,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Checks that the process still runs, so that its PID hasn't been recycled. Fails with
+    /// `ESRCH` otherwise.
+    ///
+    /// A zombie still runs in this sense: its PID can't be recycled until it's reaped.
+    pub fn check(&self) -> io::Result<()> {
+        self.send_signal(0)
+    }
+
+    /// Runs `f` with the PID of the process, checking that it runs before and after. `f` may
+    /// have acted on another process if the check after it fails.
+    pub fn with_pid<T, E>(
+        &self,
+        f: impl FnOnce(CgroupPid) -> std::result::Result<T, E>,
+        exited: impl Fn(io::Error) -> E,
+    ) -> std::result::Result<T, E> {
+        self.check().map_err(&exited)?;
+        let ret = f(self.pid)?;
+        self.check().map_err(&exited)?;
+        Ok(ret)
+    }
+}
+
+impl AsRawFd for Pidfd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl From<&Pidfd> for CgroupPid {
+    fn from(pidfd: &Pidfd) -> CgroupPid {
+        pidfd.pid
+    }
+}
+
+impl From<Pidfd> for CgroupPid {
+    fn from(pidfd: Pidfd) -> CgroupPid {
+        pidfd.pid
+    }
+}
+
+/// Opens handles to the processes of `pids` that are still listed by `members` after the handles
+/// were opened, i.e. to processes that are members of a control group, even if some of them exit
+/// and their PIDs are recycled meanwhile.
+///
+/// Returns `None` on kernels without pidfds. Processes that exited are skipped.
+pub(crate) fn open_members(
+    pids: &[CgroupPid],
+    members: impl FnOnce() -> Vec<CgroupPid>,
+) -> Option<Vec<Pidfd>> {
+    let mut pidfds = Vec::with_capacity(pids.len());
+    for pid in pids {
+        match Pidfd::open(*pid) {
+            Ok(pidfd) => pidfds.push(pidfd),
+            Err(e) if e.raw_os_error() == Some(libc::ENOSYS) => return None,
+            // The process exited.
+            Err(_) => (),
+        }
+    }
+
+    // The PIDs still listed after the handles were opened are the processes they refer to.
+    let members = members();
+    pidfds.retain(|p| members.contains(&p.pid));
+    Some(pidfds)
+}
+
+/// Sends `sig` to the processes of `pids` that are still listed by `members` after a handle to
+/// them was opened, i.e. to processes that are members of a control group, even if some of them
+/// exit and their PIDs are recycled meanwhile. E.g. with `cg.procs()` for both.
+///
+/// Falls back to `kill(2)` on kernels without pidfds. Returns the processes that were signaled,
+/// the other ones remain members.
+pub fn signal_members(
+    pids: &[CgroupPid],
+    members: impl FnOnce() -> Vec<CgroupPid>,
+    sig: libc::c_int,
+) -> Vec<CgroupPid> {
+    match open_members(pids, members) {
+        Some(pidfds) => pidfds
+            .iter()
+            .filter(|pidfd| pidfd.send_signal(sig).is_ok())
+            .map(Pidfd::pid)
+            .collect(),
+        None => pids
+            .iter()
+            .filter(|pid| unsafe { libc::kill(pid.pid as libc::pid_t, sig) } == 0)
+            .copied()
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use crate::pidfd::{open_members, signal_members, Pidfd};
+    use crate::CgroupPid;
+
+    #[test]
+    fn test_pidfd() {
+        let mut child = Command::new("sleep").arg("infinity").spawn().unwrap();
+        let pidfd = Pidfd::from_child(&child).unwrap();
+        assert_eq!(CgroupPid::from(&pidfd), CgroupPid::from(&child));
+        pidfd.check().unwrap();
+
+        assert!(open_members(&[pidfd.pid()], Vec::new).unwrap().is_empty());
+        let members = open_members(&[pidfd.pid()], || vec![pidfd.pid()]).unwrap();
+        assert_eq!(members[0].pid(), pidfd.pid());
+
+        // Not a member anymore, e.g. moved away: not signaled.
+        assert!(signal_members(&[pidfd.pid()], Vec::new, libc::SIGKILL).is_empty());
+        assert!(child.try_wait().unwrap().is_none());
+
+        assert_eq!(
+            signal_members(&[pidfd.pid()], || vec![pidfd.pid()], libc::SIGKILL),
+            vec![pidfd.pid()]
+        );
+        child.wait().unwrap();
+        let err = pidfd.check().unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ESRCH));
+        let err = pidfd
+            .with_pid(
+                |_| -> std::io::Result<()> { panic!("process exited") },
+                |e| e,
+            )
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ESRCH));
+    }
+}
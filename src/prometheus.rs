@@ -0,0 +1,667 @@
+// Copyright (c) 2025 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0 or MIT
+//
+
+//! This module renders `CgroupStats` in the Prometheus text exposition format, or in the
+//! OpenMetrics one.
+//!
+//! Every sample is labeled with `cgroup`, the name given to the stats of the control group, and
+//! with the constant labels of the `Exporter`. Metric names are prefixed with the namespace of the
+//! exporter, `cgroup` by default, e.g. `cgroup_memory_usage_bytes`. Times are converted to
+//! seconds, except the ones of `cpuacct.stat` which are kept in `USER_HZ` ticks.
+//!
+//! ```no_run
+//! # #[cfg(feature = "oci")] {
+//! use cgroups_rs::prometheus::Exporter;
+//! use cgroups_rs::{FsManager, Manager};
+//!
+//! let manager = FsManager::new("pod1/ctr1").unwrap();
+//! let stats = manager.stats();
+//! let text = Exporter::default()
+//!     .with_label("node", "node1")
+//!     .render(&[("pod1/ctr1", &stats)]);
+//! # }
+//! ```
+use std::fmt::Write;
+
+use crate::stats::{BlkioStat, CgroupStats, MemoryStats};
+
+const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+const MILLIS_PER_SEC: f64 = 1_000.0;
+
+/// The labels set by the exporter itself, which can't be constant labels.
+pub const RESERVED_LABELS: [&str; 6] = ["cgroup", "cpu", "device", "op", "pagesize", "resource"];
+
+/// The format of the rendered metrics.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    /// The Prometheus text exposition format, version 0.0.4.
+    Prometheus,
+    /// The OpenMetrics text format, terminated by `# EOF`.
+    OpenMetrics,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum MetricType {
+    Counter,
+    Gauge,
+}
+
+/// A metric family, i.e. the samples of a metric for all the control groups.
+struct Family {
+    /// The name without the namespace, and without `_total` for counters.
+    name: &'static str,
+    help: &'static str,
+    typ: MetricType,
+    samples: Vec<(String, String)>,
+}
+
+/// Collects the samples of all the control groups, grouped by metric family.
+#[derive(Default)]
+struct Families(Vec<Family>);
+
+impl Families {
+    fn add(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        typ: MetricType,
+        labels: String,
+        value: String,
+    ) {
+        let family = match self.0.iter().position(|f| f.name == name) {
+            Some(i) => &mut self.0[i],
+            None => {
+                self.0.push(Family {
+                    name,
+                    help,
+                    typ,
+                    samples: vec![],
+                });
+                self.0.last_mut().unwrap()
+            }
+        };
+        family.samples.push((labels, value));
+    }
+}
+
+/// Renders `CgroupStats` as metrics, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct Exporter {
+    namespace: String,
+    labels: Vec<(String, String)>,
+    format: Format,
+}
+
+impl Default for Exporter {
+    fn default() -> Self {
+        Exporter::new("cgroup")
+    }
+}
+
+/// Escapes a label value, see the exposition format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn seconds(nanos: u64) -> String {
+    (nanos as f64 / NANOS_PER_SEC).to_string()
+}
+
+impl Exporter {
+    /// An exporter prefixing metric names with `namespace`, e.g. `cgroup`.
+    pub fn new(namespace: &str) -> Self {
+        Exporter {
+            namespace: namespace.to_string(),
+            labels: vec![],
+            format: Format::Prometheus,
+        }
+    }
+
+    /// Adds a label with the same value to every sample, e.g. the name of the node.
+    ///
+    /// The labels of `RESERVED_LABELS` are ignored, since a sample can't have the same label twice.
+    pub fn with_label(mut self, name: &str, value: &str) -> Self {
+        if !RESERVED_LABELS.contains(&name) {
+            self.labels.push((name.to_string(), value.to_string()));
+        }
+        self
+    }
+
+    /// Sets the format of the rendered metrics, `Format::Prometheus` by default.
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Renders the stats of the control groups, given with their names.
+    pub fn render(&self, cgroups: &[(&str, &CgroupStats)]) -> String {
+        let mut families = Families::default();
+        for (name, stats) in cgroups {
+            let mut labels = vec![("cgroup".to_string(), name.to_string())];
+            labels.extend(self.labels.iter().cloned());
+            collect(&mut families, &labels, stats);
+        }
+
+        let mut out = String::new();
+        for family in &families.0 {
+            let name = format!("{}_{}", self.namespace, family.name);
+            let (typ, suffix) = match family.typ {
+                MetricType::Counter => ("counter", "_total"),
+                MetricType::Gauge => ("gauge", ""),
+            };
+            // In the Prometheus format, the family of a counter is named after its samples.
+            let family_name = match self.format {
+                Format::Prometheus => format!("{}{}", name, suffix),
+                Format::OpenMetrics => name.clone(),
+            };
+            let _ = writeln!(out, "# HELP {} {}", family_name, family.help);
+            let _ = writeln!(out, "# TYPE {} {}", family_name, typ);
+            for (labels, value) in &family.samples {
+                let _ = writeln!(out, "{}{}{{{}}} {}", name, suffix, labels, value);
+            }
+        }
+        if self.format == Format::OpenMetrics {
+            out.push_str("# EOF\n");
+        }
+        out
+    }
+}
+
+/// Renders the stats of the control groups with the default exporter.
+pub fn render(cgroups: &[(&str, &CgroupStats)]) -> String {
+    Exporter::default().render(cgroups)
+}
+
+/// Formats `labels` followed by `extra`.
+fn labels(labels: &[(String, String)], extra: &[(&str, &str)]) -> String {
+    labels
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .chain(extra.iter().copied())
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn collect(f: &mut Families, base: &[(String, String)], stats: &CgroupStats) {
+    use MetricType::*;
+
+    let l = labels(base, &[]);
+    let mut add = |name, help, typ, value: String| f.add(name, help, typ, l.clone(), value);
+
+    if let Some(acct) = &stats.cpu.cpu_acct {
+        add(
+            "cpu_usage_seconds",
+            "Total CPU time consumed.",
+            Counter,
+            seconds(acct.total_usage),
+        );
+        add(
+            "cpu_user_ticks",
+            "CPU time consumed in userspace, in USER_HZ ticks.",
+            Counter,
+            acct.user_usage.to_string(),
+        );
+        add(
+            "cpu_system_ticks",
+            "CPU time consumed in kernelspace, in USER_HZ ticks.",
+            Counter,
+            acct.system_usage.to_string(),
+        );
+    }
+    if let Some(throttling) = &stats.cpu.cpu_throttling {
+        add(
+            "cpu_periods",
+            "Number of enforcement periods elapsed.",
+            Counter,
+            throttling.periods.to_string(),
+        );
+        add(
+            "cpu_throttled_periods",
+            "Number of enforcement periods the control group was throttled in.",
+            Counter,
+            throttling.throttled_periods.to_string(),
+        );
+        add(
+            "cpu_throttled_seconds",
+            "Total time the control group was throttled for.",
+            Counter,
+            seconds(throttling.throttled_time),
+        );
+    }
+
+    type MemoryFamilies = [&'static str; 8];
+    let memories: [(Option<&MemoryStats>, MemoryFamilies); 3] = [
+        (
+            stats.memory.memory.as_ref(),
+            [
+                "memory_usage_bytes",
+                "Memory usage.",
+                "memory_max_usage_bytes",
+                "Maximum memory usage observed.",
+                "memory_limit_bytes",
+                "Memory limit.",
+                "memory_failcnt",
+                "Number of times the memory limit was hit.",
+            ],
+        ),
+        (
+            stats.memory.memory_swap.as_ref(),
+            [
+                "memory_swap_usage_bytes",
+                "Memory and swap usage on v1, swap usage on v2.",
+                "memory_swap_max_usage_bytes",
+                "Maximum memory and swap usage on v1, swap usage on v2, observed.",
+                "memory_swap_limit_bytes",
+                "Memory and swap limit on v1, swap limit on v2.",
+                "memory_swap_failcnt",
+                "Number of times the memory and swap limit was hit.",
+            ],
+        ),
+        (
+            stats.memory.kernel_memory.as_ref(),
+            [
+                "memory_kernel_usage_bytes",
+                "Kernel memory usage.",
+                "memory_kernel_max_usage_bytes",
+                "Maximum kernel memory usage observed.",
+                "memory_kernel_limit_bytes",
+                "Kernel memory limit.",
+                "memory_kernel_failcnt",
+                "Number of times the kernel memory limit was hit.",
+            ],
+        ),
+    ];
+    for (memory, n) in memories.iter() {
+        if let Some(m) = memory {
+            add(n[0], n[1], Gauge, m.usage.to_string());
+            add(n[2], n[3], Gauge, m.max_usage.to_string());
+            // Negative limits stand for no limit.
+            if m.limit >= 0 {
+                add(n[4], n[5], Gauge, m.limit.to_string());
+            }
+            add(n[6], n[7], Counter, m.fail_cnt.to_string());
+        }
+    }
+    if stats.memory.memory.is_some() {
+        let m = &stats.memory;
+        let values: [(&'static str, &'static str, MetricType, u64); 17] = [
+            ("memory_cache_bytes", "Page cache.", Gauge, m.cache),
+            ("memory_rss_bytes", "Anonymous memory.", Gauge, m.rss),
+            (
+                "memory_rss_huge_bytes",
+                "Anonymous transparent huge pages.",
+                Gauge,
+                m.rss_huge,
+            ),
+            ("memory_shmem_bytes", "Shared memory.", Gauge, m.shmem),
+            (
+                "memory_mapped_file_bytes",
+                "Memory mapped files.",
+                Gauge,
+                m.mapped_file,
+            ),
+            ("memory_dirty_bytes", "Dirty page cache.", Gauge, m.dirty),
+            (
+                "memory_writeback_bytes",
+                "Page cache under writeback.",
+                Gauge,
+                m.writeback,
+            ),
+            ("memory_swap_bytes", "Swap usage.", Gauge, m.swap),
+            (
+                "memory_pgfault",
+                "Number of page faults.",
+                Counter,
+                m.pgfault,
+            ),
+            (
+                "memory_pgmajfault",
+                "Number of major page faults.",
+                Counter,
+                m.pgmajfault,
+            ),
+            (
+                "memory_inactive_anon_bytes",
+                "Anonymous memory on the inactive LRU list.",
+                Gauge,
+                m.inactive_anon,
+            ),
+            (
+                "memory_active_anon_bytes",
+                "Anonymous memory on the active LRU list.",
+                Gauge,
+                m.active_anon,
+            ),
+            (
+                "memory_inactive_file_bytes",
+                "Page cache on the inactive LRU list.",
+                Gauge,
+                m.inactive_file,
+            ),
+            (
+                "memory_active_file_bytes",
+                "Page cache on the active LRU list.",
+                Gauge,
+                m.active_file,
+            ),
+            (
+                "memory_unevictable_bytes",
+                "Memory that can't be reclaimed.",
+                Gauge,
+                m.unevictable,
+            ),
+            (
+                "memory_pgpgin",
+                "Number of pages charged.",
+                Counter,
+                m.pgpgin,
+            ),
+            (
+                "memory_pgpgout",
+                "Number of pages uncharged.",
+                Counter,
+                m.pgpgout,
+            ),
+        ];
+        for (name, help, typ, value) in values.iter() {
+            add(name, help, *typ, value.to_string());
+        }
+    }
+
+    add(
+        "pids_current",
+        "Number of processes.",
+        Gauge,
+        stats.pids.current.to_string(),
+    );
+    if stats.pids.limit > 0 {
+        add(
+            "pids_limit",
+            "Maximum number of processes.",
+            Gauge,
+            stats.pids.limit.to_string(),
+        );
+    }
+
+    if let Some(acct) = &stats.cpu.cpu_acct {
+        for (cpu, usage) in acct.usage_percpu.iter().enumerate() {
+            f.add(
+                "cpu_usage_percpu_seconds",
+                "CPU time consumed on each CPU.",
+                Counter,
+                labels(base, &[("cpu", &cpu.to_string())]),
+                seconds(*usage),
+            );
+        }
+    }
+    // The last field is the number of units per second of the values, if they are times.
+    type BlkioFamily<'a> = (
+        &'static str,
+        &'static str,
+        MetricType,
+        &'a Vec<BlkioStat>,
+        Option<f64>,
+    );
+    let blkio: [BlkioFamily; 8] = [
+        (
+            "blkio_io_service_bytes",
+            "Bytes transferred to or from the device.",
+            Counter,
+            &stats.blkio.io_service_bytes_recursive,
+            None,
+        ),
+        (
+            "blkio_io_serviced",
+            "Number of I/O operations issued to the device.",
+            Counter,
+            &stats.blkio.io_serviced_recursive,
+            None,
+        ),
+        (
+            "blkio_io_queued",
+            "Number of I/O operations queued.",
+            Gauge,
+            &stats.blkio.io_queued_recursive,
+            None,
+        ),
+        (
+            "blkio_io_service_time_seconds",
+            "Time between the dispatch and the completion of I/O operations.",
+            Counter,
+            &stats.blkio.io_service_time_recursive,
+            Some(NANOS_PER_SEC),
+        ),
+        (
+            "blkio_io_wait_time_seconds",
+            "Time I/O operations spent waiting in the queues.",
+            Counter,
+            &stats.blkio.io_wait_time_recursive,
+            Some(NANOS_PER_SEC),
+        ),
+        (
+            "blkio_io_merged",
+            "Number of I/O operations merged.",
+            Counter,
+            &stats.blkio.io_merged_recursive,
+            None,
+        ),
+        (
+            "blkio_io_time_seconds",
+            "Time the device was allocated to the control group.",
+            Counter,
+            &stats.blkio.io_time_recursive,
+            Some(MILLIS_PER_SEC),
+        ),
+        (
+            "blkio_sectors",
+            "Number of sectors transferred to or from the device.",
+            Counter,
+            &stats.blkio.sectors_recursive,
+            None,
+        ),
+    ];
+    for (name, help, typ, entries, per_sec) in blkio.iter() {
+        for e in entries.iter() {
+            let device = format!("{}:{}", e.major, e.minor);
+            let mut extra = vec![("device", device.as_str())];
+            if !e.op.is_empty() {
+                extra.push(("op", e.op.as_str()));
+            }
+            let value = match per_sec {
+                Some(per_sec) => (e.value as f64 / per_sec).to_string(),
+                None => e.value.to_string(),
+            };
+            f.add(name, help, *typ, labels(base, &extra), value);
+        }
+    }
+
+    let mut hugetlb = stats.hugetlb.iter().collect::<Vec<_>>();
+    hugetlb.sort_by(|a, b| a.0.cmp(b.0));
+    for (size, h) in hugetlb {
+        let l = labels(base, &[("pagesize", size)]);
+        f.add(
+            "hugetlb_usage_bytes",
+            "Huge pages usage.",
+            Gauge,
+            l.clone(),
+            h.usage.to_string(),
+        );
+        f.add(
+            "hugetlb_max_usage_bytes",
+            "Maximum huge pages usage observed.",
+            Gauge,
+            l.clone(),
+            h.max_usage.to_string(),
+        );
+        f.add(
+            "hugetlb_failcnt",
+            "Number of times the huge pages limit was hit.",
+            Counter,
+            l.clone(),
+            h.fail_cnt.to_string(),
+        );
+        f.add(
+            "hugetlb_rsvd_usage_bytes",
+            "Huge pages reservation usage.",
+            Gauge,
+            l,
+            h.rsvd_usage.to_string(),
+        );
+    }
+
+    let mut misc = stats.misc.iter().collect::<Vec<_>>();
+    misc.sort_by(|a, b| a.0.cmp(b.0));
+    for (resource, m) in misc {
+        let l = labels(base, &[("resource", resource)]);
+        f.add(
+            "misc_usage",
+            "Usage of the miscellaneous resource.",
+            Gauge,
+            l.clone(),
+            m.usage.to_string(),
+        );
+        if m.limit >= 0 {
+            f.add(
+                "misc_limit",
+                "Limit of the miscellaneous resource.",
+                Gauge,
+                l.clone(),
+                m.limit.to_string(),
+            );
+        }
+        f.add(
+            "misc_failcnt",
+            "Number of times the limit of the miscellaneous resource was hit.",
+            Counter,
+            l,
+            m.fail_cnt.to_string(),
+        );
+    }
+
+    for (current, entries) in [(true, &stats.rdma.current), (false, &stats.rdma.limit)] {
+        for r in entries.iter() {
+            let l = labels(base, &[("device", &r.device)]);
+            let values = if current {
+                [
+                    ("rdma_hca_handles", "Number of HCA handles.", r.hca_handles),
+                    ("rdma_hca_objects", "Number of HCA objects.", r.hca_objects),
+                ]
+            } else {
+                [
+                    (
+                        "rdma_hca_handles_limit",
+                        "Maximum number of HCA handles.",
+                        r.hca_handles,
+                    ),
+                    (
+                        "rdma_hca_objects_limit",
+                        "Maximum number of HCA objects.",
+                        r.hca_objects,
+                    ),
+                ]
+            };
+            for (name, help, value) in values.iter() {
+                if *value >= 0 {
+                    f.add(name, help, Gauge, l.clone(), value.to_string());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prometheus::{render, Exporter, Format};
+    use crate::stats::{BlkioStat, CgroupStats, CpuAcctStats, HugeTlbStat, MemoryStats};
+
+    fn stats() -> CgroupStats {
+        let mut stats = CgroupStats::default();
+        stats.cpu.cpu_acct = Some(CpuAcctStats {
+            total_usage: 1_500_000_000,
+            ..Default::default()
+        });
+        stats.memory.memory = Some(MemoryStats {
+            usage: 4096,
+            limit: -1,
+            ..Default::default()
+        });
+        stats.pids.current = 3;
+        stats.blkio.io_service_bytes_recursive = vec![BlkioStat {
+            major: 8,
+            minor: 0,
+            op: "Read".to_string(),
+            value: 512,
+        }];
+        stats.blkio.io_wait_time_recursive = vec![BlkioStat {
+            major: 8,
+            minor: 0,
+            op: "Total".to_string(),
+            value: 250_000_000,
+        }];
+        stats.blkio.io_time_recursive = vec![BlkioStat {
+            major: 8,
+            minor: 0,
+            op: String::new(),
+            value: 1500,
+        }];
+        stats
+            .hugetlb
+            .insert("2MB".to_string(), HugeTlbStat::default());
+        stats
+    }
+
+    #[test]
+    fn test_render() {
+        let a = stats();
+        let b = CgroupStats::default();
+        let text = render(&[("pod/a", &a), ("b\"\n", &b)]);
+
+        assert!(text.contains(
+            "# HELP cgroup_cpu_usage_seconds_total Total CPU time consumed.\n\
+             # TYPE cgroup_cpu_usage_seconds_total counter\n\
+             cgroup_cpu_usage_seconds_total{cgroup=\"pod/a\"} 1.5\n"
+        ));
+        assert!(text.contains("cgroup_memory_usage_bytes{cgroup=\"pod/a\"} 4096\n"));
+        // No limit.
+        assert!(!text.contains("cgroup_memory_limit_bytes"));
+        // Samples are grouped by family, labels are escaped.
+        assert!(text.contains(
+            "# TYPE cgroup_pids_current gauge\n\
+             cgroup_pids_current{cgroup=\"pod/a\"} 3\n\
+             cgroup_pids_current{cgroup=\"b\\\"\\n\"} 0\n"
+        ));
+        assert!(text.contains(
+            "cgroup_blkio_io_service_bytes_total{cgroup=\"pod/a\",device=\"8:0\",op=\"Read\"} 512\n"
+        ));
+        assert!(text.contains(
+            "cgroup_blkio_io_wait_time_seconds_total{cgroup=\"pod/a\",device=\"8:0\",op=\"Total\"} 0.25\n"
+        ));
+        assert!(text
+            .contains("cgroup_blkio_io_time_seconds_total{cgroup=\"pod/a\",device=\"8:0\"} 1.5\n"));
+        assert!(text.contains("cgroup_hugetlb_usage_bytes{cgroup=\"pod/a\",pagesize=\"2MB\"} 0\n"));
+        assert_eq!(text.matches("# TYPE cgroup_pids_current ").count(), 1);
+        assert!(!text.contains("# EOF"));
+    }
+
+    #[test]
+    fn test_render_openmetrics() {
+        let a = stats();
+        let text = Exporter::new("ctr")
+            .with_label("node", "n1")
+            .with_label("cgroup", "other")
+            .with_format(Format::OpenMetrics)
+            .render(&[("a", &a)]);
+
+        assert!(text.contains(
+            "# TYPE ctr_cpu_usage_seconds counter\n\
+             ctr_cpu_usage_seconds_total{cgroup=\"a\",node=\"n1\"} 1.5\n"
+        ));
+        assert!(text.ends_with("# EOF\n"));
+    }
+}
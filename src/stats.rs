@@ -4,24 +4,29 @@
 // SPDX-License-Identifier: Apache-2.0 or MIT
 //
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CgroupStats {
     pub cpu: CpuCgroupStats,
     pub memory: MemoryCgroupStats,
     pub pids: PidsCgroupStats,
     pub blkio: BlkioCgroupStats,
     pub hugetlb: HugeTlbCgroupStats,
+    pub misc: MiscCgroupStats,
+    pub rdma: RdmaCgroupStats,
 }
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CpuCgroupStats {
     pub cpu_acct: Option<CpuAcctStats>,
     pub cpu_throttling: Option<CpuThrottlingStats>,
 }
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CpuAcctStats {
     /// Usage in userspace, read from `cpuacct.stat` from the line starting
     /// with `user`. Set 0 if no data.
@@ -36,6 +41,7 @@ pub struct CpuAcctStats {
 }
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CpuThrottlingStats {
     /// Periods, read from `cpu.stat` from the line starting with
     /// `nr_periods`. Set 0 if no data.
@@ -49,6 +55,7 @@ pub struct CpuThrottlingStats {
 }
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MemoryCgroupStats {
     pub memory: Option<MemoryStats>,
     pub memory_swap: Option<MemoryStats>,
@@ -99,6 +106,7 @@ pub struct MemoryCgroupStats {
 }
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MemoryStats {
     /// Memory [swap] usage, read from `memory[.memsw].usage_in_bytes` in
     /// cgroups v1 and `memory[.swap].current` in cgroups v2.
@@ -116,6 +124,7 @@ pub struct MemoryStats {
 }
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PidsCgroupStats {
     /// Current number of processes in the cgroup, read from `pids.current`.
     pub current: u64,
@@ -124,6 +133,7 @@ pub struct PidsCgroupStats {
 }
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlkioCgroupStats {
     pub io_service_bytes_recursive: Vec<BlkioStat>,
     pub io_serviced_recursive: Vec<BlkioStat>,
@@ -136,6 +146,7 @@ pub struct BlkioCgroupStats {
 }
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlkioStat {
     pub major: u64,
     pub minor: u64,
@@ -149,8 +160,57 @@ pub struct BlkioStat {
 pub type HugeTlbCgroupStats = HashMap<String, HugeTlbStat>;
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HugeTlbStat {
     pub usage: u64,
     pub max_usage: u64,
     pub fail_cnt: u64,
+    /// Reservation usage, read from `hugetlb.<size>.rsvd.usage_in_bytes`
+    /// in cgroups v1 and `hugetlb.<size>.rsvd.current` in cgroups v2.
+    pub rsvd_usage: u64,
+    /// Maximum reservation usage, read from
+    /// `hugetlb.<size>.rsvd.max_usage_in_bytes`. Only available in cgroups
+    /// v1.
+    pub rsvd_max_usage: u64,
+    /// Reservation failure count, read from `hugetlb.<size>.rsvd.failcnt`.
+    /// Only available in cgroups v1.
+    pub rsvd_fail_cnt: u64,
+    /// Usage per NUMA node ID, read from `hugetlb.<size>.numa_stat`.
+    pub usage_per_node: BTreeMap<u32, u64>,
+}
+
+/// A structure representing the statistics of the `misc` subsystem of a
+/// Cgroup. The key is the resource name, e.g. `sev`, and the value is the
+/// statistics for that resource.
+pub type MiscCgroupStats = HashMap<String, MiscStat>;
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MiscStat {
+    /// Usage, read from `misc.current`.
+    pub usage: u64,
+    /// Limit, read from `misc.max`. Set -1 if there is no limit.
+    pub limit: i64,
+    /// Number of times the usage was about to go over the limit, read from
+    /// `misc.events`.
+    pub fail_cnt: u64,
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RdmaCgroupStats {
+    /// Usage per device, read from `rdma.current`.
+    pub current: Vec<RdmaStat>,
+    /// Limits per device, read from `rdma.max`.
+    pub limit: Vec<RdmaStat>,
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RdmaStat {
+    pub device: String,
+    /// HCA handles. Set -1 if there is no limit.
+    pub hca_handles: i64,
+    /// HCA objects. Set -1 if there is no limit.
+    pub hca_objects: i64,
 }
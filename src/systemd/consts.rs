@@ -12,6 +12,12 @@ pub const UNIT_MODE_REPLACE: &str = "replace";
 /// No such unit error
 pub const NO_SUCH_UNIT: &str = "org.freedesktop.systemd1.NoSuchUnit";
 
+/// Systemd D-Bus service
+pub const SYSTEMD_DESTINATION: &str = "org.freedesktop.systemd1";
+
+/// Unit D-Bus interface
+pub const UNIT_INTERFACE: &str = "org.freedesktop.systemd1.Unit";
+
 /// Default description for transient units.
 pub const DEFAULT_DESCRIPTION: &str = "cgroups-rs transient unit";
 
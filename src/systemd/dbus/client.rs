@@ -4,12 +4,15 @@
 // SPDX-License-Identifier: Apache-2.0 or MIT
 //
 
+use zbus::blocking::Proxy;
 use zbus::zvariant::Value;
 use zbus::{Error as ZbusError, Result as ZbusResult};
 
 use crate::systemd::dbus::error::{Error, Result};
 use crate::systemd::dbus::proxy::systemd_manager_proxy;
-use crate::systemd::{Property, NO_SUCH_UNIT, PIDS, UNIT_MODE_REPLACE};
+use crate::systemd::{
+    Property, NO_SUCH_UNIT, PIDS, SYSTEMD_DESTINATION, UNIT_INTERFACE, UNIT_MODE_REPLACE,
+};
 use crate::CgroupPid;
 
 pub struct SystemdClient<'a> {
@@ -165,6 +168,23 @@ impl SystemdClient<'_> {
 
         Ok(())
     }
+
+    /// Get the name of the unit a process belongs to through dbus
+    /// `GetUnitByPID`, e.g. `cri-containerd-<id>.scope`.
+    pub fn unit_by_pid(pid: CgroupPid) -> Result<String> {
+        let sys_proxy = systemd_manager_proxy()?;
+
+        let path = sys_proxy.get_unit_by_pid(pid.pid as u32)?;
+        let unit = Proxy::new(
+            sys_proxy.inner().connection(),
+            SYSTEMD_DESTINATION,
+            path,
+            UNIT_INTERFACE,
+        )?;
+        let id = unit.get_property::<String>("Id")?;
+
+        Ok(id)
+    }
 }
 
 fn ignore_no_such_unit<T>(result: ZbusResult<T>) -> ZbusResult<bool> {
@@ -541,4 +561,19 @@ pub mod tests {
         child.wait().unwrap();
         child1.wait().unwrap();
     }
+
+    #[test]
+    fn test_unit_by_pid() {
+        skip_if_no_systemd!();
+
+        let unit = test_unit();
+        let mut child = spawn_sleep_inf();
+        let pid = CgroupPid::from(child.id() as u64);
+        let cgroup = start_default_cgroup(pid, &unit);
+
+        assert_eq!(SystemdClient::unit_by_pid(pid).unwrap(), unit);
+
+        stop_cgroup(&cgroup);
+        child.wait().unwrap();
+    }
 }
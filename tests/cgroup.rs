@@ -5,18 +5,26 @@
 //
 
 //! Simple unit tests about the control groups system.
+use nix::errno::Errno;
+use std::os::unix::fs::MetadataExt;
 use std::process::Command;
 use std::thread::sleep;
 use std::time::Duration;
 
 use cgroups_rs::fs::cgroup::{
-    CGROUP_MODE_DOMAIN, CGROUP_MODE_DOMAIN_INVALID, CGROUP_MODE_DOMAIN_THREADED,
-    CGROUP_MODE_THREADED,
+    CgroupType, DeleteOptions, DeleteProcs, CGROUP_MODE_DOMAIN, CGROUP_MODE_DOMAIN_INVALID,
+    CGROUP_MODE_DOMAIN_THREADED, CGROUP_MODE_THREADED,
 };
+use cgroups_rs::fs::cgroup_builder::CgroupBuilder;
+use cgroups_rs::fs::command::{CgroupCommandExt, CloneCommand};
+use cgroups_rs::fs::error::ErrorKind;
+use cgroups_rs::fs::freezer::FreezerController;
+use cgroups_rs::fs::guard::{CgroupGuard, RemainingProcs};
 use cgroups_rs::fs::memory::MemController;
+use cgroups_rs::fs::migrate::MigrateOptions;
 use cgroups_rs::fs::Controller;
-use cgroups_rs::fs::{Cgroup, Subsystem};
-use cgroups_rs::CgroupPid;
+use cgroups_rs::fs::{Cgroup, MaxValue, Subsystem};
+use cgroups_rs::{CgroupPid, FreezerState, Pidfd};
 
 #[test]
 fn test_procs_iterator_cgroup() {
@@ -273,3 +281,530 @@ fn test_cgroup_v2() {
 
     cg.delete().unwrap();
 }
+
+#[test]
+fn test_cgroup_max_depth_and_descendants() {
+    if !cgroups_rs::fs::hierarchies::is_cgroup2_unified_mode() {
+        return;
+    }
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let cg = Cgroup::new(h, String::from("test_cgroup_max_depth")).unwrap();
+    {
+        assert_eq!(cg.max_depth().unwrap(), MaxValue::Max);
+        assert_eq!(cg.max_descendants().unwrap(), MaxValue::Max);
+
+        cg.set_max_depth(MaxValue::Value(1)).unwrap();
+        cg.set_max_descendants(MaxValue::Value(1)).unwrap();
+        assert_eq!(cg.max_depth().unwrap(), MaxValue::Value(1));
+        assert_eq!(cg.max_descendants().unwrap(), MaxValue::Value(1));
+
+        let h = cgroups_rs::fs::hierarchies::auto();
+        let child = Cgroup::new(h, String::from("test_cgroup_max_depth/a")).unwrap();
+        let stat = cg.stat().unwrap();
+        assert_eq!(stat.nr_descendants, 1);
+
+        // Neither a grandchild nor a second child are allowed.
+        let h = cgroups_rs::fs::hierarchies::auto();
+        assert!(Cgroup::new(h, String::from("test_cgroup_max_depth/a/b")).is_err());
+        let h = cgroups_rs::fs::hierarchies::auto();
+        assert!(Cgroup::new(h, String::from("test_cgroup_max_depth/c")).is_err());
+
+        child.delete().unwrap();
+    }
+    cg.delete().unwrap();
+}
+
+#[test]
+fn test_cgroup_subtree_control() {
+    if !cgroups_rs::fs::hierarchies::is_cgroup2_unified_mode() {
+        return;
+    }
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let cg = Cgroup::new(h, String::from("test_cgroup_subtree_control")).unwrap();
+    {
+        let pids = vec![String::from("pids")];
+        cg.enable_controllers(&pids).unwrap();
+        assert!(cg.subtree_control().unwrap().contains(&pids[0]));
+
+        // The no-internal-process rule forbids enabling controllers once a process joined.
+        let mut child = Command::new("sleep").arg("infinity").spawn().unwrap();
+        cg.disable_controllers(&pids).unwrap();
+        assert!(!cg.subtree_control().unwrap().contains(&pids[0]));
+        cg.add_task_by_tgid(CgroupPid::from(child.id() as u64))
+            .unwrap();
+        let err = cg.enable_controllers(&pids).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::SubtreeControl(path, body, Errno::EBUSY)
+                if path.ends_with("test_cgroup_subtree_control") && body == "+pids"
+        ));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+    cg.delete().unwrap();
+}
+
+#[test]
+fn test_threaded_subtree() {
+    if !cgroups_rs::fs::hierarchies::is_cgroup2_unified_mode() {
+        return;
+    }
+    let pid = libc::pid_t::from(nix::unistd::getpid()) as u64;
+    let cg = Cgroup::new(
+        cgroups_rs::fs::hierarchies::auto(),
+        String::from("test_threaded_subtree"),
+    )
+    .unwrap();
+    let vcpu = CgroupBuilder::new("test_threaded_subtree/vcpu")
+        .set_specified_controllers(vec![String::from("cpu")])
+        .threaded()
+        .build(cgroups_rs::fs::hierarchies::auto())
+        .unwrap();
+    {
+        assert_eq!(cg.cgroup_type().unwrap(), CgroupType::DomainThreaded);
+        assert_eq!(vcpu.cgroup_type().unwrap(), CgroupType::Threaded);
+
+        cg.add_task_by_tgid(CgroupPid::from(pid)).unwrap();
+        let tid = libc::pid_t::from(nix::unistd::gettid()) as u64;
+        vcpu.move_thread(CgroupPid::from(pid), CgroupPid::from(tid))
+            .unwrap();
+        assert!(vcpu
+            .move_thread(CgroupPid::from(pid), CgroupPid::from(u32::MAX as u64))
+            .is_err());
+        let mut child = Command::new("true").spawn().unwrap();
+        let child_pid = CgroupPid::from(&child);
+        child.wait().unwrap();
+        let err = vcpu.move_thread(child_pid, child_pid).unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::ProcessExited(child_pid.pid));
+
+        let threads = cg.threads_per_child().unwrap();
+        assert_eq!(threads.get("vcpu"), Some(&vec![CgroupPid::from(tid)]));
+
+        vcpu.move_task_to_parent(CgroupPid::from(tid)).unwrap();
+        cg.remove_task_by_tgid(CgroupPid::from(pid)).unwrap();
+    }
+    vcpu.delete().unwrap();
+    cg.delete().unwrap();
+}
+
+#[test]
+fn test_delete_recursive() {
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let cg = Cgroup::new(h, String::from("test_delete_recursive")).unwrap();
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let child = Cgroup::new(h, String::from("test_delete_recursive/a/b")).unwrap();
+
+    let mut sleeper = Command::new("sleep").arg("infinity").spawn().unwrap();
+    let pid = CgroupPid::from(sleeper.id() as u64);
+    child.add_task_by_tgid(pid).unwrap();
+
+    // The process keeps the control groups busy.
+    let opts = DeleteOptions {
+        retries: 1,
+        ..Default::default()
+    };
+    let report = cg.delete_recursive(&opts).unwrap();
+    assert!(!report.is_complete());
+    assert!(report.failed.iter().any(|f| f.procs == vec![pid]));
+    assert!(report.evicted.is_empty());
+
+    let opts = DeleteOptions {
+        procs: DeleteProcs::MoveToParent,
+        ..Default::default()
+    };
+    let report = cg.delete_recursive(&opts).unwrap();
+    assert!(report.is_complete(), "{:?}", report);
+    assert_eq!(report.evicted, vec![pid]);
+    assert!(!cg.exists());
+
+    sleeper.kill().unwrap();
+    sleeper.wait().unwrap();
+}
+
+#[test]
+fn test_cgroup_guard() {
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let cg = CgroupGuard::new(
+        Cgroup::new(h, String::from("test_cgroup_guard")).unwrap(),
+        RemainingProcs::Kill,
+    );
+    let mut sleeper = Command::new("sleep").arg("infinity").spawn().unwrap();
+    cg.add_task_by_tgid(CgroupPid::from(&sleeper)).unwrap();
+    let path = cg.subsystems()[0].to_controller().path().to_path_buf();
+    drop(cg);
+
+    assert!(!path.exists());
+    sleeper.wait().unwrap();
+
+    // A released control group stays around.
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let cg = CgroupGuard::new(
+        Cgroup::new(h, String::from("test_cgroup_guard")).unwrap(),
+        RemainingProcs::Leak,
+    )
+    .release();
+    assert!(cg.exists());
+    cg.delete().unwrap();
+}
+
+#[test]
+fn test_spawn_into_cgroup() {
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let cg = Cgroup::new(h, String::from("test_spawn_into_cgroup")).unwrap();
+    {
+        let mut child = Command::new("sleep")
+            .arg("infinity")
+            .spawn_into_cgroup(&cg)
+            .unwrap();
+        assert_eq!(cg.procs(), vec![CgroupPid::from(child.id() as u64)]);
+        child.kill().unwrap();
+        assert!(!child.wait().unwrap().success());
+
+        let mut child = Command::new("sleep")
+            .arg("infinity")
+            .cgroup(&cg)
+            .unwrap()
+            .spawn()
+            .unwrap();
+        assert_eq!(cg.procs(), vec![CgroupPid::from(&child)]);
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let err = Command::new("/nonexistent")
+            .spawn_into_cgroup(&cg)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+        let mut child = CloneCommand::new("sleep")
+            .arg("infinity")
+            .spawn_into_cgroup(&cg)
+            .unwrap();
+        assert_eq!(cg.procs(), vec![CgroupPid::from(child.id() as u64)]);
+        child.kill().unwrap();
+        assert!(!child.wait().unwrap().success());
+        // Reaped: the pid isn't signaled anymore.
+        child.kill().unwrap();
+
+        let err = CloneCommand::new("/nonexistent")
+            .spawn_into_cgroup(&cg)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    }
+    cg.delete().unwrap();
+}
+
+#[test]
+fn test_cgroup_rejects_escaping_paths() {
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let err = Cgroup::new(h, String::from("test_escape/../../escaped")).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::InvalidPath);
+
+    // A leading slash doesn't leave the hierarchy either.
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let root = h.root();
+    let cg = Cgroup::load(h, String::from("/test_leading_slash"));
+    for sub in cg.subsystems() {
+        assert!(sub.to_controller().path().starts_with(&root));
+    }
+}
+
+#[test]
+fn test_cgroup_id() {
+    if !cgroups_rs::fs::hierarchies::is_cgroup2_unified_mode() {
+        return;
+    }
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let cg = Cgroup::new(h, String::from("test_cgroup_id/a")).unwrap();
+    {
+        let id = cg.id().unwrap();
+        let h = cgroups_rs::fs::hierarchies::auto();
+        let found = Cgroup::load_by_id(h, id).unwrap();
+        assert_eq!(found.path(), "test_cgroup_id/a");
+        assert!(cg.dir_fd().unwrap().metadata().unwrap().is_dir());
+    }
+    cg.delete().unwrap();
+    let h = cgroups_rs::fs::hierarchies::auto();
+    Cgroup::load(h, String::from("test_cgroup_id"))
+        .delete()
+        .unwrap();
+}
+
+#[test]
+fn test_cgroup_delegate() {
+    if !cgroups_rs::fs::hierarchies::is_cgroup2_unified_mode() {
+        return;
+    }
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let cg = Cgroup::new(h, String::from("test_cgroup_delegate")).unwrap();
+    {
+        let files = cg.delegate(1000, 1000, &["pids".to_string()]).unwrap();
+        assert!(files.contains(&"cgroup.procs".to_string()));
+        assert!(cg.subtree_control().unwrap().contains(&"pids".to_string()));
+
+        let dir = std::path::Path::new("/sys/fs/cgroup/test_cgroup_delegate");
+        for f in std::iter::once(dir.to_path_buf()).chain(files.iter().map(|f| dir.join(f))) {
+            let m = std::fs::metadata(&f).unwrap();
+            assert_eq!((m.uid(), m.gid()), (1000, 1000), "{}", f.display());
+        }
+        // Limits of the delegated control group stay with the delegator.
+        assert_eq!(std::fs::metadata(dir.join("pids.max")).unwrap().uid(), 0);
+    }
+    cg.delete().unwrap();
+
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let root = Cgroup::load(h, String::from(""));
+    assert_eq!(
+        *root.delegate(1000, 1000, &[]).unwrap_err().kind(),
+        ErrorKind::InvalidOperation
+    );
+}
+
+#[test]
+fn test_hybrid_unified_mirror() {
+    if !cgroups_rs::fs::hierarchies::is_cgroup2_hybrid_mode() {
+        return;
+    }
+    let h = cgroups_rs::fs::hierarchies::auto();
+    assert!(!h.v2());
+    let unified = h.unified_root().unwrap();
+    let cg = Cgroup::new(h, String::from("test_hybrid_unified_mirror")).unwrap();
+    let mirror = unified.join("test_hybrid_unified_mirror");
+    assert!(mirror.is_dir());
+    {
+        let mut sleeper = Command::new("sleep").arg("infinity").spawn().unwrap();
+        cg.add_task_by_tgid(CgroupPid::from(&sleeper)).unwrap();
+        let procs = std::fs::read_to_string(mirror.join("cgroup.procs")).unwrap();
+        assert_eq!(procs.trim(), sleeper.id().to_string());
+        assert!(cg.events().unwrap().populated);
+        assert_eq!(cg.id().unwrap(), std::fs::metadata(&mirror).unwrap().ino());
+
+        // A thread is left where it is in a domain control group of the unified hierarchy.
+        let tid = CgroupPid::from(&sleeper);
+        let dom = Cgroup::new(
+            cgroups_rs::fs::hierarchies::auto(),
+            String::from("test_hybrid_unified_mirror/dom"),
+        )
+        .unwrap();
+        dom.add_task(tid).unwrap();
+        assert_eq!(dom.tasks(), vec![tid]);
+        let procs = std::fs::read_to_string(mirror.join("cgroup.procs")).unwrap();
+        assert_eq!(procs.trim(), sleeper.id().to_string());
+        cg.add_task(tid).unwrap();
+        dom.delete().unwrap();
+
+        // And moved in a threaded one.
+        let threaded = Cgroup::new(
+            cgroups_rs::fs::hierarchies::auto(),
+            String::from("test_hybrid_unified_mirror/threaded"),
+        )
+        .unwrap();
+        let threaded_mirror = mirror.join("threaded");
+        std::fs::write(threaded_mirror.join("cgroup.type"), CGROUP_MODE_THREADED).unwrap();
+        threaded.add_task(tid).unwrap();
+        let threads = std::fs::read_to_string(threaded_mirror.join("cgroup.threads")).unwrap();
+        assert_eq!(threads.trim(), sleeper.id().to_string());
+        cg.add_task(tid).unwrap();
+        threaded.delete().unwrap();
+
+        // The unified hierarchy is skipped if the control group doesn't exist there.
+        let unmirrored = Cgroup::new(
+            cgroups_rs::fs::hierarchies::auto(),
+            String::from("test_hybrid_unified_mirror/unmirrored"),
+        )
+        .unwrap();
+        std::fs::remove_dir(mirror.join("unmirrored")).unwrap();
+        unmirrored.add_task_by_tgid(tid).unwrap();
+        assert_eq!(unmirrored.procs(), vec![tid]);
+        let procs = std::fs::read_to_string(mirror.join("cgroup.procs")).unwrap();
+        assert_eq!(procs.trim(), sleeper.id().to_string());
+        cg.add_task_by_tgid(tid).unwrap();
+        unmirrored.delete().unwrap();
+
+        // A spawned child joins the mirror as well.
+        let mut child = Command::new("sleep")
+            .arg("infinity")
+            .spawn_into_cgroup(&cg)
+            .unwrap();
+        let procs = std::fs::read_to_string(mirror.join("cgroup.procs")).unwrap();
+        assert!(procs.lines().any(|p| p == child.id().to_string()));
+
+        if cg.kill().is_err() {
+            // cgroup.kill requires kernel 5.14+.
+            sleeper.kill().unwrap();
+            child.kill().unwrap();
+        }
+        sleeper.wait().unwrap();
+        child.wait().unwrap();
+    }
+    cg.delete().unwrap();
+    assert!(!mirror.exists());
+}
+
+#[test]
+fn test_cgroup_namespace() {
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let cg = Cgroup::new(h, String::from("test_cgroup_namespace")).unwrap();
+    {
+        let output = Command::new("cat")
+            .arg("/proc/self/cgroup")
+            .cgroup_ns(&cg)
+            .unwrap()
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        // The control group is the root of the namespace of the child.
+        let own = cgroups_rs::fs::cgroup::get_cgroups_relative_paths().unwrap();
+        for line in String::from_utf8(output.stdout).unwrap().lines() {
+            let (key, path) = line.split_once(':').unwrap().1.split_once(':').unwrap();
+            let joined = cg.subsystems().iter().any(|s| {
+                key.split(',').any(|k| k == s.controller_name()) || (key.is_empty() && cg.v2())
+            });
+            if joined {
+                assert_eq!(path, "/", "{}", line);
+            } else if own.get(key).map(|p| p == "/").unwrap_or(false) {
+                // Not joined, the namespace root is where we are.
+                assert_eq!(path, "/", "{}", line);
+            }
+        }
+    }
+    cg.delete().unwrap();
+}
+
+#[test]
+fn test_cgroup_for_pid() {
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let cg = Cgroup::new(h, String::from("test_cgroup_for_pid")).unwrap();
+    {
+        let mut sleeper = Command::new("sleep")
+            .arg("infinity")
+            .cgroup(&cg)
+            .unwrap()
+            .spawn()
+            .unwrap();
+        let pid = CgroupPid::from(sleeper.id() as u64);
+
+        let found = Cgroup::for_pid(pid).unwrap();
+        assert_eq!(found.path(), "test_cgroup_for_pid");
+        assert_eq!(found.v2(), cg.v2());
+        for sub in found.subsystems() {
+            let dir = sub.to_controller().path().to_path_buf();
+            assert!(
+                cg.subsystems()
+                    .iter()
+                    .any(|s| s.to_controller().path() == dir),
+                "{}",
+                dir.display()
+            );
+        }
+        assert!(found.procs().contains(&pid));
+        if cgroups_rs::fs::hierarchies::is_cgroup2_hybrid_mode() {
+            // The path in the unified hierarchy, where the process was placed as well.
+            assert_eq!(found.id().unwrap(), cg.id().unwrap());
+        }
+
+        sleeper.kill().unwrap();
+        sleeper.wait().unwrap();
+    }
+    cg.delete().unwrap();
+}
+
+#[test]
+fn test_add_task_by_pidfd() {
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let cg = Cgroup::new(h, String::from("test_add_task_by_pidfd")).unwrap();
+    {
+        let mut sleeper = Command::new("sleep").arg("infinity").spawn().unwrap();
+        let pidfd = Pidfd::from_child(&sleeper).unwrap();
+        cg.add_task_by_pidfd(&pidfd).unwrap();
+        assert!(cg.procs().contains(&CgroupPid::from(&pidfd)));
+
+        // Once the process exited, its PID is never written.
+        sleeper.kill().unwrap();
+        sleeper.wait().unwrap();
+        let err = cg.add_task_by_pidfd(&pidfd).unwrap_err();
+        assert_eq!(
+            *err.kind(),
+            ErrorKind::ProcessExited(pidfd.pid().pid),
+            "{}",
+            err
+        );
+    }
+    cg.delete().unwrap();
+}
+
+#[test]
+fn test_cgroup_migrate() {
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let src = Cgroup::new(h, String::from("test_cgroup_migrate_src")).unwrap();
+    let h = cgroups_rs::fs::hierarchies::auto();
+    let dest = Cgroup::new(h, String::from("test_cgroup_migrate_dest")).unwrap();
+    {
+        // A process with children, which must all follow it.
+        let mut parent = Command::new("sh")
+            .arg("-c")
+            .arg("sleep infinity & sleep infinity & wait")
+            .cgroup(&src)
+            .unwrap()
+            .spawn()
+            .unwrap();
+        for _ in 0..50 {
+            if src.procs().len() >= 3 {
+                break;
+            }
+            sleep(Duration::from_millis(10));
+        }
+        let procs = src.procs();
+        assert!(procs.len() >= 3, "{:?}", procs);
+
+        // Charge moving was removed from recent kernels.
+        let move_charge = dest
+            .controller_of::<MemController>()
+            .map(|m| m.set_move_charge_at_immigrate(3).is_ok())
+            .unwrap_or(false);
+        let opts = MigrateOptions {
+            move_charge: if move_charge { Some(3) } else { None },
+            ..Default::default()
+        };
+        let report = src.migrate(&dest, &opts).unwrap();
+        assert!(report.is_complete(), "{:?}", report);
+        assert!(!report.threads);
+        assert_eq!(report.moved, procs);
+        assert!(src.procs().is_empty());
+        assert_eq!(dest.procs(), procs);
+        if move_charge {
+            let mem: &MemController = dest.controller_of().unwrap();
+            assert_eq!(mem.memory_stat().move_charge_at_immigrate, 3);
+        }
+
+        // Thawed afterwards.
+        let freezer: &FreezerController = dest.controller_of().unwrap();
+        assert!(matches!(freezer.state().unwrap(), FreezerState::Thawed));
+
+        // A source frozen beforehand is left frozen.
+        let src_freezer: &FreezerController = src.controller_of().unwrap();
+        src_freezer.freeze().unwrap();
+        let report = src.migrate(&dest, &opts).unwrap();
+        assert!(report.moved.is_empty());
+        assert!(matches!(src_freezer.state().unwrap(), FreezerState::Frozen));
+        src_freezer.thaw().unwrap();
+
+        dest.kill()
+            .or_else(|_| {
+                procs.iter().for_each(|p| unsafe {
+                    libc::kill(p.pid as libc::pid_t, libc::SIGKILL);
+                });
+                Ok::<_, cgroups_rs::fs::error::Error>(())
+            })
+            .unwrap();
+        parent.wait().unwrap();
+    }
+    for _ in 0..50 {
+        if dest.procs().is_empty() {
+            break;
+        }
+        sleep(Duration::from_millis(10));
+    }
+    src.delete().unwrap();
+    dest.delete().unwrap();
+}
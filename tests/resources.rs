@@ -29,3 +29,25 @@ fn pid_resources() {
     }
     cg.delete().unwrap();
 }
+
+#[cfg(feature = "serde")]
+#[test]
+fn deserialize_partial_resources() {
+    let res: Resources =
+        serde_json::from_str(r#"{"pid": {"maximum_number_of_processes": {"Value": 512}}}"#)
+            .unwrap();
+    assert_eq!(
+        res,
+        Resources {
+            pid: PidResources {
+                maximum_number_of_processes: Some(MaxValue::Value(512)),
+            },
+            ..Default::default()
+        }
+    );
+
+    // Entries have no defaults.
+    let err = serde_json::from_str::<Resources>(r#"{"devices": {"devices": [{"major": 1}]}}"#)
+        .unwrap_err();
+    assert!(err.to_string().contains("missing field"));
+}